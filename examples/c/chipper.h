@@ -0,0 +1,36 @@
+/* C API for the Chipper emulator core. Build the library with:
+ *
+ *   cargo build --release --no-default-features --features cdylib
+ *
+ * A machine is created with chipper_new() and must be freed with chipper_free().
+ * Passing NULL to any function is safe and does nothing.
+ */
+#ifndef CHIPPER_H
+#define CHIPPER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct Chip8 Chip8;
+
+/* Owned by the caller, free with chipper_free(). */
+Chip8 *chipper_new(uint64_t seed);
+void chipper_free(Chip8 *chip8);
+
+/* The ROM is copied. Returns 0 on success, -1 on failure. */
+int32_t chipper_load_rom(Chip8 *chip8, const uint8_t *rom, size_t len);
+
+void chipper_step(Chip8 *chip8);
+void chipper_run_frame(Chip8 *chip8);
+
+/* One byte per pixel in row order, width * height bytes. Borrowed from the
+ * machine and only valid until the next call that modifies it. */
+const uint8_t *chipper_display(const Chip8 *chip8);
+size_t chipper_display_width(const Chip8 *chip8);
+size_t chipper_display_height(const Chip8 *chip8);
+
+/* key is 0x0 - 0xF. A non-zero pressed presses it, zero releases it. */
+void chipper_set_key(Chip8 *chip8, uint8_t key, int32_t pressed);
+int32_t chipper_sound_active(const Chip8 *chip8);
+
+#endif
@@ -0,0 +1,49 @@
+/* Runs a ROM for a second of emulated time and prints the display as text.
+ *
+ *   cc examples/c/main.c -Iexamples/c -Ltarget/release -lchipper -o chipper-c
+ *   LD_LIBRARY_PATH=target/release ./chipper-c roms/BRIX.ch8
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chipper.h"
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <rom>\n", argv[0]);
+        return 1;
+    }
+
+    FILE *file = fopen(argv[1], "rb");
+    if (!file) {
+        perror(argv[1]);
+        return 1;
+    }
+    uint8_t rom[4096];
+    size_t len = fread(rom, 1, sizeof(rom), file);
+    fclose(file);
+
+    Chip8 *chip8 = chipper_new(0);
+    if (chipper_load_rom(chip8, rom, len) != 0) {
+        fprintf(stderr, "Failed to load %s\n", argv[1]);
+        chipper_free(chip8);
+        return 1;
+    }
+
+    for (int frame = 0; frame < 60; frame++) {
+        chipper_run_frame(chip8);
+    }
+
+    size_t width = chipper_display_width(chip8);
+    size_t height = chipper_display_height(chip8);
+    const uint8_t *display = chipper_display(chip8);
+    for (size_t y = 0; y < height; y++) {
+        for (size_t x = 0; x < width; x++) {
+            putchar(display[y * width + x] ? '#' : ' ');
+        }
+        putchar('\n');
+    }
+
+    chipper_free(chip8);
+    return 0;
+}
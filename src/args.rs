@@ -0,0 +1,422 @@
+use crate::config::{Config, MAX_DISPLAY_SCALE, MIN_DISPLAY_SCALE};
+use crate::trace::TraceFormat;
+use chipper::chip8::{self, Chip8, Divergence, QuirkProfile};
+use std::io::{self, Read};
+
+pub const USAGE: &str = "Usage: chipper [ROM] [options]
+       chipper compare ROM [--a PRESET] [--b PRESET] [--steps N]
+       chipper check MANIFEST [ROM]
+
+  ROM                 A ROM to run, instead of the boot ROM
+  -, --stdin          Read the ROM from standard input
+  --test-rom          Run the built-in diagnostic ROM
+  --scale N           Display scale, from 1 to 32
+  --ipf N             Instructions per frame, from 1 to 1000
+  --quirks PRESET     vip, schip or xochip
+  --paused            Start paused
+  --fullscreen        Start fullscreen
+  --mute              Start muted
+  --trace FORMAT      Trace every instruction to a file, as text or csv
+  --help              Show this message
+
+The options only last for the session. They aren't saved unless they're changed
+in the UI.
+
+compare runs the ROM with two quirk presets side by side, vip and schip unless
+they're given, and stops at the first instruction where they differ. It gives up
+after 100000 instructions unless --steps says otherwise. It exits with 1 if they
+differed.
+
+check runs each test ROM in the manifest until its display settles, or just the
+one named ROM, and checks what it drew against the manifest. It exits with 1 if
+any of them failed, printing what they drew.";
+
+// How long chipper compare runs for, unless it's told.
+const COMPARE_STEPS: u64 = 100_000;
+
+/// The command line arguments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+    pub rom: Option<String>,
+    /// The ROM is piped in, rather than being a file.
+    pub stdin: bool,
+    /// Run the built-in diagnostic ROM.
+    pub test_rom: bool,
+    pub scale: Option<u32>,
+    pub ipf: Option<usize>,
+    pub quirks: Option<QuirkProfile>,
+    pub paused: bool,
+    pub fullscreen: bool,
+    pub mute: bool,
+    /// Start tracing to a file from the first instruction.
+    pub trace: Option<TraceFormat>,
+    pub help: bool,
+}
+
+impl Args {
+    /// Applies the arguments to the settings and the machine, for this session only.
+    pub fn apply(self: &Self, config: &mut Config, chip8: &mut Chip8) {
+        if let Some(profile) = self.quirks {
+            chip8.set_variant(profile.variant());
+            profile.apply(chip8);
+        }
+        config.override_for_session(|config| {
+            if let Some(scale) = self.scale {
+                config.display_scale = scale;
+            }
+            if let Some(ipf) = self.ipf {
+                config.instructions_per_second = ipf as u32 * 60;
+            }
+            if self.quirks.is_some() {
+                config.shift_using_vy = chip8.shift_using_vy;
+                config.increment_i_on_ld = chip8.increment_i_on_ld;
+                config.wrap_sprites_x = chip8.wrap_sprites_x;
+                config.wrap_sprites_y = chip8.wrap_sprites_y;
+                config.vf_reset = chip8.vf_reset;
+                config.jump_using_vx = chip8.jump_using_vx;
+                config.wait_for_vblank = chip8.wait_for_vblank;
+            }
+            if self.mute {
+                config.muted = true;
+            }
+        });
+    }
+}
+
+/// Parses the arguments, not including the program name.
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                parsed.scale = Some(number(
+                    &arg,
+                    args.next(),
+                    MIN_DISPLAY_SCALE,
+                    MAX_DISPLAY_SCALE,
+                )?)
+            }
+            "--ipf" => parsed.ipf = Some(number(&arg, args.next(), 1, 1000)? as usize),
+            "--quirks" => parsed.quirks = Some(quirk_profile(&arg, args.next())?),
+            "--paused" => parsed.paused = true,
+            "--fullscreen" => parsed.fullscreen = true,
+            "--mute" => parsed.mute = true,
+            "--trace" => {
+                parsed.trace = Some(match args.next().as_deref() {
+                    Some("text") => TraceFormat::Text,
+                    Some("csv") => TraceFormat::Csv,
+                    _ => return Err("--trace needs text or csv".to_string()),
+                })
+            }
+            "--help" | "-h" => parsed.help = true,
+            "-" | "--stdin" => parsed.stdin = true,
+            "--test-rom" => parsed.test_rom = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if parsed.rom.is_some() => return Err("Only one ROM can be given".to_string()),
+            _ => parsed.rom = Some(arg),
+        }
+    }
+    let roms = parsed.rom.is_some() as u32 + parsed.stdin as u32 + parsed.test_rom as u32;
+    if roms > 1 {
+        return Err("Only one ROM can be given".to_string());
+    }
+    Ok(parsed)
+}
+
+/// The arguments to chipper compare.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompareArgs {
+    pub rom: String,
+    pub a: QuirkProfile,
+    pub b: QuirkProfile,
+    pub steps: u64,
+}
+
+impl CompareArgs {
+    /// Loads the ROM into a machine for each preset, with the same RND seed, and
+    /// runs them until they differ.
+    pub fn run(self: &Self) -> Result<Option<Divergence>, String> {
+        let load = |profile: QuirkProfile| {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_variant(profile.variant());
+            profile.apply(&mut chip8);
+            chip8
+                .boot_rom(&self.rom)
+                .map(|()| chip8)
+                .map_err(|e| format!("Failed to load {}: {}", self.rom, e))
+        };
+        let (mut a, mut b) = (load(self.a)?, load(self.b)?);
+        Ok(chip8::compare_run(&mut a, &mut b, self.steps))
+    }
+
+    /// Says what run() found.
+    pub fn describe(self: &Self, divergence: Option<Divergence>) -> String {
+        let (a, b) = (self.a.name(), self.b.name());
+        match divergence {
+            Some(d) => format!(
+                "{} and {} first differ in {} after {} instructions, at {:03X}: {:04X} ({})",
+                a,
+                b,
+                d.difference.description(),
+                d.step,
+                d.pc,
+                d.opcode,
+                chip8::disassemble(d.opcode)
+            ),
+            None => format!(
+                "{} and {} didn't differ in {} instructions",
+                a, b, self.steps
+            ),
+        }
+    }
+}
+
+/// Parses the arguments to chipper compare, not including compare.
+pub fn parse_compare<I: Iterator<Item = String>>(mut args: I) -> Result<CompareArgs, String> {
+    let mut rom = None;
+    let mut a = QuirkProfile::CosmacVip;
+    let mut b = QuirkProfile::SuperChip;
+    let mut steps = COMPARE_STEPS;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--a" => a = quirk_profile(&arg, args.next())?,
+            "--b" => b = quirk_profile(&arg, args.next())?,
+            "--steps" => {
+                steps = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&n| n > 0)
+                    .ok_or("--steps needs a number of instructions")?
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if rom.is_some() => return Err("Only one ROM can be given".to_string()),
+            _ => rom = Some(arg),
+        }
+    }
+    Ok(CompareArgs {
+        rom: rom.ok_or("compare needs a ROM")?,
+        a,
+        b,
+        steps,
+    })
+}
+
+/// The arguments to chipper check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckArgs {
+    pub manifest: String,
+    /// Only the ROM with this file name is run, rather than all of them.
+    pub rom: Option<String>,
+}
+
+/// Parses the arguments to chipper check, not including check.
+pub fn parse_check<I: Iterator<Item = String>>(args: I) -> Result<CheckArgs, String> {
+    let mut names = Vec::new();
+    for arg in args {
+        if arg.starts_with('-') {
+            return Err(format!("Unknown option {}", arg));
+        }
+        names.push(arg);
+    }
+    let mut names = names.into_iter();
+    let manifest = names.next().ok_or("check needs a manifest")?;
+    let rom = names.next();
+    if names.next().is_some() {
+        return Err("Only one ROM can be given".to_string());
+    }
+    Ok(CheckArgs { manifest, rom })
+}
+
+/// Reads a piped in ROM. It stops at the end of the input, or as soon as there's
+/// more than max_size, so that it doesn't wait on a pipe that's left open. Anything
+/// over is read, so that loading it fails as it would for a file that's too big.
+pub fn read_rom<R: Read>(reader: R, max_size: usize) -> io::Result<Vec<u8>> {
+    let mut rom = Vec::new();
+    reader.take(max_size as u64 + 1).read_to_end(&mut rom)?;
+    if rom.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "nothing was piped in",
+        ));
+    }
+    Ok(rom)
+}
+
+/// Parses the value of an option that takes a quirk preset.
+pub fn quirk_profile(option: &str, value: Option<String>) -> Result<QuirkProfile, String> {
+    match value.as_deref() {
+        Some("vip") => Ok(QuirkProfile::CosmacVip),
+        Some("schip") => Ok(QuirkProfile::SuperChip),
+        Some("xochip") => Ok(QuirkProfile::XoChip),
+        _ => Err(format!("{} needs vip, schip or xochip", option)),
+    }
+}
+
+// Parses the value of an option that takes a number.
+fn number(option: &str, value: Option<String>, min: u32, max: u32) -> Result<u32, String> {
+    value
+        .and_then(|v| v.parse().ok())
+        .filter(|n| (min..=max).contains(n))
+        .ok_or_else(|| format!("{} needs a number from {} to {}", option, min, max))
+}
+
+/// Shows a message from the command line handling. The program is built as a
+/// Windows GUI app, which has no console to print to, so there it's shown in a
+/// message box instead.
+pub fn show_message(message: &str, is_error: bool) {
+    if is_error {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+    #[cfg(windows)]
+    {
+        use sdl2::messagebox::{show_simple_message_box, MessageBoxFlag};
+        let flag = if is_error {
+            MessageBoxFlag::ERROR
+        } else {
+            MessageBoxFlag::INFORMATION
+        };
+        let _ = show_simple_message_box(flag, "Chipper", message, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Args, String> {
+        parse(args.split_whitespace().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_everything() {
+        let args = parse_str(
+            "game.ch8 --scale 12 --ipf 20 --quirks schip --paused --fullscreen --mute --trace csv",
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            Args {
+                rom: Some("game.ch8".to_string()),
+                stdin: false,
+                test_rom: false,
+                scale: Some(12),
+                ipf: Some(20),
+                quirks: Some(QuirkProfile::SuperChip),
+                paused: true,
+                fullscreen: true,
+                mute: true,
+                trace: Some(TraceFormat::Csv),
+                help: false,
+            }
+        );
+        assert_eq!(parse_str("").unwrap(), Args::default());
+    }
+
+    #[test]
+    fn refuses_bad_arguments() {
+        assert!(parse_str("--scale").is_err());
+        assert!(parse_str("--scale 0").is_err());
+        assert!(parse_str("--scale big").is_err());
+        assert!(parse_str("--ipf 1001").is_err());
+        assert!(parse_str("--quirks amiga").is_err());
+        assert!(parse_str("--turbo").is_err());
+        assert!(parse_str("--trace").is_err());
+        assert!(parse_str("--trace xml").is_err());
+        assert!(parse_str("a.ch8 b.ch8").is_err());
+        assert!(parse_str("a.ch8 -").is_err());
+        assert!(parse_str("--test-rom -").is_err());
+        assert!(parse_str("--stdin").unwrap().stdin);
+        assert!(parse_str("--test-rom").unwrap().test_rom);
+    }
+
+    #[test]
+    fn quirks_override_for_the_session() {
+        let mut config = Config::default();
+        let mut chip8 = Chip8::new();
+        let args = parse_str("--quirks vip --ipf 30").unwrap();
+        args.apply(&mut config, &mut chip8);
+        assert!(config.shift_using_vy && config.increment_i_on_ld);
+        assert!(!config.wrap_sprites_x && !config.wrap_sprites_y);
+        assert_eq!(config.instructions_per_second, 1800);
+    }
+
+    #[test]
+    fn parses_compare() {
+        let parse = |args: &str| parse_compare(args.split_whitespace().map(|a| a.to_string()));
+        assert_eq!(
+            parse("game.ch8 --b xochip --steps 50").unwrap(),
+            CompareArgs {
+                rom: "game.ch8".to_string(),
+                a: QuirkProfile::CosmacVip,
+                b: QuirkProfile::XoChip,
+                steps: 50,
+            }
+        );
+        assert!(parse("").is_err());
+        assert!(parse("game.ch8 --a").is_err());
+        assert!(parse("game.ch8 --steps 0").is_err());
+        assert!(parse("a.ch8 b.ch8").is_err());
+    }
+
+    #[test]
+    fn parses_check() {
+        let parse = |args: &str| parse_check(args.split_whitespace().map(|a| a.to_string()));
+        assert_eq!(
+            parse("tests/conformance.json BC_test.ch8").unwrap(),
+            CheckArgs {
+                manifest: "tests/conformance.json".to_string(),
+                rom: Some("BC_test.ch8".to_string()),
+            }
+        );
+        assert_eq!(parse("tests/conformance.json").unwrap().rom, None);
+        assert!(parse("").is_err());
+        assert!(parse("tests/conformance.json --all").is_err());
+        assert!(parse("manifest.json a.ch8 b.ch8").is_err());
+    }
+
+    #[test]
+    fn compare_finds_the_shift_quirk() {
+        let path = std::env::temp_dir().join(format!("chipper-compare-{}.ch8", std::process::id()));
+        std::fs::write(
+            &path,
+            [
+                0x60, 0x03, // 0x200: LD V0, 3
+                0x61, 0x08, // 0x202: LD V1, 8
+                0x80, 0x16, // 0x204: SHR V0, V1
+            ],
+        )
+        .unwrap();
+        let compare = CompareArgs {
+            rom: path.to_string_lossy().into_owned(),
+            a: QuirkProfile::CosmacVip,
+            b: QuirkProfile::SuperChip,
+            steps: 100,
+        };
+        let divergence = compare.run();
+        std::fs::remove_file(&path).unwrap();
+        let divergence = divergence.unwrap();
+        assert_eq!(divergence.map(|d| (d.step, d.pc)), Some((3, 0x204)));
+        assert!(compare
+            .describe(divergence)
+            .starts_with("COSMAC VIP and SUPER-CHIP first differ in V0 after 3 instructions"));
+
+        let compare = CompareArgs {
+            b: QuirkProfile::CosmacVip,
+            ..compare
+        };
+        assert!(compare.run().is_err());
+    }
+
+    #[test]
+    fn reads_piped_rom() {
+        assert_eq!(read_rom(&[0x12u8, 0x00][..], 3584).unwrap(), [0x12, 0x00]);
+        assert!(read_rom(&[][..], 3584).is_err());
+
+        // Too big, but only by as much as it takes to tell.
+        let rom = read_rom(&[0u8; 5000][..], 3584).unwrap();
+        assert_eq!(rom.len(), 3585);
+        assert!(Chip8::new().load_rom_bytes(&rom).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::mpsc::Receiver;
+
+const TONE_FREQUENCY: f32 = 440.0;
+// The amplitude of the tone at full volume.
+const MAX_VOLUME: f32 = 0.1;
+
+/// The shape of the tone. The original machines played a square wave, which can be
+/// harsh, so there's a choice of softer ones.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+pub const WAVEFORMS: [Waveform; 4] = [
+    Waveform::Square,
+    Waveform::Sine,
+    Waveform::Triangle,
+    Waveform::Sawtooth,
+];
+
+impl Waveform {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Waveform::Square => "Square",
+            Waveform::Sine => "Sine",
+            Waveform::Triangle => "Triangle",
+            Waveform::Sawtooth => "Sawtooth",
+        }
+    }
+
+    /// Returns the level of the wave, from -1 to 1, at a phase from 0 to 1.
+    pub fn sample(self: &Self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase <= 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+/// Generates the Chip8 tone while it's been told to.
+pub struct Tone {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    waveform: Waveform,
+    // Whether the tone should be playing, each time that changes.
+    sound: Receiver<bool>,
+    playing: bool,
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        if let Some(playing) = self.sound.try_iter().last() {
+            self.playing = playing;
+        }
+        if !self.playing {
+            for x in out.iter_mut() {
+                *x = 0.0;
+            }
+            return;
+        }
+        for x in out.iter_mut() {
+            *x = self.waveform.sample(self.phase) * self.volume;
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// The beeper plays the tone while the sound timer is running. The emulation thread
+/// tells the audio callback straight away when that changes, without going through
+/// the UI thread.
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+    // The settings last applied, so the callback is only locked when they change.
+    volume: u32,
+    muted: bool,
+    waveform: Waveform,
+}
+
+impl Beeper {
+    pub fn new(sdl_context: &sdl2::Sdl, sound: Receiver<bool>) -> Result<Beeper, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| Tone {
+            phase_inc: TONE_FREQUENCY / spec.freq as f32,
+            phase: 0.0,
+            volume: MAX_VOLUME,
+            waveform: Waveform::Square,
+            sound,
+            playing: false,
+        })?;
+        device.resume();
+
+        Ok(Beeper {
+            device,
+            volume: 100,
+            muted: false,
+            waveform: Waveform::Square,
+        })
+    }
+
+    /// Changes the shape of the tone, straight away like the volume.
+    pub fn set_waveform(self: &mut Self, waveform: Waveform) {
+        if waveform != self.waveform {
+            self.waveform = waveform;
+            self.device.lock().waveform = waveform;
+        }
+    }
+
+    /// Sets the volume, as a percentage. It takes effect straight away, even in the
+    /// middle of a tone. Muting stops the device altogether, which doesn't affect the
+    /// sound timer.
+    pub fn set_volume(self: &mut Self, volume: u32, muted: bool) {
+        if volume != self.volume {
+            self.volume = volume;
+            self.device.lock().volume = MAX_VOLUME * volume.min(100) as f32 / 100.0;
+        }
+        if muted != self.muted {
+            self.muted = muted;
+            if muted {
+                self.device.pause();
+            } else {
+                self.device.resume();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms_stay_in_range() {
+        for waveform in &WAVEFORMS {
+            for i in 0..100 {
+                let level = waveform.sample(i as f32 / 100.0);
+                assert!((-1.0..=1.0).contains(&level), "{}", waveform.name());
+            }
+        }
+        assert_eq!(Waveform::Square.sample(0.25), 1.0);
+        assert_eq!(Waveform::Square.sample(0.75), -1.0);
+        assert_eq!(Waveform::Triangle.sample(0.5), 1.0);
+        assert_eq!(Waveform::Triangle.sample(0.0), -1.0);
+        assert_eq!(Waveform::Sawtooth.sample(0.0), -1.0);
+        assert!((Waveform::Sine.sample(0.25) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,145 @@
+use chipper::chip8::Chip8;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Cheats are kept here, relative to the working directory, in a file per ROM named
+/// after its hash, like save states.
+pub const CHEAT_DIR: &str = "./cheats";
+
+/// Where programs start. Anything below is the interpreter's, such as the font.
+const PROGRAM_START: u16 = 0x200;
+
+/// A byte of memory to set. A frozen cheat is set again after every frame, so the
+/// program can't change it, which is how most trainers work.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub enabled: bool,
+    pub frozen: bool,
+}
+
+impl Cheat {
+    pub fn apply(self: &Self, chip8: &mut Chip8) {
+        chip8.poke(self.address, self.value);
+    }
+}
+
+/// Applies the cheats that are frozen and enabled. Called after each frame has run.
+pub fn apply_frozen(cheats: &[Cheat], chip8: &mut Chip8) {
+    for cheat in cheats.iter().filter(|c| c.enabled && c.frozen) {
+        cheat.apply(chip8);
+    }
+}
+
+pub fn cheats_path(rom_hash: u64) -> PathBuf {
+    PathBuf::from(CHEAT_DIR).join(format!("{:016x}.json", rom_hash))
+}
+
+/// Loads the cheats for a ROM. A ROM with no cheats file has no cheats, and a broken
+/// file is ignored.
+pub fn load(rom_hash: u64) -> Vec<Cheat> {
+    let path = cheats_path(rom_hash);
+    match fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+            println!("Ignoring invalid cheats file {}: {}", path.display(), e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn save(rom_hash: u64, cheats: &[Cheat]) -> io::Result<()> {
+    fs::create_dir_all(CHEAT_DIR)?;
+    let json = serde_json::to_string_pretty(cheats)?;
+    fs::write(cheats_path(rom_hash), json)
+}
+
+/// Parses cheats typed in as address=value, in hex, such as "3A2=FF". Several can be
+/// given at once, separated by spaces or commas. The cheats start off enabled but
+/// not frozen. Addresses below 0x200 are refused unless allow_interpreter_area is set,
+/// as writing there is more likely to be a typo than a cheat.
+pub fn parse(
+    text: &str,
+    memory_size: usize,
+    allow_interpreter_area: bool,
+) -> Result<Vec<Cheat>, String> {
+    let mut cheats = Vec::new();
+    for entry in text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|e| !e.is_empty())
+    {
+        let mut parts = entry.splitn(2, '=');
+        let (address, value) = match (parts.next(), parts.next()) {
+            (Some(address), Some(value)) => (address, value),
+            _ => return Err(format!("{} isn't address=value", entry)),
+        };
+        let address = parse_hex(address)
+            .filter(|&a| a < memory_size)
+            .ok_or_else(|| format!("{} isn't an address", address))?;
+        let value = parse_hex(value)
+            .filter(|&v| v <= 0xff)
+            .ok_or_else(|| format!("{} isn't a byte", value))?;
+        if address < PROGRAM_START as usize && !allow_interpreter_area {
+            return Err(format!("{:03X} is in the interpreter area", address));
+        }
+        cheats.push(Cheat {
+            address: address as u16,
+            value: value as u8,
+            enabled: true,
+            frozen: false,
+        });
+    }
+    if cheats.is_empty() {
+        return Err("Enter a cheat as address=value".to_string());
+    }
+    Ok(cheats)
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_several_cheats() {
+        let cheats = parse("3A2=FF, 0x3a3=1  400=0", 4096, false).unwrap();
+        let parsed: Vec<(u16, u8)> = cheats.iter().map(|c| (c.address, c.value)).collect();
+        assert_eq!(parsed, [(0x3A2, 0xFF), (0x3A3, 0x01), (0x400, 0x00)]);
+        assert!(cheats.iter().all(|c| c.enabled && !c.frozen));
+    }
+
+    #[test]
+    fn refuses_bad_cheats() {
+        assert!(parse("", 4096, false).is_err());
+        assert!(parse("3A2", 4096, false).is_err());
+        assert!(parse("3A2=100", 4096, false).is_err());
+        assert!(parse("1000=1", 4096, false).is_err());
+        assert!(parse("1000=1", 65536, false).is_ok());
+        assert!(parse("XYZ=1", 4096, false).is_err());
+    }
+
+    #[test]
+    fn interpreter_area_has_to_be_allowed() {
+        assert!(parse("50=1", 4096, false).is_err());
+        assert!(parse("50=1", 4096, true).is_ok());
+    }
+
+    #[test]
+    fn frozen_cheats_are_reapplied() {
+        let mut chip8 = Chip8::with_seed(1);
+        let mut cheats = parse("300=7 301=8", 4096, false).unwrap();
+        cheats[0].frozen = true;
+        cheats[1].frozen = true;
+        cheats[1].enabled = false;
+        apply_frozen(&cheats, &mut chip8);
+        assert_eq!(chip8.peek(0x300), 7);
+        assert_eq!(chip8.peek(0x301), 0);
+    }
+}
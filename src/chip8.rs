@@ -1,7 +1,23 @@
-use rand::{rngs::ThreadRng, Rng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use std::convert::TryInto;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::ops::Range;
+use std::time::Duration;
+
+mod breakpoints;
+mod compare;
+mod input;
+mod settle;
+mod state;
+mod watchdog;
+
+pub use breakpoints::{Breakpoint, Comparator, Comparison, Condition, Operand, StopReason};
+pub use compare::{compare_run, Difference, Divergence};
+pub use input::{InputEvent, InputLog, InputPlayer, InputRecorder, ReplayResult};
+pub use watchdog::Watchdog;
 
 const CHARSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -22,13 +38,623 @@ const CHARSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The SUPER-CHIP adds a larger 8x10 charset for the digits 0-9. XO-CHIP
+/// extends it with the hex digits A-F.
+const BIG_CHARSET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 ///Helper variables that aren't part of chip8 definition:
 const FLAG: usize = 15; // Index to the 16th V register.
 const ROMTOP: usize = 512;
+const BIG_CHARSET_ADDR: usize = 80; // The big charset sits right after the small one.
+const MEMORY_SIZE: usize = 0x1000;
+const XOCHIP_MEMORY_SIZE: usize = 0x10000;
+
+/// The display is allocated at the largest (hi-res) size. In lo-res mode only
+/// the first 64 * 32 pixels are used.
+const MAX_DISPLAY_WIDTH: usize = 128;
+const MAX_DISPLAY_HEIGHT: usize = 64;
+
+/// The number of instructions executed a second by default, which is 10 a frame.
+pub const INSTRUCTIONS_PER_SECOND: u32 = 600;
+
+/// The instructions owed are counted in 60 billionths of an instruction, so that a
+/// 60th of a second and a nanosecond are both a whole number of them.
+const CYCLE_UNIT: u128 = 60_000_000_000;
+
+/// The timers count down at 60Hz.
+const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// The Chipper boot ROM is built in, so there's always something to run even
+/// without a ROM directory.
+pub const BOOT_ROM: &[u8] = include_bytes!("../roms/ChipperBoot.ch8");
+
+/// A diagnostic ROM for checking a build without needing any other ROMs. It runs
+/// through twelve groups of instructions, drawing the number of each one that
+/// passes along the top, and then PASS underneath. If one fails it stops there and
+/// shows FAIL followed by its number instead.
+pub const TEST_ROM: &[u8] = include_bytes!("../roms/ChipperTest.ch8");
+
+/// Minimum number of extended opcodes a ROM has to contain before it's considered
+/// to be written for that variant. Even with only reachable code being scanned,
+/// data can end up being decoded as the odd stray opcode, so one isn't enough.
+const DETECT_THRESHOLD: usize = 2;
+
+/// The Chip8 variants supported by the emulator. Each one is a superset of the
+/// one before it.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+/// The quirks of the well known interpreters, for setting them all in one go. XO-CHIP
+/// has the same shift and load/store quirks as the COSMAC VIP, but wraps sprites
+/// where the others clip them, and doesn't have the VIP's other quirks. Only
+/// SUPER-CHIP jumps with Bxnn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuirkProfile {
+    CosmacVip,
+    SuperChip,
+    XoChip,
+}
+
+impl QuirkProfile {
+    pub const ALL: [QuirkProfile; 3] = [
+        QuirkProfile::CosmacVip,
+        QuirkProfile::SuperChip,
+        QuirkProfile::XoChip,
+    ];
+
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            QuirkProfile::CosmacVip => "COSMAC VIP",
+            QuirkProfile::SuperChip => "SUPER-CHIP",
+            QuirkProfile::XoChip => "XO-CHIP",
+        }
+    }
+
+    /// The variant the profile's machine ran.
+    pub fn variant(self: &Self) -> Variant {
+        match self {
+            QuirkProfile::CosmacVip => Variant::Chip8,
+            QuirkProfile::SuperChip => Variant::SuperChip,
+            QuirkProfile::XoChip => Variant::XoChip,
+        }
+    }
+
+    /// Sets the machine's quirks to the profile's.
+    pub fn apply(self: &Self, chip8: &mut Chip8) {
+        let original = *self != QuirkProfile::SuperChip;
+        chip8.shift_using_vy = original;
+        chip8.increment_i_on_ld = original;
+        let wrap = *self == QuirkProfile::XoChip;
+        chip8.wrap_sprites_x = wrap;
+        chip8.wrap_sprites_y = wrap;
+        let vip = *self == QuirkProfile::CosmacVip;
+        chip8.vf_reset = vip;
+        chip8.wait_for_vblank = vip;
+        chip8.jump_using_vx = *self == QuirkProfile::SuperChip;
+    }
+}
+
+/// The kinds of memory access that are counted when access tracking is enabled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Per-address memory access counts, for seeing which parts of memory a program
+/// uses, and how many times each kind of instruction in OPCODE_KINDS has been run.
+/// Counts saturate rather than wrap.
+#[derive(Clone, Debug)]
+pub struct AccessCounts {
+    pub reads: Vec<u32>,
+    pub writes: Vec<u32>,
+    pub executes: Vec<u32>,
+    pub opcodes: [u32; OPCODE_KINDS.len()],
+}
+
+impl AccessCounts {
+    fn new(size: usize) -> AccessCounts {
+        AccessCounts {
+            reads: vec![0; size],
+            writes: vec![0; size],
+            executes: vec![0; size],
+            opcodes: [0; OPCODE_KINDS.len()],
+        }
+    }
+
+    /// Returns the counts for the given kind of access.
+    pub fn get(self: &Self, access: Access) -> &[u32] {
+        match access {
+            Access::Read => &self.reads,
+            Access::Write => &self.writes,
+            Access::Execute => &self.executes,
+        }
+    }
+}
+
+/// An instruction that's been executed, and the V registers after it. Bit x of
+/// changed is set if it changed Vx. See Chip8::set_tracing().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub changed: u16,
+}
+
+/// The most code writes that are logged, and the most patches that are kept.
+/// They're still counted after that.
+pub const CODE_WRITE_LOG_LIMIT: usize = 1000;
+
+/// A write into the ROM that was loaded, which is usually self-modifying code. pc is
+/// the instruction that made it, or None if it was poked from outside.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub pc: Option<u16>,
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// How many writes there have been into the ROM, and the first of them if they're
+/// being logged. See Chip8::set_code_write_tracking().
+#[derive(Clone, Debug)]
+pub struct CodeWrites {
+    pub count: u64,
+    pub log: Option<Vec<CodeWrite>>,
+    /// How many writes have landed on instructions that had already been run, which
+    /// is self-modifying code for certain, and the first of them. These are looked
+    /// for anywhere in memory, as code can be copied out of the ROM and run.
+    pub patch_count: u64,
+    pub patches: Vec<CodeWrite>,
+    // Which addresses have been run as part of an instruction.
+    executed: Vec<bool>,
+}
+
+impl CodeWrites {
+    /// Whether the byte at addr has been written over after it was run.
+    pub fn is_patched(self: &Self, addr: u16) -> bool {
+        self.patches
+            .iter()
+            .any(|patch| addr >= patch.addr && ((addr - patch.addr) as usize) < patch.bytes.len())
+    }
+}
+
+/// Things that stop a program from carrying on normally.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// The opcode isn't an instruction of the current variant.
+    UnknownOpcode,
+    /// The opcode is an instruction chipper doesn't run, such as a machine code
+    /// call. Only raised with strict_opcodes set.
+    Unimplemented,
+    /// CALL with the stack already full.
+    StackOverflow,
+    /// RET with nothing on the stack.
+    StackUnderflow,
+    /// The PC has gone past the end of memory.
+    PcOutOfRange,
+    /// The PC is at an odd address, which real programs never run code from. Only
+    /// raised with fault_on_odd_pc set.
+    OddPc,
+    /// The program jumped to itself. Programs do this when they're finished, as
+    /// nothing can happen after it.
+    ProgramEnded,
+}
+
+impl FaultKind {
+    pub fn description(self: &Self) -> &'static str {
+        match self {
+            FaultKind::UnknownOpcode => "Unknown opcode",
+            FaultKind::Unimplemented => "Unimplemented instruction",
+            FaultKind::StackOverflow => "Stack overflow",
+            FaultKind::StackUnderflow => "Stack underflow",
+            FaultKind::PcOutOfRange => "PC out of range",
+            FaultKind::OddPc => "Code run from an odd address",
+            FaultKind::ProgramEnded => "Program ended",
+        }
+    }
+
+    /// Whether it makes sense to skip the instruction and carry on. A program that
+    /// has ended would just end again, and skipping from an odd PC lands on another.
+    pub fn can_continue(self: &Self) -> bool {
+        !matches!(
+            self,
+            FaultKind::PcOutOfRange | FaultKind::OddPc | FaultKind::ProgramEnded
+        )
+    }
+}
+
+/// A fault and the instruction that caused it. See Chip8::get_fault().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u16,
+    pub opcode: u16,
+}
+
+/// A subroutine that's been called and hasn't returned yet. See
+/// Chip8::get_call_stack().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CallFrame {
+    /// Where the CALL is.
+    pub call_site: u16,
+    /// The subroutine it called.
+    pub callee: u16,
+    /// Where RET will go back to, which is the instruction after the CALL.
+    pub return_address: u16,
+    /// Whether the CALL has been written over since it was made, such as by a
+    /// program storing registers over its own code with LD [I], Vx. The return
+    /// address is safe, as the stack isn't in memory, but the code it goes back to
+    /// may not be what called the subroutine any more.
+    pub overwritten: bool,
+}
+
+/// Returns the assembly for an opcode, for any variant. Addresses and bytes are
+/// in hex. Opcodes that aren't instructions are shown as data.
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let kk = opcode & 0x00FF;
+    let n = opcode & 0x000F;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            0x00C1..=0x00CF => format!("SCD {}", n),
+            0x00D1..=0x00DF => format!("SCU {}", n),
+            0x00FB => "SCR".to_string(),
+            0x00FC => "SCL".to_string(),
+            0x00FD => "EXIT".to_string(),
+            0x00FE => "LOW".to_string(),
+            0x00FF => "HIGH".to_string(),
+            _ => format!("SYS 0x{:03X}", nnn),
+        },
+        0x1000 => format!("JP 0x{:03X}", nnn),
+        0x2000 => format!("CALL 0x{:03X}", nnn),
+        0x3000 => format!("SE V{:X}, 0x{:02X}", x, kk),
+        0x4000 => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        0x5000 if n == 0x0 => format!("SE V{:X}, V{:X}", x, y),
+        0x5000 if n == 0x2 => format!("SAVE V{:X} - V{:X}", x, y),
+        0x5000 if n == 0x3 => format!("LOAD V{:X} - V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, 0x{:02X}", x, kk),
+        0x7000 => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        0x8000 => {
+            let mnemonic = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return format!("DW 0x{:04X}", opcode),
+            };
+            format!("{} V{:X}, V{:X}", mnemonic, x, y)
+        }
+        0x9000 if n == 0x0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, 0x{:03X}", nnn),
+        0xB000 => format!("JP V0, 0x{:03X}", nnn),
+        0xC000 => format!("RND V{:X}, 0x{:02X}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 if kk == 0x9E => format!("SKP V{:X}", x),
+        0xE000 if kk == 0xA1 => format!("SKNP V{:X}", x),
+        0xF000 => match kk {
+            0x00 if x == 0 => "LD I, long".to_string(),
+            0x01 => format!("PLANE {}", x),
+            0x02 if x == 0 => "AUDIO".to_string(),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => format!("DW 0x{:04X}", opcode),
+        },
+        _ => format!("DW 0x{:04X}", opcode),
+    }
+}
+
+/// The kinds of instruction there are, across all of the variants, as the opcode
+/// pattern and the mnemonic.
+pub const OPCODE_KINDS: [&str; 51] = [
+    "00E0 CLS",
+    "00EE RET",
+    "00Cn SCD",
+    "00Dn SCU",
+    "00FB SCR",
+    "00FC SCL",
+    "00FD EXIT",
+    "00FE LOW",
+    "00FF HIGH",
+    "0nnn SYS",
+    "1nnn JP",
+    "2nnn CALL",
+    "3xkk SE",
+    "4xkk SNE",
+    "5xy0 SE",
+    "5xy2 SAVE",
+    "5xy3 LOAD",
+    "6xkk LD",
+    "7xkk ADD",
+    "8xy0 LD",
+    "8xy1 OR",
+    "8xy2 AND",
+    "8xy3 XOR",
+    "8xy4 ADD",
+    "8xy5 SUB",
+    "8xy6 SHR",
+    "8xy7 SUBN",
+    "8xyE SHL",
+    "9xy0 SNE",
+    "Annn LD I",
+    "Bnnn JP V0",
+    "Cxkk RND",
+    "Dxyn DRW",
+    "Ex9E SKP",
+    "ExA1 SKNP",
+    "F000 LD I, long",
+    "Fx01 PLANE",
+    "F002 AUDIO",
+    "Fx07 LD Vx, DT",
+    "Fx0A LD Vx, K",
+    "Fx15 LD DT",
+    "Fx18 LD ST",
+    "Fx1E ADD I",
+    "Fx29 LD F",
+    "Fx30 LD HF",
+    "Fx33 LD B",
+    "Fx3A PITCH",
+    "Fx55 LD [I]",
+    "Fx65 LD Vx, [I]",
+    "Fx75 LD R",
+    "Fx85 LD Vx, R",
+];
+
+/// Returns the index in OPCODE_KINDS of the kind of instruction an opcode is, or None
+/// if it isn't one. Whether the variant has it isn't checked.
+pub fn opcode_kind(opcode: u16) -> Option<usize> {
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+    let pattern = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            0x00C1..=0x00CF => "00Cn",
+            0x00D1..=0x00DF => "00Dn",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            _ => "0nnn",
+        },
+        0x1000 => "1nnn",
+        0x2000 => "2nnn",
+        0x3000 => "3xkk",
+        0x4000 => "4xkk",
+        0x5000 => match n {
+            0x0 => "5xy0",
+            0x2 => "5xy2",
+            0x3 => "5xy3",
+            _ => return None,
+        },
+        0x6000 => "6xkk",
+        0x7000 => "7xkk",
+        0x8000 => match n {
+            0x0 => "8xy0",
+            0x1 => "8xy1",
+            0x2 => "8xy2",
+            0x3 => "8xy3",
+            0x4 => "8xy4",
+            0x5 => "8xy5",
+            0x6 => "8xy6",
+            0x7 => "8xy7",
+            0xE => "8xyE",
+            _ => return None,
+        },
+        0x9000 if n == 0x0 => "9xy0",
+        0xA000 => "Annn",
+        0xB000 => "Bnnn",
+        0xC000 => "Cxkk",
+        0xD000 => "Dxyn",
+        0xE000 if kk == 0x9E => "Ex9E",
+        0xE000 if kk == 0xA1 => "ExA1",
+        0xF000 => match kk {
+            0x00 if opcode == 0xF000 => "F000",
+            0x01 => "Fx01",
+            0x02 if opcode == 0xF002 => "F002",
+            0x07 => "Fx07",
+            0x0A => "Fx0A",
+            0x15 => "Fx15",
+            0x18 => "Fx18",
+            0x1E => "Fx1E",
+            0x29 => "Fx29",
+            0x30 => "Fx30",
+            0x33 => "Fx33",
+            0x3A => "Fx3A",
+            0x55 => "Fx55",
+            0x65 => "Fx65",
+            0x75 => "Fx75",
+            0x85 => "Fx85",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    OPCODE_KINDS
+        .iter()
+        .position(|kind| kind.starts_with(pattern))
+}
+
+/// Which kinds of instruction in OPCODE_KINDS have been run since the access counts
+/// were last reset, and which haven't. See Chip8::coverage_report().
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeCoverage {
+    pub executed: Vec<&'static str>,
+    pub not_executed: Vec<&'static str>,
+}
+
+// The size of the display in lo-res or hi-res mode.
+fn display_width(hires: bool) -> usize {
+    if hires {
+        MAX_DISPLAY_WIDTH
+    } else {
+        64
+    }
+}
+
+fn display_height(hires: bool) -> usize {
+    if hires {
+        MAX_DISPLAY_HEIGHT
+    } else {
+        32
+    }
+}
+
+/// 64-bit FNV-1a. It's simple and plenty good enough to tell ROMs and displays apart.
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Does a static scan of the opcodes in a ROM and returns the variant it looks
+/// like it was written for. The scan follows the control flow from the start of
+/// the ROM, so sprite data that happens to look like an opcode isn't counted.
+pub fn detect_variant(rom: &[u8]) -> Variant {
+    let mut schip_count = 0;
+    let mut xochip_count = 0;
+    let mut visited = vec![false; rom.len()];
+    let mut pending = vec![0usize];
+
+    while let Some(offset) = pending.pop() {
+        if offset + 1 >= rom.len() || visited[offset] {
+            continue;
+        }
+        visited[offset] = true;
+
+        let opcode = ((rom[offset] as u16) << 8) | (rom[offset + 1] as u16);
+        let target = ((opcode & 0x0fff) as usize).checked_sub(ROMTOP);
+
+        match opcode & 0xF000 {
+            // JP addr
+            0x1000 => pending.extend(target),
+            // CALL addr
+            0x2000 => {
+                pending.extend(target);
+                pending.push(offset + 2);
+            }
+            // SE, SNE, SKP and SKNP can continue at either of the next two instructions.
+            0x3000 | 0x4000 | 0x9000 | 0xE000 => {
+                pending.push(offset + 2);
+                pending.push(offset + 4);
+            }
+            0x5000 => {
+                match opcode & 0x000F {
+                    0x2 | 0x3 => xochip_count += 1, // SAVE Vx - Vy, LOAD Vx - Vy
+                    _ => pending.push(offset + 4),
+                }
+                pending.push(offset + 2);
+            }
+            // JP V0, addr. The target isn't known until runtime.
+            0xB000 => (),
+            0x0000 => match opcode {
+                // RET
+                0x00EE => (),
+                // EXIT
+                0x00FD => schip_count += 1,
+                // SCD n, SCR, SCL, LOW, HIGH
+                0x00C1..=0x00CF | 0x00FB..=0x00FF => {
+                    schip_count += 1;
+                    pending.push(offset + 2);
+                }
+                // SCU n
+                0x00D1..=0x00DF => {
+                    xochip_count += 1;
+                    pending.push(offset + 2);
+                }
+                _ => pending.push(offset + 2),
+            },
+            _ => {
+                match opcode & 0xF0FF {
+                    // LD HF, Vx, LD R, Vx, LD Vx, R
+                    0xF030 | 0xF075 | 0xF085 => schip_count += 1,
+                    // PLANE n, AUDIO, PITCH Vx
+                    0xF001 | 0xF002 | 0xF03A => xochip_count += 1,
+                    // LD I, long addr
+                    0xF000 if opcode == 0xF000 => {
+                        xochip_count += 1;
+                        pending.push(offset + 4);
+                        continue;
+                    }
+                    // DRW Vx, Vy, 0
+                    _ if opcode & 0xF00F == 0xD000 => schip_count += 1,
+                    _ => (),
+                }
+                pending.push(offset + 2);
+            }
+        }
+    }
+
+    if xochip_count >= DETECT_THRESHOLD {
+        Variant::XoChip
+    } else if schip_count + xochip_count >= DETECT_THRESHOLD {
+        Variant::SuperChip
+    } else {
+        Variant::Chip8
+    }
+}
 
+#[derive(Clone)]
 pub struct Chip8 {
-    /// The Chip8 has 4k of memory
-    memory: [u8; 4096],
+    /// The Chip8 has 4k of memory. XO-CHIP extends this to 64k. All addresses wrap
+    /// around at the end of memory.
+    memory: Vec<u8>,
 
     /// The chip8 includes a hexadecimal charset in binary form where
     /// each character is of size 5x8 bits.
@@ -42,11 +668,15 @@ pub struct Chip8 {
     /// The chip8 has a stack space for 16 16-bit addresses.
     stack: [u16; 16],
 
+    /// The subroutine each return address on the stack was pushed by a call to,
+    /// for the call stack. It's kept alongside the stack by CALL.
+    callees: [u16; 16],
+
     ///The 16-bit stack pointer is used to point to the top of the Stack space.
     reg_sp: u16,
 
     /// A 16 bit general purpose register used to store memory addresses. Only 12
-    /// bits are actually used.
+    /// bits are actually used, except in XO-CHIP's 64k memory mode.
     reg_i: u16,
 
     /// The Program Counter is an internal register and can't be used by chip8 programs.
@@ -58,64 +688,642 @@ pub struct Chip8 {
     reg_dt: u8,
     reg_st: u8,
 
+    /// Whether the tone was playing during the last timer update, and whether it
+    /// started or stopped playing on that update. Front-ends use the edges to
+    /// start and stop their audio instead of polling ST.
+    sound_active: bool,
+    sound_started: bool,
+    sound_stopped: bool,
+
     /// Holds the value of the key currently being pressed.
     key_pressed: u8,
 
     // Undocumented behaviour that's required by certain programs to run correctly.
     pub shift_using_vy: bool,
     pub increment_i_on_ld: bool,
+    /// Whether sprites wrap around the left and right edges of the display, or are
+    /// clipped. Where a sprite starts always wraps.
+    pub wrap_sprites_x: bool,
+    /// The same for the top and bottom edges.
+    pub wrap_sprites_y: bool,
+    /// Whether OR, AND and XOR set VF to 0, as the COSMAC VIP's did.
+    pub vf_reset: bool,
+    /// Whether Bnnn jumps to nnn plus Vx, where x is the top digit of nnn, rather
+    /// than plus V0. SUPER-CHIP does this, by mistake.
+    pub jump_using_vx: bool,
+    /// Whether each DRW waits for the next frame, as the COSMAC VIP waited for the
+    /// display to be drawn. It ends the frame, so there's one sprite a frame at most.
+    pub wait_for_vblank: bool,
+    /// Whether instructions that aren't implemented, such as 0nnn machine code calls,
+    /// stop the program with a fault. Otherwise they're skipped and counted, which
+    /// is what programs written for the real hardware expect.
+    pub strict_opcodes: bool,
+    /// Experimental, for homebrew tools that want it: sprites are ORed onto the
+    /// display rather than XORed, so they only ever turn pixels on, and VF is left
+    /// alone. No real machine draws like this.
+    pub or_sprites: bool,
+    /// Whether running code from an odd address stops the program with a fault.
+    /// Otherwise each odd address is noted in get_odd_pcs() and it carries on, for
+    /// the rare program that does it on purpose.
+    pub fault_on_odd_pc: bool,
+
+    /// The Chip8 variant being emulated. Decides which of the extended
+    /// SUPER-CHIP and XO-CHIP instructions are available.
+    variant: Variant,
+
+    /// The display memory of chip8. Each pixel holds one bit per bit-plane, of which
+    /// only XO-CHIP uses more than one.
+    display: [u8; MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT],
+
+    /// Set whenever the display changes, so front-ends can skip redrawing it
+    /// when nothing has. See take_display_dirty().
+    display_dirty: bool,
+
+    /// SUPER-CHIP's 128x64 hi-res display mode.
+    hires: bool,
+
+    /// The display as it was when it was last published, which is what front-ends
+    /// get. The instructions work on display, and it's only copied here once they're
+    /// done running, so a sprite that's erased to be drawn again is never caught in
+    /// between. See publish_display().
+    published_display: [u8; MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT],
+    published_hires: bool,
+
+    /// The bit-planes that CLS, DRW and the scroll instructions act on (XO-CHIP).
+    plane_mask: u8,
+
+    /// The SUPER-CHIP 'RPL' user flags, which can be saved to and loaded from
+    /// the V registers. XO-CHIP allows all 16 to be used.
+    reg_flags: [u8; 16],
+
+    /// The XO-CHIP audio pattern buffer and playback pitch.
+    audio_pattern: [u8; 16],
+    pitch: u8,
+
+    /// Set by the SUPER-CHIP EXIT instruction. Nothing is executed after this.
+    halted: bool,
+
+    /// Set when the program faults. Nothing is executed until it's cleared.
+    fault: Option<Fault>,
+
+    /// How many unimplemented instructions have been skipped since the ROM was
+    /// loaded, when strict_opcodes isn't set.
+    skipped_opcodes: u64,
+
+    /// The odd addresses code has been run from since the ROM was loaded, in the
+    /// order they were first run, when fault_on_odd_pc isn't set.
+    odd_pcs: Vec<u16>,
+
+    /// Size of the currently loaded ROM.
+    rom_size: usize,
+
+    /// A hash of the currently loaded ROM, so save states can be matched to it.
+    rom_hash: u64,
+
+    /// Counts the changes made to the machine from outside the program, other than
+    /// through the keypad, such as loading a ROM or a state or poking memory.
+    outside_changes: u64,
+
+    /// Memory access counts, only kept while access tracking is enabled so that
+    /// normal emulation doesn't pay for it.
+    access_counts: Option<Box<AccessCounts>>,
 
-    /// The display memory of chip8.
-    display: [u8; 64 * 32],
+    /// Writes into the ROM, only looked for while code write tracking is on.
+    code_writes: Option<Box<CodeWrites>>,
 
-    // Used for the RND instruction.
-    rng: ThreadRng,
+    /// The instructions executed since the trace was last taken, only kept while
+    /// tracing is on.
+    trace: Option<Vec<TraceEntry>>,
+
+    /// The breakpoints, sorted by address, and the one that's been stopped at.
+    /// leaving_breakpoint is set for the instruction that's carried on from.
+    breakpoints: Vec<Breakpoint>,
+    breakpoint_hit: Option<u16>,
+    leaving_breakpoint: bool,
+
+    /// The clock speed. It's the same however often the machine is run, as
+    /// run_frame() and run_for() both run as many as the time they're given is worth.
+    pub instructions_per_second: u32,
+
+    /// The most DRW instructions run_cycles() executes in one go, if limited. Once
+    /// the limit is reached it stops at the next DRW, so games that draw a lot
+    /// each frame flicker less at high clock rates.
+    pub max_draws_per_frame: Option<usize>,
+
+    /// Time passed to tick_timers() that hasn't added up to a timer update yet.
+    timer_accum: Duration,
+
+    /// The part of an instruction that's been given time for but hasn't run yet, in
+    /// CYCLE_UNITs.
+    cycle_accum: u128,
+
+    // Used for the RND instruction. It's seedable so that runs can be reproduced.
+    // The seed and the number of numbers drawn are kept so that save states can
+    // put it back to where it was.
+    rng: ChaCha20Rng,
+    rng_seed: u64,
+    rng_draws: u64,
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_seed(rand::random())
+    }
+
+    /// Creates a Chip8 whose RND instruction produces the same sequence every run.
+    pub fn with_seed(seed: u64) -> Chip8 {
         let mut chip8 = Chip8 {
-            memory: [0; 4096],
+            memory: vec![0; MEMORY_SIZE],
             stack: [0; 16],
-            display: [0; 64 * 32],
+            callees: [0; 16],
+            display: [0; MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT],
             reg_v: [0; 16],
             reg_sp: 0,
             reg_i: 0,
             reg_pc: 0x200,
             reg_dt: 0,
             reg_st: 0,
+            sound_active: false,
+            sound_started: false,
+            sound_stopped: false,
             key_pressed: 0,
             shift_using_vy: false,
             increment_i_on_ld: false,
-            rng: rand::thread_rng(),
+            wrap_sprites_x: true,
+            wrap_sprites_y: true,
+            vf_reset: false,
+            jump_using_vx: false,
+            wait_for_vblank: false,
+            strict_opcodes: false,
+            or_sprites: false,
+            fault_on_odd_pc: false,
+            variant: Variant::Chip8,
+            display_dirty: true,
+            hires: false,
+            published_display: [0; MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT],
+            published_hires: false,
+            plane_mask: 1,
+            reg_flags: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: 64,
+            halted: false,
+            fault: None,
+            skipped_opcodes: 0,
+            odd_pcs: Vec::new(),
+            rom_size: 0,
+            rom_hash: hash_bytes(&[]),
+            outside_changes: 0,
+            access_counts: None,
+            code_writes: None,
+            trace: None,
+            breakpoints: Vec::new(),
+            breakpoint_hit: None,
+            leaving_breakpoint: false,
+            instructions_per_second: INSTRUCTIONS_PER_SECOND,
+            max_draws_per_frame: None,
+            timer_accum: Duration::from_secs(0),
+            cycle_accum: 0,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            rng_seed: seed,
+            rng_draws: 0,
         };
 
-        for i in 0..80 {
-            chip8.memory[i] = CHARSET[i];
+        chip8.memory[..CHARSET.len()].copy_from_slice(&CHARSET);
+        chip8.memory[BIG_CHARSET_ADDR..BIG_CHARSET_ADDR + BIG_CHARSET.len()]
+            .copy_from_slice(&BIG_CHARSET);
+
+        chip8
+    }
+
+    pub fn get_variant(self: &Self) -> Variant {
+        self.variant
+    }
+
+    /// Switches the emulated variant. The ROM should be rebooted afterwards as
+    /// programs generally can't survive a change of instruction set mid-flight.
+    pub fn set_variant(self: &mut Self, variant: Variant) {
+        self.variant = variant;
+        if variant == Variant::XoChip {
+            self.memory.resize(XOCHIP_MEMORY_SIZE, 0);
+        } else {
+            self.memory.truncate(MEMORY_SIZE);
+        }
+        if variant == Variant::Chip8 && self.hires {
+            self.set_hires(false);
         }
+        if variant != Variant::XoChip {
+            self.plane_mask = 1;
+        }
+        if self.access_counts.is_some() {
+            self.access_counts = Some(Box::new(AccessCounts::new(self.memory.len())));
+        }
+        self.reset_code_writes();
+        self.publish_display();
+    }
 
-        for i in 80..4096 {
-            chip8.memory[i] = 0;
+    /// Starts the RND sequence over from a new seed, so that what's run from here on
+    /// can be reproduced.
+    pub fn reseed(self: &mut Self, seed: u64) {
+        self.rng = ChaCha20Rng::seed_from_u64(seed);
+        self.rng_seed = seed;
+        self.rng_draws = 0;
+    }
+
+    /// Turns memory access counting on or off. Turning it on starts from zero.
+    pub fn set_access_tracking(self: &mut Self, enabled: bool) {
+        self.access_counts = if enabled {
+            Some(Box::new(AccessCounts::new(self.memory.len())))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the memory access counts, or None if tracking is off.
+    pub fn get_access_counts(self: &Self) -> Option<&AccessCounts> {
+        self.access_counts.as_deref()
+    }
+
+    /// Clears the memory access counts, if tracking is on.
+    pub fn reset_access_counts(self: &mut Self) {
+        if self.access_counts.is_some() {
+            self.set_access_tracking(true);
         }
+    }
 
-        chip8
+    /// Lists the kinds of instruction that have and haven't been run, or None if
+    /// access tracking is off, as that's when they're counted.
+    pub fn coverage_report(self: &Self) -> Option<OpcodeCoverage> {
+        let counts = self.access_counts.as_ref()?;
+        let (executed, not_executed) = OPCODE_KINDS
+            .iter()
+            .zip(counts.opcodes.iter())
+            .partition::<Vec<_>, _>(|(_, &count)| count > 0);
+        Some(OpcodeCoverage {
+            executed: executed.into_iter().map(|(kind, _)| *kind).collect(),
+            not_executed: not_executed.into_iter().map(|(kind, _)| *kind).collect(),
+        })
+    }
+
+    /// Turns looking for writes into the ROM and over code that's been run on or off,
+    /// and with logging, keeping where the writes into the ROM were and what was
+    /// written. Turning it on starts from zero.
+    pub fn set_code_write_tracking(self: &mut Self, enabled: bool, logging: bool) {
+        self.code_writes = if enabled {
+            Some(Box::new(CodeWrites {
+                count: 0,
+                log: if logging { Some(Vec::new()) } else { None },
+                patch_count: 0,
+                patches: Vec::new(),
+                executed: vec![false; self.memory.len()],
+            }))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the writes into the ROM, or None if tracking is off.
+    pub fn get_code_writes(self: &Self) -> Option<&CodeWrites> {
+        self.code_writes.as_deref()
+    }
+
+    /// Clears the writes into the ROM, if tracking is on.
+    pub fn reset_code_writes(self: &mut Self) {
+        if let Some(writes) = &self.code_writes {
+            let logging = writes.log.is_some();
+            self.set_code_write_tracking(true, logging);
+        }
+    }
+
+    // Notes a write of len bytes from addr if any of them are in the ROM, or have
+    // been run.
+    fn check_code_write(self: &mut Self, pc: Option<u16>, addr: usize, len: usize) {
+        let mask = self.addr_mask();
+        let rom = ROMTOP..ROMTOP + self.rom_size;
+        let memory = &self.memory;
+        let writes = match &mut self.code_writes {
+            Some(writes) => writes,
+            None => return,
+        };
+        let in_rom = (addr..addr + len).any(|a| rom.contains(&(a & mask)));
+        let patched = (addr..addr + len).any(|a| writes.executed[a & mask]);
+        if !in_rom && !patched {
+            return;
+        }
+        let write = CodeWrite {
+            pc,
+            addr: (addr & mask) as u16,
+            bytes: (addr..addr + len).map(|a| memory[a & mask]).collect(),
+        };
+        if patched {
+            writes.patch_count += 1;
+            if writes.patches.len() < CODE_WRITE_LOG_LIMIT {
+                writes.patches.push(write.clone());
+            }
+        }
+        if in_rom {
+            writes.count += 1;
+            if let Some(log) = &mut writes.log {
+                if log.len() < CODE_WRITE_LOG_LIMIT {
+                    log.push(write);
+                }
+            }
+        }
+    }
+
+    /// Turns the instruction trace on or off. While it's on every instruction that's
+    /// executed is kept until take_trace(), which should be called regularly.
+    pub fn set_tracing(self: &mut Self, enabled: bool) {
+        self.trace = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn is_tracing(self: &Self) -> bool {
+        self.trace.is_some()
+    }
+
+    /// Returns the instructions executed since the last call, oldest first.
+    pub fn take_trace(self: &mut Self) -> Vec<TraceEntry> {
+        self.trace.as_mut().map_or(Vec::new(), std::mem::take)
+    }
+
+    /// Counts an access to len bytes of memory from addr, if tracking is on.
+    /// Writes into the ROM are looked for here too, as every write goes through it,
+    /// and so is what's been run, for spotting writes over it.
+    fn count_access(self: &mut Self, access: Access, addr: usize, len: usize) {
+        if access == Access::Write && self.code_writes.is_some() {
+            // The PC has already moved on from the instruction.
+            let pc = self.reg_pc.wrapping_sub(2);
+            self.check_code_write(Some(pc), addr, len);
+        }
+        let mask = self.addr_mask();
+        if access == Access::Execute {
+            if let Some(writes) = &mut self.code_writes {
+                for a in addr..addr + len {
+                    writes.executed[a & mask] = true;
+                }
+            }
+        }
+        if let Some(counts) = &mut self.access_counts {
+            let counts = match access {
+                Access::Read => &mut counts.reads,
+                Access::Write => &mut counts.writes,
+                Access::Execute => &mut counts.executes,
+            };
+            for a in addr..addr + len {
+                let count = &mut counts[a & mask];
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+
+    /// Scans the loaded ROM for opcodes of the extended variants.
+    /// See detect_variant().
+    pub fn detect_variant(self: &Self) -> Variant {
+        detect_variant(&self.memory[ROMTOP..ROMTOP + self.rom_size])
+    }
+
+    /// Returns a hash of the loaded ROM that identifies it, for example to keep
+    /// save states for different ROMs apart.
+    pub fn get_rom_hash(self: &Self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Returns the addresses the ROM was loaded into.
+    pub fn get_rom_range(self: &Self) -> Range<usize> {
+        ROMTOP..ROMTOP + self.rom_size
+    }
+
+    pub fn is_halted(self: &Self) -> bool {
+        self.halted
+    }
+
+    /// Returns the fault that stopped the program, if it's stopped on one. The PC is
+    /// left pointing at the faulting instruction.
+    pub fn get_fault(self: &Self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Skips the faulting instruction and carries on from the one after it. Does
+    /// nothing for faults that can't be continued from.
+    pub fn skip_fault(self: &mut Self) {
+        if let Some(fault) = self.fault {
+            if fault.kind.can_continue() {
+                self.fault = None;
+                self.reg_pc = fault.pc.wrapping_add(2);
+            }
+        }
+    }
+
+    /// Stops the program on a fault at the instruction at pc.
+    fn raise_fault(self: &mut Self, kind: FaultKind, pc: u16, opcode: u16) {
+        self.fault = Some(Fault { kind, pc, opcode });
+        self.reg_pc = pc;
+    }
+
+    /// Returns how many unimplemented instructions have been skipped since the ROM
+    /// was loaded. Always 0 with strict_opcodes set, as they fault instead.
+    pub fn get_skipped_opcodes(self: &Self) -> u64 {
+        self.skipped_opcodes
+    }
+
+    /// Returns the odd addresses code has been run from since the ROM was loaded.
+    /// Always empty with fault_on_odd_pc set, as they fault instead.
+    pub fn get_odd_pcs(self: &Self) -> &[u16] {
+        &self.odd_pcs
+    }
+
+    /// Returns how many times the machine has been changed from outside the program,
+    /// other than through the keypad, such as by loading a ROM or a state or poking
+    /// memory. It only ever goes up, so a change shows up as a different count.
+    pub fn get_outside_changes(self: &Self) -> u64 {
+        self.outside_changes
+    }
+
+    // Clears VF after a logic instruction, if the VF reset quirk is on.
+    fn reset_vf(self: &mut Self) {
+        if self.vf_reset {
+            self.reg_v[FLAG] = 0;
+        }
+    }
+
+    // Handles an instruction that isn't implemented, by faulting in strict mode and
+    // skipping it otherwise.
+    fn unimplemented(self: &mut Self, pc: u16, opcode: u16) {
+        if self.strict_opcodes {
+            self.raise_fault(FaultKind::Unimplemented, pc, opcode);
+        } else {
+            self.skipped_opcodes += 1;
+        }
+    }
+
+    /// Returns the V register with the given index (0x0 - 0xF).
+    pub fn get_v(self: &Self, index: usize) -> u8 {
+        self.reg_v[index]
+    }
+
+    pub fn get_i(self: &Self) -> u16 {
+        self.reg_i
+    }
+
+    pub fn get_pc(self: &Self) -> u16 {
+        self.reg_pc
+    }
+
+    pub fn get_sp(self: &Self) -> u16 {
+        self.reg_sp
+    }
+
+    /// Returns the subroutines that have been called and not returned from, the
+    /// outermost first.
+    pub fn get_call_stack(self: &Self) -> Vec<CallFrame> {
+        (1..=self.reg_sp as usize)
+            .map(|i| {
+                let return_address = self.stack[i];
+                let call_site = (return_address.wrapping_sub(2) as usize & self.addr_mask()) as u16;
+                let callee = self.callees[i];
+                CallFrame {
+                    call_site,
+                    callee,
+                    return_address,
+                    overwritten: self.read_opcode(call_site) != 0x2000 | callee,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the flag registers, which SUPER-CHIP's LD R, Vx saves to. They're
+    /// kept when another ROM is loaded, as they were on the HP48.
+    pub fn get_flags(self: &Self) -> [u8; 16] {
+        self.reg_flags
+    }
+
+    pub fn set_flags(self: &mut Self, flags: [u8; 16]) {
+        self.reg_flags = flags;
+    }
+
+    pub fn get_dt(self: &Self) -> u8 {
+        self.reg_dt
+    }
+
+    pub fn get_st(self: &Self) -> u8 {
+        self.reg_st
     }
 
     pub fn set_key_pressed(&mut self, key: u8) {
         self.key_pressed = key;
     }
 
+    /// Releases the key if it's the one currently being pressed.
+    pub fn set_key_released(&mut self, key: u8) {
+        if self.key_pressed == key {
+            self.key_pressed = 0xff;
+        }
+    }
+
+    /// Returns the keypad key that's being pressed, if there is one. Only one is
+    /// held at a time, the last one pressed.
+    pub fn get_key_pressed(self: &Self) -> Option<u8> {
+        if self.key_pressed <= 0xF {
+            Some(self.key_pressed)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the display memory for the active resolution, one byte per pixel
+    /// in row order, as of the last time it was published. See get_display_width()
+    /// and get_display_height().
     pub fn get_display_data(self: &Self) -> &[u8] {
-        &self.display
+        &self.published_display[..self.get_display_width() * self.get_display_height()]
+    }
+
+    pub fn get_display_width(self: &Self) -> usize {
+        display_width(self.published_hires)
+    }
+
+    pub fn get_display_height(self: &Self) -> usize {
+        display_height(self.published_hires)
+    }
+
+    /// Makes what's been drawn so far what front-ends get. This is done whenever
+    /// instructions have finished running, which is the end of each frame.
+    fn publish_display(self: &mut Self) {
+        self.published_display = self.display;
+        self.published_hires = self.hires;
     }
 
+    /// Returns the display as text, with '#' for lit pixels and '.' for blank ones,
+    /// one line per row. Handy for bug reports and for checking the display in tests.
+    pub fn display_ascii(self: &Self) -> String {
+        let width = self.get_display_width();
+        let mut ascii = String::new();
+        for row in self.get_display_data().chunks(width) {
+            for &pixel in row {
+                ascii.push(if pixel > 0 { '#' } else { '.' });
+            }
+            ascii.push('\n');
+        }
+        ascii
+    }
+
+    /// Returns a hash of what's on the display, for checking that two runs drew the
+    /// same thing.
+    pub fn get_display_hash(self: &Self) -> u64 {
+        hash_bytes(self.get_display_data())
+    }
+
+    /// Returns the bit-planes that are lit at (x, y) in the active resolution, which
+    /// is 0 for anything off the display. This is what's being drawn, which may not
+    /// have been published yet.
+    pub fn get_pixel(self: &Self, x: usize, y: usize) -> u8 {
+        self.pixel_offset(x, y)
+            .map_or(0, |offset| self.display[offset])
+    }
+
+    /// Sets the bit-planes that are lit at (x, y) in the active resolution. Anything
+    /// off the display is ignored.
+    fn set_pixel(self: &mut Self, x: usize, y: usize, planes: u8) {
+        if let Some(offset) = self.pixel_offset(x, y) {
+            self.display[offset] = planes;
+        }
+    }
+
+    // Where (x, y) is in the display memory. The rows are as wide as the active
+    // resolution, so this is the one place that has to know about it.
+    fn pixel_offset(self: &Self, x: usize, y: usize) -> Option<usize> {
+        let width = display_width(self.hires);
+        if x < width && y < display_height(self.hires) {
+            Some(y * width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Clears the selected bit-planes. For anything other than XO-CHIP that's
+    /// the whole display.
     pub fn clear_display(self: &mut Self) {
-        for i in 0..64 * 32 {
-            self.display[i] = 0;
+        for y in 0..display_height(self.hires) {
+            for x in 0..display_width(self.hires) {
+                let kept = self.get_pixel(x, y) & !self.plane_mask;
+                self.set_pixel(x, y, kept);
+            }
         }
+        self.display_dirty = true;
+    }
+
+    /// Returns true if the display has changed since the last call.
+    pub fn take_display_dirty(self: &mut Self) -> bool {
+        let dirty = self.display_dirty;
+        self.display_dirty = false;
+        dirty
     }
 
     pub fn update_timers(self: &mut Self) {
+        // The tone plays for as long as ST is non-zero at the start of an update.
+        let active = self.reg_st > 0;
+        self.sound_started = active && !self.sound_active;
+        self.sound_stopped = !active && self.sound_active;
+        self.sound_active = active;
+
         if self.reg_dt > 0 {
             self.reg_dt -= 1;
         }
@@ -124,22 +1332,105 @@ impl Chip8 {
             self.reg_st -= 1;
         }
     }
+
+    /// Updates the timers for the given amount of real time, at a steady 60Hz no
+    /// matter how often it's called. Time left over is carried on to the next call,
+    /// so a 34ms frame gives two updates. The sound edges cover all of the updates.
+    pub fn tick_timers(self: &mut Self, elapsed: Duration) {
+        let mut started = false;
+        let mut stopped = false;
+        self.timer_accum += elapsed;
+        while self.timer_accum >= TIMER_PERIOD {
+            self.timer_accum -= TIMER_PERIOD;
+            self.update_timers();
+            started |= self.sound_started;
+            stopped |= self.sound_stopped;
+        }
+        self.sound_started = started;
+        self.sound_stopped = stopped;
+    }
+
+    /// Returns true if the tone is currently playing.
+    pub fn get_sound_active(self: &Self) -> bool {
+        self.sound_active
+    }
+
+    /// Returns true if the tone started playing on the last timer update.
+    pub fn sound_started(self: &Self) -> bool {
+        self.sound_started
+    }
+
+    /// Returns true if the tone stopped playing on the last timer update.
+    pub fn sound_stopped(self: &Self) -> bool {
+        self.sound_stopped
+    }
+
     /// Lots of Rust-y things going on here:
     /// The method needs to return a Result because both File::open and File::read do so,
     /// as signified by the ? operator at the end of the respective functions.
     /// See https://m4rw3r.github.io/rust-questionmark-operator for reference.
     pub fn boot_rom(self: &mut Self, file_name: &str) -> std::io::Result<()> {
         let mut f = File::open(file_name)?;
-        let file_len: usize = f.metadata().unwrap().len() as usize;
-        let n = f.read(&mut self.memory[ROMTOP..ROMTOP + file_len])?;
-        //println!("{:?}", &self.memory[ROMTOP .. ROMTOP + 10]);
-        if n != file_len {
-            println!(
-                "There was an error reading the ROM. Read {}. Expected {}.",
-                n, file_len
-            );
+        let mut rom = Vec::new();
+        f.read_to_end(&mut rom)?;
+        self.load_rom_bytes(&rom)?;
+        println!("Loaded Chip8 ROM: {}", file_name);
+
+        Ok(())
+    }
+
+    /// Writes the whole of memory to a file, as a raw image. Any code the program has
+    /// modified is saved as it is now.
+    pub fn dump_memory(self: &Self, path: &str) -> std::io::Result<()> {
+        let mut f = File::create(path)?;
+        f.write_all(&self.memory)
+    }
+
+    /// Replaces the whole of memory with a raw image from dump_memory(). The image
+    /// has to be the size of the memory of the current variant. Nothing else about
+    /// the machine is changed.
+    pub fn load_memory(self: &mut Self, path: &str) -> std::io::Result<()> {
+        let mut f = File::open(path)?;
+        let mut image = Vec::new();
+        f.read_to_end(&mut image)?;
+        if image.len() != self.memory.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Memory image is the wrong size. Size {}. Expected {}.",
+                    image.len(),
+                    self.memory.len()
+                ),
+            ));
+        }
+        self.memory.copy_from_slice(&image);
+        self.outside_changes += 1;
+
+        Ok(())
+    }
+
+    /// Loads a ROM image into memory at 0x200 and resets the machine to run it.
+    pub fn load_rom_bytes(self: &mut Self, rom: &[u8]) -> std::io::Result<()> {
+        let max_size = self.memory.len() - ROMTOP;
+        if rom.len() > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is too large. Size {}. Maximum {}.",
+                    rom.len(),
+                    max_size
+                ),
+            ));
         }
 
+        for i in ROMTOP..self.memory.len() {
+            self.memory[i] = 0;
+        }
+        self.memory[ROMTOP..ROMTOP + rom.len()].copy_from_slice(rom);
+        self.rom_size = rom.len();
+        self.rom_hash = hash_bytes(rom);
+        self.outside_changes += 1;
+
         self.key_pressed = 0xff;
         self.reg_sp = 0;
         self.reg_i = 0;
@@ -149,21 +1440,358 @@ impl Chip8 {
 
         for i in 0..16 {
             self.stack[i] = 0;
+            self.callees[i] = 0;
             self.reg_v[i] = 0;
         }
 
+        self.hires = false;
+        self.plane_mask = 1;
+        self.halted = false;
+        self.fault = None;
+        self.skipped_opcodes = 0;
+        self.odd_pcs.clear();
+        self.breakpoint_hit = None;
+        self.timer_accum = Duration::from_secs(0);
+        self.cycle_accum = 0;
         self.clear_display();
-        println!("Loaded Chip8 ROM: {}", file_name);
+        self.publish_display();
+        self.reset_access_counts();
+        self.reset_code_writes();
 
         Ok(())
     }
 
+    /// Returns the size of the active memory, which is 4k or 64k for XO-CHIP.
+    pub fn get_memory_size(self: &Self) -> usize {
+        self.memory.len()
+    }
+
+    /// Reads a byte of memory, for debuggers and the like. Unlike the program's own
+    /// reads it isn't counted as an access.
+    pub fn peek(self: &Self, addr: u16) -> u8 {
+        self.memory[addr as usize & self.addr_mask()]
+    }
+
+    /// Reads the opcode at an address, for disassembling. Isn't counted as an access
+    /// either.
+    pub fn peek_opcode(self: &Self, addr: u16) -> u16 {
+        self.read_opcode(addr)
+    }
+
+    /// Writes a byte of memory from outside the program, such as for a cheat. Isn't
+    /// counted as an access either.
+    pub fn poke(self: &mut Self, addr: u16, value: u8) {
+        let mask = self.addr_mask();
+        self.memory[addr as usize & mask] = value;
+        self.outside_changes += 1;
+        if self.code_writes.is_some() {
+            self.check_code_write(None, addr as usize, 1);
+        }
+    }
+
+    /// Returns every address in memory that the pattern starts at, such as to find a
+    /// sprite or some text. An empty pattern isn't found anywhere.
+    pub fn find_bytes(self: &Self, pattern: &[u8]) -> Vec<u16> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        self.memory
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, bytes)| *bytes == pattern)
+            .map(|(addr, _)| addr as u16)
+            .collect()
+    }
+
+    /// Addresses are masked with this so that they wrap around within the active memory.
+    fn addr_mask(self: &Self) -> usize {
+        self.memory.len() - 1
+    }
+
+    /// Reads the big-endian opcode at the given address.
+    fn read_opcode(self: &Self, addr: u16) -> u16 {
+        let addr = addr as usize;
+        let high_byte = self.memory[addr & self.addr_mask()];
+        let low_byte = self.memory[(addr + 1) & self.addr_mask()];
+        ((high_byte as u16) << 8) | (low_byte as u16)
+    }
+
+    /// Skips the next instruction. XO-CHIP's 'LD I, long addr' is 4 bytes long so
+    /// it has to be skipped over in one go.
+    fn skip_next(self: &mut Self) {
+        if self.variant == Variant::XoChip && self.read_opcode(self.reg_pc) == 0xF000 {
+            self.reg_pc = self.reg_pc.wrapping_add(4);
+        } else {
+            self.reg_pc = self.reg_pc.wrapping_add(2);
+        }
+    }
+
+    /// XORs a sprite at I onto the selected bit-planes, with the data for each
+    /// plane following on from the previous one. A height of 0 draws a 16x16
+    /// sprite on the extended variants. Sprites start on the display wherever they
+    /// are, and wrap around or are clipped at the edges depending on the quirks.
+    fn draw_sprite(self: &mut Self, vx: u8, vy: u8, n: u16) {
+        let width = display_width(self.hires);
+        let height = display_height(self.hires);
+        let (rows, cols) = if n == 0 && self.variant != Variant::Chip8 {
+            (16, 16)
+        } else {
+            (n as usize, 8)
+        };
+        let bytes_per_row = cols / 8;
+        let (start_col, start_row) = (vx as usize % width, vy as usize % height);
+        let mut addr = self.reg_i as usize;
+        self.display_dirty = true;
+
+        // Rows where any pixel was turned off, in any plane.
+        let mut collided_rows = [false; 16];
+
+        for plane in 0..2 {
+            let bit = 1 << plane;
+            if self.plane_mask & bit == 0 {
+                continue;
+            }
+
+            for (r, collided) in collided_rows.iter_mut().enumerate().take(rows) {
+                let row = start_row + r;
+                if row >= height && !self.wrap_sprites_y {
+                    break;
+                }
+                let row = row % height;
+
+                for c in 0..cols {
+                    let sprite = self.memory[(addr + r * bytes_per_row + c / 8) & self.addr_mask()];
+                    if (sprite << (c % 8)) & 0x80 == 0 {
+                        continue;
+                    }
+
+                    let col = start_col + c;
+                    if col >= width && !self.wrap_sprites_x {
+                        break;
+                    }
+                    let col = col % width;
+                    let pixel = self.get_pixel(col, row);
+                    if self.or_sprites {
+                        self.set_pixel(col, row, pixel | bit);
+                        continue;
+                    }
+                    if pixel & bit != 0 {
+                        *collided = true;
+                    }
+                    self.set_pixel(col, row, pixel ^ bit);
+                }
+            }
+
+            self.count_access(Access::Read, addr, rows * bytes_per_row);
+            addr += rows * bytes_per_row;
+        }
+
+        // Nothing can collide when ORing.
+        if self.or_sprites {
+            return;
+        }
+
+        // In hi-res mode the SUPER-CHIP sets VF to the number of rows that collided
+        // rather than just 1, plus the number of rows clipped off the bottom. Pixels
+        // that are clipped off the side are never drawn, so never collide. XO-CHIP
+        // keeps to 0 or 1, as Octo does.
+        let collisions = collided_rows.iter().filter(|&&collided| collided).count();
+        self.reg_v[FLAG] = if self.hires && self.variant == Variant::SuperChip {
+            let clipped = if self.wrap_sprites_y {
+                0
+            } else {
+                (start_row + rows).saturating_sub(height)
+            };
+            (collisions + clipped) as u8
+        } else {
+            (collisions > 0) as u8
+        };
+    }
+
+    /// Scrolls the selected bit-planes by the given number of pixels. Positive dx
+    /// scrolls right and positive dy scrolls down. Pixels scrolled in are blank.
+    fn scroll(self: &mut Self, dx: i32, dy: i32) {
+        let width = display_width(self.hires);
+        let height = display_height(self.hires);
+        let mut scrolled = [0u8; MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT];
+        self.display_dirty = true;
+
+        for row in 0..height {
+            for col in 0..width {
+                let (src_col, src_row) = (col as i32 - dx, row as i32 - dy);
+                if src_col >= 0 && src_row >= 0 {
+                    scrolled[row * width + col] =
+                        self.get_pixel(src_col as usize, src_row as usize);
+                }
+            }
+        }
+
+        for row in 0..height {
+            for col in 0..width {
+                let kept = self.get_pixel(col, row) & !self.plane_mask;
+                let moved = scrolled[row * width + col] & self.plane_mask;
+                self.set_pixel(col, row, kept | moved);
+            }
+        }
+    }
+
+    /// Switches between lo-res and hi-res, which also clears the display.
+    fn set_hires(self: &mut Self, hires: bool) {
+        self.hires = hires;
+        for i in 0..MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT {
+            self.display[i] = 0;
+        }
+        self.display_dirty = true;
+    }
+
+    /// Runs one 60Hz frame: a 60th of a second's instructions followed by a timer
+    /// update. It's for running in step with the frames, such as when recording.
+    pub fn run_frame(self: &mut Self) {
+        self.cycle_accum += self.instructions_per_second as u128 * (CYCLE_UNIT / 60);
+        self.run_owed_cycles();
+        self.update_timers();
+    }
+
+    /// Runs for the given amount of real time, at instructions_per_second, and
+    /// updates the timers to match. What isn't enough for a whole instruction is
+    /// carried on to the next call, so the speed comes out the same however often
+    /// it's called.
+    pub fn run_for(self: &mut Self, elapsed: Duration) {
+        self.cycle_accum += elapsed.as_nanos()
+            * self.instructions_per_second as u128
+            * (CYCLE_UNIT / 1_000_000_000);
+        self.run_owed_cycles();
+        self.tick_timers(elapsed);
+    }
+
+    // Runs the whole instructions that have been given time for.
+    fn run_owed_cycles(self: &mut Self) {
+        let cycles = self.cycle_accum / CYCLE_UNIT;
+        self.cycle_accum %= CYCLE_UNIT;
+        self.run_cycles(cycles as usize);
+    }
+
+    /// The number of instructions run in a 60th of a second, rounded down, for
+    /// showing the clock speed the way other emulators do.
+    pub fn cycles_per_frame(self: &Self) -> usize {
+        self.instructions_per_second as usize / 60
+    }
+
+    /// Executes the given number of instructions without touching the timers, for
+    /// when they're driven by tick_timers() instead. Stops early if it gets to a DRW
+    /// after max_draws_per_frame of them, or after a DRW with wait_for_vblank.
+    pub fn run_cycles(self: &mut Self, cycles: usize) {
+        let mut draws = 0;
+        for _ in 0..cycles {
+            if self.out_of_draws(&mut draws) {
+                break;
+            }
+            let drawing = self.read_opcode(self.reg_pc) & 0xF000 == 0xD000;
+            self.execute();
+            if drawing && self.wait_for_vblank {
+                break;
+            }
+        }
+        self.publish_display();
+    }
+
+    // Whether the next instruction is a DRW after max_draws_per_frame of them. If it's
+    // one that's allowed it's counted in draws.
+    fn out_of_draws(self: &Self, draws: &mut usize) -> bool {
+        if let Some(max_draws) = self.max_draws_per_frame {
+            if self.read_opcode(self.reg_pc) & 0xF000 == 0xD000 {
+                if *draws == max_draws {
+                    return true;
+                }
+                *draws += 1;
+            }
+        }
+        false
+    }
+
+    /// Steps until pred holds or max_cycles instructions have been executed, and
+    /// returns whether pred held. It's checked before every step, so nothing is run
+    /// if it already holds. Timers aren't updated.
+    pub fn run_until<F: Fn(&Chip8) -> bool>(self: &mut Self, max_cycles: usize, pred: F) -> bool {
+        for _ in 0..max_cycles {
+            if pred(self) {
+                self.publish_display();
+                return true;
+            }
+            self.execute();
+        }
+        self.publish_display();
+        pred(self)
+    }
+
+    /// Executes a single instruction, and publishes the display after it so that
+    /// single stepping shows every change.
     pub fn step(self: &mut Self) {
+        self.execute();
+        self.publish_display();
+    }
+
+    // Runs the next instruction, and counts and traces it if that's on.
+    fn execute(self: &mut Self) {
+        if self.trace.is_none() && self.access_counts.is_none() {
+            self.execute_instruction();
+            return;
+        }
+        let pc = self.reg_pc;
+        let opcode = self.read_opcode(pc);
+        let stopped = self.halted || self.fault.is_some() || self.breakpoint_hit.is_some();
+        let before = self.reg_v;
+        self.execute_instruction();
+        // An instruction that faults, or is stopped at, doesn't run.
+        if stopped || self.fault.is_some() || self.breakpoint_hit.is_some() {
+            return;
+        }
+        if let (Some(counts), Some(kind)) = (&mut self.access_counts, opcode_kind(opcode)) {
+            counts.opcodes[kind] = counts.opcodes[kind].saturating_add(1);
+        }
+        if let Some(trace) = &mut self.trace {
+            let v = self.reg_v;
+            let changed = (0..16)
+                .filter(|&x| v[x] != before[x])
+                .fold(0, |changed, x| changed | 1 << x);
+            trace.push(TraceEntry {
+                pc,
+                opcode,
+                v,
+                changed,
+            });
+        }
+    }
+
+    fn execute_instruction(self: &mut Self) {
+        if self.halted || self.fault.is_some() || self.breakpoint_hit.is_some() {
+            return;
+        }
+
         // Big-endian order
-        let high_byte = self.memory[self.reg_pc as usize];
-        let low_byte = self.memory[(self.reg_pc + 1) as usize];
-        let opcode: u16 = ((high_byte as u16) << 8) | (low_byte as u16);
-        self.reg_pc += 2;
+        let pc = self.reg_pc;
+        if self.stop_at_breakpoint(pc) {
+            return;
+        }
+        let opcode: u16 = self.read_opcode(pc);
+        if pc as usize + 2 > self.memory.len() {
+            self.raise_fault(FaultKind::PcOutOfRange, pc, opcode);
+            return;
+        }
+        // Opcodes are always at even addresses, so this is nearly always a bad jump,
+        // and what's run from here is the halves of two instructions.
+        if pc & 1 != 0 {
+            if self.fault_on_odd_pc {
+                self.raise_fault(FaultKind::OddPc, pc, opcode);
+                return;
+            }
+            if !self.odd_pcs.contains(&pc) {
+                self.odd_pcs.push(pc);
+            }
+        }
+        self.count_access(Access::Execute, pc as usize, 2);
+        // Like memory, the PC wraps around at the top of XO-CHIP's 64k.
+        self.reg_pc = self.reg_pc.wrapping_add(2);
         // display[rand() % 200] = rand() % 16384;
         // cache common operations
         let nnn: u16 = opcode & 0x0fff;
@@ -182,40 +1810,120 @@ impl Chip8 {
                     }
                     // RET
                     0x00EE => {
+                        if self.reg_sp == 0 {
+                            self.raise_fault(FaultKind::StackUnderflow, pc, opcode);
+                            return;
+                        }
                         self.reg_pc = self.stack[self.reg_sp as usize];
                         self.reg_sp -= 1;
                     }
+                    // SCD nibble
+                    0x00C1..=0x00CF if self.variant >= Variant::SuperChip => {
+                        self.scroll(0, n as i32);
+                    }
+                    // SCU nibble
+                    0x00D1..=0x00DF if self.variant == Variant::XoChip => {
+                        self.scroll(0, -(n as i32));
+                    }
+                    // SCR
+                    0x00FB if self.variant >= Variant::SuperChip => {
+                        self.scroll(4, 0);
+                    }
+                    // SCL
+                    0x00FC if self.variant >= Variant::SuperChip => {
+                        self.scroll(-4, 0);
+                    }
+                    // EXIT
+                    0x00FD if self.variant >= Variant::SuperChip => {
+                        self.halted = true;
+                    }
+                    // LOW
+                    0x00FE if self.variant >= Variant::SuperChip => {
+                        self.set_hires(false);
+                    }
+                    // HIGH
+                    0x00FF if self.variant >= Variant::SuperChip => {
+                        self.set_hires(true);
+                    }
+                    // The ones above, in a variant that doesn't have them. They'd be
+                    // machine code calls on the original interpreter, but a program
+                    // using them was written for another variant.
+                    0x00C1..=0x00CF | 0x00D1..=0x00DF | 0x00FB..=0x00FF => {
+                        self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
+                    }
+                    // SYS addr
+                    // Runs a machine code routine on the COSMAC VIP. Interpreters are
+                    // expected to ignore it, and it turns up in some older ROMs.
                     _ => {
-                        println!("Unsupported instruction: {} ", opcode);
+                        self.unimplemented(pc, opcode);
                     }
                 }
             }
             // JP addr
             0x1 => {
+                if nnn == pc {
+                    self.raise_fault(FaultKind::ProgramEnded, pc, opcode);
+                    return;
+                }
                 self.reg_pc = nnn;
             }
             // CALL addr
             0x2 => {
+                if self.reg_sp as usize == self.stack.len() - 1 {
+                    self.raise_fault(FaultKind::StackOverflow, pc, opcode);
+                    return;
+                }
                 self.reg_sp += 1;
                 self.stack[self.reg_sp as usize] = self.reg_pc;
+                self.callees[self.reg_sp as usize] = nnn;
                 self.reg_pc = nnn;
             }
             // SE Vx, byte
             0x3 => {
                 if self.reg_v[x] == kk {
-                    self.reg_pc += 2;
+                    self.skip_next();
                 }
             }
             // SNE Vx, byte
             0x4 => {
                 if self.reg_v[x] != kk {
-                    self.reg_pc += 2;
+                    self.skip_next();
                 }
             }
-            // SE Vx, Vy
             0x5 => {
-                if (n == 0) && (self.reg_v[x] == self.reg_v[y]) {
-                    self.reg_pc += 2;
+                match n {
+                    // SE Vx, Vy
+                    0x0 => {
+                        if self.reg_v[x] == self.reg_v[y] {
+                            self.skip_next();
+                        }
+                    }
+                    // SAVE Vx - Vy
+                    0x2 if self.variant == Variant::XoChip => {
+                        // Registers are handled in reverse order if x > y.
+                        let i = self.reg_i as usize;
+                        let count = x.max(y) - x.min(y);
+                        for a in 0..count + 1 {
+                            let r = if x <= y { x + a } else { x - a };
+                            let addr = (i + a) & self.addr_mask();
+                            self.memory[addr] = self.reg_v[r];
+                        }
+                        self.count_access(Access::Write, i, count + 1);
+                    }
+                    // LOAD Vx - Vy
+                    0x3 if self.variant == Variant::XoChip => {
+                        // Registers are handled in reverse order if x > y.
+                        let i = self.reg_i as usize;
+                        let count = x.max(y) - x.min(y);
+                        for a in 0..count + 1 {
+                            let r = if x <= y { x + a } else { x - a };
+                            self.reg_v[r] = self.memory[(i + a) & self.addr_mask()];
+                        }
+                        self.count_access(Access::Read, i, count + 1);
+                    }
+                    _ => {
+                        self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
+                    }
                 }
             }
             // LD Vx, byte
@@ -236,14 +1944,17 @@ impl Chip8 {
                     // OR Vx, Vy
                     0x1 => {
                         self.reg_v[x] |= self.reg_v[y];
+                        self.reset_vf();
                     }
                     // AND Vx, Vy
                     0x2 => {
                         self.reg_v[x] &= self.reg_v[y];
+                        self.reset_vf();
                     }
                     // XOR Vx, Vy
                     0x3 => {
                         self.reg_v[x] ^= self.reg_v[y];
+                        self.reset_vf();
                     }
                     // ADD Vx, Vy
                     0x4 => {
@@ -282,76 +1993,78 @@ impl Chip8 {
                         }
                     }
                     _ => {
-                        println!("Uknown instruction: {}", opcode);
+                        self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
                     }
                 }
             }
             // SNE Vx, Vy
             0x9 => {
                 if (n == 0) && (self.reg_v[x] != self.reg_v[y]) {
-                    self.reg_pc += 2;
+                    self.skip_next();
                 }
             }
             // LD I, addr
             0xa => {
                 self.reg_i = nnn;
             }
-            // JP V0 + addr
+            // JP V0 + addr, or Vx + addr
             0xb => {
-                self.reg_pc = nnn.wrapping_add(self.reg_v[0] as u16);
+                let offset = if self.jump_using_vx {
+                    self.reg_v[x]
+                } else {
+                    self.reg_v[0]
+                };
+                self.reg_pc = nnn.wrapping_add(offset as u16);
             }
             // RND Vx, byte
             0xc => {
                 let r: u8 = self.rng.gen();
+                self.rng_draws = self.rng_draws.wrapping_add(1);
                 self.reg_v[x] = r & kk;
             }
             // DRW Vx, Vy, nibble
             0xd => {
-                self.reg_v[FLAG] = 0;
-
-                for c in 0..n {
-                    let mut sprite = self.memory[(self.reg_i + c) as usize];
-                    let row = ((self.reg_v[y] as u16) + c) % 32;
-
-                    for f in 0..8 {
-                        let b = (sprite & 0x80) >> 7;
-                        let col = (self.reg_v[x] + f) % 64;
-                        let offset = (row * 64 + (col as u16)) as usize;
-
-                        if b == 1 {
-                            if self.display[offset] != 0 {
-                                self.display[offset] = 0;
-                                self.reg_v[FLAG] = 1;
-                            } else {
-                                self.display[offset] = 1;
-                            }
-                        }
-
-                        sprite <<= 1;
-                    }
-                }
+                self.draw_sprite(self.reg_v[x], self.reg_v[y], n);
             }
             0xe => {
                 match kk {
                     // SKP Vx
                     0x9e => {
                         if self.key_pressed == self.reg_v[x] {
-                            self.reg_pc += 2;
+                            self.skip_next();
                         }
                     }
                     // SKNP Vx
                     0xA1 => {
                         if self.key_pressed != self.reg_v[x] {
-                            self.reg_pc += 2;
+                            self.skip_next();
                         }
                     }
                     _ => {
-                        println!("Uknown instruction: {}", opcode);
+                        self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
                     }
                 }
             }
             0xf => {
                 match kk {
+                    // LD I, long addr
+                    0x00 if self.variant == Variant::XoChip && x == 0 => {
+                        self.reg_i = self.read_opcode(self.reg_pc);
+                        self.count_access(Access::Read, self.reg_pc as usize, 2);
+                        self.reg_pc = self.reg_pc.wrapping_add(2);
+                    }
+                    // PLANE n
+                    0x01 if self.variant == Variant::XoChip => {
+                        self.plane_mask = (x & 0x3) as u8;
+                    }
+                    // AUDIO
+                    0x02 if self.variant == Variant::XoChip && x == 0 => {
+                        for a in 0..16 {
+                            self.audio_pattern[a] =
+                                self.memory[(self.reg_i as usize + a) & self.addr_mask()];
+                        }
+                        self.count_access(Access::Read, self.reg_i as usize, 16);
+                    }
                     // LD Vx, DT
                     0x07 => {
                         self.reg_v[x] = self.reg_dt;
@@ -361,7 +2074,7 @@ impl Chip8 {
                         if self.key_pressed != 0xff {
                             self.reg_v[x] = self.key_pressed;
                         } else {
-                            self.reg_pc -= 2;
+                            self.reg_pc = pc;
                         }
                     }
                     // LD DT, Vx
@@ -378,14 +2091,22 @@ impl Chip8 {
                         // VF is set to 1 when there is a range overflow (I+VX>0xFFF), and to
                         // 0 when there isn't. This is an undocumented feature of the CHIP - 8
                         // and used by the Spacefight 2091!game
-                        let add = self.reg_i + (self.reg_v[x] as u16);
-                        self.reg_v[FLAG] = if add > 0xfff { 1 } else { 0 };
-                        self.reg_i = add & 0xfff;
+                        let add = self.reg_i as usize + (self.reg_v[x] as usize);
+                        self.reg_v[FLAG] = if add > self.addr_mask() { 1 } else { 0 };
+                        self.reg_i = (add & self.addr_mask()) as u16;
                     }
                     // LD F, Vx
                     0x29 => {
-                        self.reg_i = (self.reg_v[x] * 5).into();
-                        self.reg_i &= 0xfff;
+                        self.reg_i = (self.reg_v[x] & 0xf) as u16 * 5;
+                    }
+                    // LD HF, Vx
+                    0x30 if self.variant >= Variant::SuperChip => {
+                        self.reg_i =
+                            (BIG_CHARSET_ADDR + (self.reg_v[x] & 0xf) as usize * 10) as u16;
+                    }
+                    // PITCH Vx
+                    0x3a if self.variant == Variant::XoChip => {
+                        self.pitch = self.reg_v[x];
                     }
                     // LD B, Vx
                     0x33 => {
@@ -396,20 +2117,24 @@ impl Chip8 {
                         bcd = bcd / 10;
                         let hundreds = bcd % 10;
                         let i = self.reg_i as usize;
-                        self.memory[i] = hundreds;
-                        self.memory[i + 1] = tens;
-                        self.memory[i + 2] = unit;
+                        let mask = self.addr_mask();
+                        self.memory[i & mask] = hundreds;
+                        self.memory[(i + 1) & mask] = tens;
+                        self.memory[(i + 2) & mask] = unit;
+                        self.count_access(Access::Write, i, 3);
                     }
                     // LD [I], Vx
                     0x55 => {
                         let i = self.reg_i as usize;
 
                         for a in 0..x + 1 {
-                            self.memory[i + a] = self.reg_v[a];
+                            let addr = (i + a) & self.addr_mask();
+                            self.memory[addr] = self.reg_v[a];
                         }
+                        self.count_access(Access::Write, i, x + 1);
 
                         if self.increment_i_on_ld {
-                            self.reg_i += (x + 1) as u16;
+                            self.reg_i = ((i + x + 1) & self.addr_mask()) as u16;
                         }
                     }
                     // LD Vx, [I]
@@ -417,21 +2142,1300 @@ impl Chip8 {
                         let i = self.reg_i as usize;
 
                         for a in 0..x + 1 {
-                            self.reg_v[a] = self.memory[i + a];
+                            self.reg_v[a] = self.memory[(i + a) & self.addr_mask()];
                         }
+                        self.count_access(Access::Read, i, x + 1);
 
                         if self.increment_i_on_ld {
-                            self.reg_i += (x + 1) as u16;
+                            self.reg_i = ((i + x + 1) & self.addr_mask()) as u16;
+                        }
+                    }
+                    // LD R, Vx
+                    0x75 if self.variant >= Variant::SuperChip => {
+                        let count = if self.variant == Variant::SuperChip {
+                            x.min(7)
+                        } else {
+                            x
+                        };
+                        for a in 0..count + 1 {
+                            self.reg_flags[a] = self.reg_v[a];
+                        }
+                    }
+                    // LD Vx, R
+                    0x85 if self.variant >= Variant::SuperChip => {
+                        let count = if self.variant == Variant::SuperChip {
+                            x.min(7)
+                        } else {
+                            x
+                        };
+                        for a in 0..count + 1 {
+                            self.reg_v[a] = self.reg_flags[a];
                         }
                     }
                     _ => {
-                        println!("Unknown instruction: {}", opcode);
+                        self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
                     }
                 }
             }
             _ => {
-                println!("Unknown instruction: {}", opcode);
+                self.raise_fault(FaultKind::UnknownOpcode, pc, opcode);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(program).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn detects_plain_roms_as_chip8() {
+        let roms_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+        for entry in fs::read_dir(roms_dir).unwrap() {
+            let path = entry.unwrap().path();
+            let rom = fs::read(&path).unwrap();
+            assert_eq!(detect_variant(&rom), Variant::Chip8, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn counts_memory_accesses_when_tracking() {
+        let program = [
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+            0xF0, 0x65, // LD V0, [I]
+        ];
+        let mut chip8 = load(&program);
+        for _ in 0..3 {
+            chip8.step();
+        }
+        assert!(chip8.get_access_counts().is_none());
+
+        let mut chip8 = load(&program);
+        chip8.set_access_tracking(true);
+        for _ in 0..3 {
+            chip8.step();
+        }
+        let counts = chip8.get_access_counts().unwrap();
+        assert_eq!(&counts.executes[0x200..0x207], &[1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(&counts.writes[0x300..0x303], &[1, 1, 0]);
+        assert_eq!(&counts.reads[0x300..0x302], &[1, 0]);
+
+        chip8.reset_access_counts();
+        let counts = chip8.get_access_counts().unwrap();
+        assert!(counts.executes.iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    fn notices_writes_into_the_rom() {
+        let program = [
+            0x60, 0x12, // 0x200: LD V0, 0x12
+            0xA3, 0x00, // 0x202: LD I, 0x300
+            0xF0, 0x55, // 0x204: LD [I], V0
+            0xA2, 0x0A, // 0x206: LD I, 0x20A
+            0xF0, 0x33, // 0x208: LD B, V0
+            0x12, 0x0A, // 0x20A: JP 0x20A
+        ];
+        let mut chip8 = load(&program);
+        chip8.run_cycles(5);
+        assert!(chip8.get_code_writes().is_none());
+
+        let mut chip8 = load(&program);
+        chip8.set_code_write_tracking(true, false);
+        chip8.run_cycles(5);
+        let writes = chip8.get_code_writes().unwrap();
+        assert_eq!(writes.count, 1);
+        assert!(writes.log.is_none());
+
+        let mut chip8 = load(&program);
+        chip8.set_code_write_tracking(true, true);
+        chip8.run_cycles(5);
+        chip8.poke(0x300, 0xff);
+        chip8.poke(0x200, 0xff);
+        let writes = chip8.get_code_writes().unwrap();
+        assert_eq!(writes.count, 2);
+        assert_eq!(
+            writes.log.as_deref().unwrap(),
+            &[
+                CodeWrite {
+                    pc: Some(0x208),
+                    addr: 0x20A,
+                    bytes: vec![0, 1, 8],
+                },
+                CodeWrite {
+                    pc: None,
+                    addr: 0x200,
+                    bytes: vec![0xff],
+                },
+            ]
+        );
+
+        // Loading a ROM starts over, without turning tracking off.
+        chip8.load_rom_bytes(&program).unwrap();
+        let writes = chip8.get_code_writes().unwrap();
+        assert_eq!(writes.count, 0);
+        assert_eq!(writes.log.as_deref(), Some(&[][..]));
+    }
+
+    #[test]
+    fn notices_a_rom_patching_code_it_has_run() {
+        let mut chip8 = load(&[
+            0x61, 0x01, // 0x200: LD V1, 1
+            0x60, 0x61, // 0x202: LD V0, 0x61
+            0x61, 0x05, // 0x204: LD V1, 5
+            0xA2, 0x00, // 0x206: LD I, 0x200
+            0xF1, 0x55, // 0x208: LD [I], V1, making 0x200 LD V1, 5
+            0xA2, 0x10, // 0x20A: LD I, 0x210
+            0xF1, 0x55, // 0x20C: LD [I], V1
+            0x12, 0x0E, // 0x20E: JP 0x20E
+            0x00, 0x00, // 0x210: data, never run
+        ]);
+        chip8.set_code_write_tracking(true, false);
+        chip8.run_cycles(7);
+
+        // Both writes are into the ROM, but only the first is over code that's run.
+        let writes = chip8.get_code_writes().unwrap();
+        assert_eq!(writes.count, 2);
+        assert_eq!(writes.patch_count, 1);
+        assert_eq!(
+            writes.patches,
+            vec![CodeWrite {
+                pc: Some(0x208),
+                addr: 0x200,
+                bytes: vec![0x61, 0x05],
+            }]
+        );
+        assert!(writes.is_patched(0x200) && writes.is_patched(0x201));
+        assert!(!writes.is_patched(0x202) && !writes.is_patched(0x210));
+        assert_eq!(disassemble(chip8.peek_opcode(0x200)), "LD V1, 0x05");
+
+        // Code run outside the ROM counts too.
+        chip8.poke(0x300, 0x00);
+        chip8.reg_pc = 0x300;
+        chip8.step();
+        chip8.poke(0x301, 0xE0);
+        let writes = chip8.get_code_writes().unwrap();
+        assert_eq!(writes.patch_count, 2);
+        assert_eq!(writes.patches[1].pc, None);
+        assert_eq!(writes.count, 2);
+
+        chip8.reset_code_writes();
+        assert!(chip8.get_code_writes().unwrap().patches.is_empty());
+        chip8.poke(0x200, 0x00);
+        assert_eq!(chip8.get_code_writes().unwrap().patch_count, 0);
+    }
+
+    #[test]
+    fn traces_executed_instructions() {
+        let mut chip8 = load(&[
+            0x60, 0x05, // 0x200: LD V0, 5
+            0x00, 0x00, // 0x202:
+            0xFF, 0xFF, // 0x204: unknown
+        ]);
+        chip8.run_cycles(1);
+        assert!(chip8.take_trace().is_empty());
+
+        chip8.set_tracing(true);
+        chip8.run_cycles(10);
+        let trace = chip8.take_trace();
+        // The unknown opcode faults, so it never ran.
+        let ran: Vec<(u16, u16, u8)> = trace.iter().map(|e| (e.pc, e.opcode, e.v[0])).collect();
+        assert_eq!(ran, [(0x202, 0x0000, 5)]);
+        assert!(chip8.take_trace().is_empty());
+
+        // Only the registers an instruction actually changed are marked.
+        let mut chip8 = load(&[
+            0x60, 0xF0, // 0x200: LD V0, 0xF0
+            0x80, 0x04, // 0x202: ADD V0, V0
+            0x60, 0xE0, // 0x204: LD V0, 0xE0
+        ]);
+        chip8.set_tracing(true);
+        chip8.run_cycles(3);
+        let changed: Vec<u16> = chip8.take_trace().iter().map(|e| e.changed).collect();
+        assert_eq!(changed, [0x0001, 0x8001, 0x0000]);
+    }
+
+    #[test]
+    fn run_until_stops_when_condition_holds() {
+        let mut chip8 = load(&[
+            0x70, 0x01, // 0x200: ADD V0, 1
+            0x30, 0x05, // 0x202: SE V0, 5
+            0x12, 0x00, // 0x204: JP 0x200
+            0x13, 0x00, // 0x206: JP 0x300
+        ]);
+        assert!(chip8.run_until(100, |c| c.reg_pc == 0x300));
+        assert_eq!(chip8.reg_v[0], 5);
+
+        // Already there, so nothing more is run.
+        assert!(chip8.run_until(0, |c| c.reg_pc == 0x300));
+    }
+
+    #[test]
+    fn run_until_gives_up_after_max_cycles() {
+        let mut chip8 = load(&[
+            0x70, 0x01, // 0x200: ADD V0, 1
+            0x12, 0x00, // 0x202: JP 0x200
+        ]);
+        assert!(!chip8.run_until(10, |c| c.reg_v[FLAG] == 1));
+        assert_eq!(chip8.reg_v[0], 5);
+    }
+
+    // Runs 8XYn with V1 = vx and V2 = vy, returning V1 and VF afterwards.
+    fn run_alu(n: u8, vx: u8, vy: u8) -> (u8, u8) {
+        let mut chip8 = load(&[
+            0x61,
+            vx, // LD V1, vx
+            0x62,
+            vy, // LD V2, vy
+            0x81,
+            0x20 | n, // 8 1 2 n
+        ]);
+        for _ in 0..3 {
+            chip8.step();
+        }
+        (chip8.reg_v[1], chip8.reg_v[FLAG])
+    }
+
+    #[test]
+    fn add_sets_carry_on_overflow() {
+        assert_eq!(run_alu(0x4, 0x10, 0x20), (0x30, 0));
+        assert_eq!(run_alu(0x4, 0xFF, 0x00), (0xFF, 0));
+        assert_eq!(run_alu(0x4, 0xFF, 0x01), (0x00, 1));
+        assert_eq!(run_alu(0x4, 0x80, 0x90), (0x10, 1));
+    }
+
+    #[test]
+    fn sub_clears_flag_on_borrow() {
+        assert_eq!(run_alu(0x5, 0x30, 0x10), (0x20, 1));
+        assert_eq!(run_alu(0x5, 0x10, 0x30), (0xE0, 0));
+        // No borrow when Vx == Vy
+        assert_eq!(run_alu(0x5, 0x42, 0x42), (0x00, 1));
+    }
+
+    #[test]
+    fn subn_clears_flag_on_borrow() {
+        assert_eq!(run_alu(0x7, 0x10, 0x30), (0x20, 1));
+        assert_eq!(run_alu(0x7, 0x30, 0x10), (0xE0, 0));
+        // No borrow when Vx == Vy
+        assert_eq!(run_alu(0x7, 0x42, 0x42), (0x00, 1));
+    }
+
+    #[test]
+    fn runs_embedded_boot_rom() {
+        let mut chip8 = load(BOOT_ROM);
+        for _ in 0..5000 {
+            chip8.step();
+            let pc = chip8.reg_pc as usize;
+            assert!(pc >= ROMTOP && pc < ROMTOP + BOOT_ROM.len());
+        }
+        assert!(!chip8.is_halted());
+    }
+
+    #[test]
+    fn pixel_addressing_at_corners() {
+        let mut chip8 = Chip8::new();
+        for &(width, height) in &[(64, 32), (128, 64)] {
+            chip8.set_hires(width == 128);
+            let corners = [
+                (0, 0),
+                (width - 1, 0),
+                (0, height - 1),
+                (width - 1, height - 1),
+            ];
+            for (i, &(x, y)) in corners.iter().enumerate() {
+                chip8.set_pixel(x, y, i as u8 + 1);
+            }
+            chip8.publish_display();
+            let data = chip8.get_display_data();
+            assert_eq!(data.len(), width * height);
+            assert_eq!(
+                [
+                    data[0],
+                    data[width - 1],
+                    data[width * (height - 1)],
+                    data[width * height - 1]
+                ],
+                [1, 2, 3, 4]
+            );
+            for (i, &(x, y)) in corners.iter().enumerate() {
+                assert_eq!(chip8.get_pixel(x, y), i as u8 + 1);
+            }
+
+            // Off the display is blank, and setting it does nothing.
+            chip8.set_pixel(width, 0, 1);
+            chip8.set_pixel(0, height, 1);
+            assert_eq!(chip8.get_pixel(width, 0), 0);
+            assert_eq!(chip8.get_pixel(0, height), 0);
+            assert_eq!(
+                chip8.get_display_data().iter().filter(|&&p| p > 0).count(),
+                4
+            );
+        }
+    }
+
+    #[test]
+    fn erased_sprites_are_not_published() {
+        let mut chip8 = load(&[
+            0x60, 0x00, // LD V0, 0
+            0xF0, 0x29, // LD F, V0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xD0, 0x05, // DRW V0, V0, 5, which erases it
+            0xD0, 0x05, // DRW V0, V0, 5, which draws it again
+            0x12, 0x06, // JP 0x206
+        ]);
+        chip8.instructions_per_second = 180;
+        chip8.run_frame();
+        let drawn = chip8.display_ascii();
+        assert!(drawn.starts_with("####"));
+
+        // Erased part way through a frame, which isn't shown.
+        chip8.execute();
+        assert_eq!(chip8.get_pixel(0, 0), 0);
+        assert_eq!(chip8.display_ascii(), drawn);
+
+        // Each frame from here on erases it and draws it again, so it's always shown.
+        chip8.execute();
+        chip8.execute();
+        for _ in 0..10 {
+            chip8.run_frame();
+            assert_eq!(chip8.display_ascii(), drawn);
+        }
+    }
+
+    #[test]
+    fn test_rom_runs_every_original_instruction_it_can() {
+        let mut chip8 = load(TEST_ROM);
+        chip8.set_access_tracking(true);
+        for _ in 0..60 {
+            chip8.run_frame();
+        }
+        let coverage = chip8.coverage_report().unwrap();
+        assert_eq!(
+            coverage.executed.len() + coverage.not_executed.len(),
+            OPCODE_KINDS.len()
+        );
+
+        // It only checks the original instructions. Of those, calling machine code
+        // isn't supported, and waiting for a key, sound and BNNN's quirk aren't
+        // tested.
+        let extended = [
+            "00Cn SCD",
+            "00Dn SCU",
+            "00FB SCR",
+            "00FC SCL",
+            "00FD EXIT",
+            "00FE LOW",
+            "00FF HIGH",
+            "5xy2 SAVE",
+            "5xy3 LOAD",
+            "F000 LD I, long",
+            "Fx01 PLANE",
+            "F002 AUDIO",
+            "Fx30 LD HF",
+            "Fx3A PITCH",
+            "Fx75 LD R",
+            "Fx85 LD Vx, R",
+        ];
+        let missed: Vec<&str> = coverage
+            .not_executed
+            .into_iter()
+            .filter(|kind| !extended.contains(kind))
+            .collect();
+        assert_eq!(
+            missed,
+            ["0nnn SYS", "Bnnn JP V0", "Fx0A LD Vx, K", "Fx18 LD ST"]
+        );
+
+        assert!(load(TEST_ROM).coverage_report().is_none());
+    }
+
+    #[test]
+    fn opcode_kinds_match_the_disassembly() {
+        for opcode in 0..=0xFFFF {
+            let mnemonic = disassemble(opcode);
+            match opcode_kind(opcode) {
+                Some(kind) => {
+                    let kind_mnemonic = OPCODE_KINDS[kind].split(' ').nth(1);
+                    assert_eq!(kind_mnemonic, mnemonic.split(' ').next(), "{:04X}", opcode);
+                }
+                None => assert!(mnemonic.starts_with("DW"), "{:04X}", opcode),
+            }
+        }
+    }
+
+    #[test]
+    fn detects_superchip_rom() {
+        let rom = [
+            0x00, 0xFF, // HIGH
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x10, // DRW V0, V1, 0
+            0x00, 0xFB, // SCR
+            0x00, 0xFD, // EXIT
+        ];
+        assert_eq!(detect_variant(&rom), Variant::SuperChip);
+    }
+
+    #[test]
+    fn detects_xochip_rom() {
+        let rom = [
+            0x00, 0xFF, // HIGH
+            0xF0, 0x00, 0x02, 0x10, // LD I, long 0x210
+            0xF3, 0x01, // PLANE 3
+            0x50, 0x32, // SAVE V0 - V3
+            0x12, 0x0A, // JP 0x20A
+        ];
+        assert_eq!(detect_variant(&rom), Variant::XoChip);
+    }
+
+    #[test]
+    fn ignores_stray_extended_opcodes() {
+        // A plain program with sprite data that happens to look like DRW V0, V0, 0
+        let rom = [
+            0xA2, 0x06, // LD I, 0x206
+            0xD0, 0x15, // DRW V0, V1, 5
+            0x12, 0x04, // JP 0x204
+            0xD0, 0x00, 0xF0, 0x30, 0xF0, // sprite data
+        ];
+        assert_eq!(detect_variant(&rom), Variant::Chip8);
+    }
+
+    // Draws the same sprite twice, with rows 0 and 2 set, and returns VF.
+    fn draw_twice(variant: Variant, hires: bool) -> u8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_variant(variant);
+        let high = if hires { 0xFF } else { 0xE0 }; // HIGH, or CLS
+        chip8
+            .load_rom_bytes(&[
+                0x00, high, // 0x200: HIGH or CLS
+                0xA2, 0x0A, // 0x202: LD I, 0x20A
+                0xD0, 0x03, // 0x204: DRW V0, V0, 3
+                0xD0, 0x03, // 0x206: DRW V0, V0, 3
+                0x12, 0x08, // 0x208: JP 0x208
+                0xFF, 0x00, 0x81, // 0x20A: sprite data
+            ])
+            .unwrap();
+        for _ in 0..4 {
+            chip8.step();
+        }
+        chip8.reg_v[FLAG]
+    }
+
+    #[test]
+    fn hires_drw_counts_colliding_rows() {
+        assert_eq!(draw_twice(Variant::SuperChip, true), 2);
+    }
+
+    #[test]
+    fn xochip_hires_drw_sets_collision_flag() {
+        assert_eq!(draw_twice(Variant::XoChip, true), 1);
+    }
+
+    #[test]
+    fn lores_drw_sets_collision_flag() {
+        assert_eq!(draw_twice(Variant::Chip8, false), 1);
+        assert_eq!(draw_twice(Variant::SuperChip, false), 1);
+        assert_eq!(draw_twice(Variant::XoChip, false), 1);
+    }
+
+    #[test]
+    fn overlapping_sprites_xor_or_or() {
+        // Two 4 pixel wide lines, the second 2 pixels to the right of the first, with
+        // VF set beforehand to see whether DRW touches it.
+        let program = [
+            0x6F, 0x07, // 0x200: LD VF, 7
+            0xA2, 0x0C, // 0x202: LD I, 0x20C
+            0xD0, 0x01, // 0x204: DRW V0, V0, 1
+            0x60, 0x02, // 0x206: LD V0, 2
+            0xD0, 0x11, // 0x208: DRW V0, V1, 1
+            0x12, 0x0A, // 0x20A: JP 0x20A
+            0xF0, // 0x20C: sprite data
+        ];
+        let lit = |chip8: &Chip8| -> Vec<usize> {
+            (0..8).filter(|&x| chip8.get_pixel(x, 0) != 0).collect()
+        };
+
+        // Where they overlap is erased, and that's a collision.
+        let mut chip8 = load(&program);
+        chip8.run_cycles(5);
+        assert_eq!(lit(&chip8), vec![0, 1, 4, 5]);
+        assert_eq!(chip8.reg_v[FLAG], 1);
+
+        // ORing leaves it lit, and never sets VF.
+        let mut chip8 = load(&program);
+        chip8.or_sprites = true;
+        chip8.run_cycles(5);
+        assert_eq!(lit(&chip8), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(chip8.reg_v[FLAG], 7);
+
+        // Nor does it in hi-res, where VF counts the rows that collide.
+        let mut chip8 = load(&program);
+        chip8.set_variant(Variant::SuperChip);
+        chip8.set_hires(true);
+        chip8.or_sprites = true;
+        chip8.run_cycles(5);
+        assert_eq!(lit(&chip8), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(chip8.reg_v[FLAG], 7);
+    }
+
+    // Draws a 4x4 sprite at (62, 30), where it hangs off the right and the bottom,
+    // over a pixel lit at (0, 0). Returns the lit pixels and VF.
+    fn draw_at_corner(wrap_x: bool, wrap_y: bool) -> (Vec<(usize, usize)>, u8) {
+        let mut chip8 = load(&[
+            0x60, 62, // LD V0, 62
+            0x61, 30, // LD V1, 30
+            0xA2, 0x08, // LD I, 0x208
+            0xD0, 0x14, // DRW V0, V1, 4
+            0xF0, 0xF0, 0xF0, 0xF0, // sprite data
+        ]);
+        chip8.wrap_sprites_x = wrap_x;
+        chip8.wrap_sprites_y = wrap_y;
+        chip8.set_pixel(0, 0, 1);
+        chip8.run_cycles(4);
+        let lit = (0..32)
+            .flat_map(|y| (0..64).map(move |x| (x, y)))
+            .filter(|&(x, y)| chip8.get_pixel(x, y) != 0)
+            .collect();
+        (lit, chip8.reg_v[FLAG])
+    }
+
+    // The pixels in the given columns and rows.
+    fn pixels(cols: &[usize], rows: &[usize]) -> Vec<(usize, usize)> {
+        let mut pixels: Vec<(usize, usize)> = rows
+            .iter()
+            .flat_map(|&y| cols.iter().map(move |&x| (x, y)))
+            .collect();
+        pixels.sort_by_key(|&(x, y)| (y, x));
+        pixels
+    }
+
+    #[test]
+    fn sprites_wrap_or_clip_on_each_axis() {
+        // Wrapping both ways, the corner lands on the pixel at (0, 0) and erases it.
+        let (lit, vf) = draw_at_corner(true, true);
+        let mut expected = pixels(&[62, 63, 0, 1], &[30, 31, 0, 1]);
+        expected.retain(|&pixel| pixel != (0, 0));
+        assert_eq!(lit, expected);
+        assert_eq!(vf, 1);
+
+        // Anything clipped isn't drawn, so it can't collide.
+        let (lit, vf) = draw_at_corner(true, false);
+        let mut expected = pixels(&[62, 63, 0, 1], &[30, 31]);
+        expected.insert(0, (0, 0));
+        assert_eq!(lit, expected);
+        assert_eq!(vf, 0);
+
+        let (lit, vf) = draw_at_corner(false, true);
+        let mut expected = pixels(&[62, 63], &[30, 31, 0, 1]);
+        expected.insert(0, (0, 0));
+        assert_eq!(lit, expected);
+        assert_eq!(vf, 0);
+
+        let (lit, vf) = draw_at_corner(false, false);
+        let mut expected = pixels(&[62, 63], &[30, 31]);
+        expected.insert(0, (0, 0));
+        assert_eq!(lit, expected);
+        assert_eq!(vf, 0);
+    }
+
+    #[test]
+    fn hires_counts_rows_clipped_off_the_bottom() {
+        let mut chip8 = Chip8::new();
+        chip8.set_variant(Variant::SuperChip);
+        QuirkProfile::SuperChip.apply(&mut chip8);
+        chip8
+            .load_rom_bytes(&[
+                0x00, 0xFF, // HIGH
+                0x61, 62, // LD V1, 62
+                0xA2, 0x0A, // LD I, 0x20A
+                0xD0, 0x14, // DRW V0, V1, 4
+                0x12, 0x08, // JP 0x208
+                0xF0, 0xF0, 0xF0, 0xF0, // sprite data
+            ])
+            .unwrap();
+        chip8.run_cycles(4);
+        assert_eq!(chip8.reg_v[FLAG], 2);
+    }
+
+    #[test]
+    fn extended_opcodes_need_matching_variant() {
+        let mut chip8 = load(&[0x00, 0xFF]); // HIGH
+        chip8.step();
+        assert_eq!(chip8.get_display_width(), 64);
+        assert_eq!(chip8.get_fault().unwrap().kind, FaultKind::UnknownOpcode);
+
+        chip8.set_variant(Variant::SuperChip);
+        chip8.load_rom_bytes(&[0x00, 0xFF]).unwrap();
+        chip8.step();
+        assert_eq!(chip8.get_display_width(), 128);
+        assert_eq!(chip8.get_display_height(), 64);
+    }
+
+    // Runs an opcode on its own under the given variant, and returns the fault if
+    // it raised one.
+    fn run_opcode(variant: Variant, opcode: &[u8]) -> Option<FaultKind> {
+        let mut chip8 = Chip8::new();
+        chip8.set_variant(variant);
+        chip8.load_rom_bytes(opcode).unwrap();
+        chip8.step();
+        chip8.get_fault().map(|fault| fault.kind)
+    }
+
+    const HIGH: [u8; 2] = [0x00, 0xFF];
+    const SCROLL_DOWN: [u8; 2] = [0x00, 0xC1];
+    const LONG_LOAD: [u8; 4] = [0xF0, 0x00, 0x03, 0x00];
+
+    #[test]
+    fn chip8_faults_on_extended_opcodes() {
+        for opcode in &[&HIGH[..], &SCROLL_DOWN, &LONG_LOAD] {
+            assert_eq!(
+                run_opcode(Variant::Chip8, opcode),
+                Some(FaultKind::UnknownOpcode),
+                "{:02X?}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn superchip_runs_its_opcodes_but_not_xochip_ones() {
+        assert_eq!(run_opcode(Variant::SuperChip, &HIGH), None);
+        assert_eq!(run_opcode(Variant::SuperChip, &SCROLL_DOWN), None);
+        assert_eq!(
+            run_opcode(Variant::SuperChip, &LONG_LOAD),
+            Some(FaultKind::UnknownOpcode)
+        );
+    }
+
+    #[test]
+    fn xochip_runs_extended_opcodes() {
+        for opcode in &[&HIGH[..], &SCROLL_DOWN, &LONG_LOAD] {
+            assert_eq!(run_opcode(Variant::XoChip, opcode), None, "{:02X?}", opcode);
+        }
+    }
+
+    #[test]
+    fn skips_over_long_load_on_xochip() {
+        let mut chip8 = load(&[
+            0x30, 0x00, // SE V0, 0
+            0xF0, 0x00, 0x03, 0x00, // LD I, long 0x300
+            0x61, 0x01, // LD V1, 1
+        ]);
+        chip8.set_variant(Variant::XoChip);
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0x206);
+        chip8.step();
+        assert_eq!(chip8.reg_v[1], 1);
+        assert_eq!(chip8.reg_i, 0);
+    }
+
+    #[test]
+    fn xochip_writes_above_4k() {
+        let mut chip8 = Chip8::new();
+        chip8.set_variant(Variant::XoChip);
+        assert_eq!(chip8.get_memory_size(), 0x10000);
+        chip8
+            .load_rom_bytes(&[
+                0xF0, 0x00, 0x12, 0x34, // LD I, long 0x1234
+                0x60, 0xAB, // LD V0, 0xAB
+                0xF0, 0x55, // LD [I], V0
+            ])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.step();
+        }
+        assert_eq!(chip8.memory[0x1234], 0xAB);
+        assert_eq!(chip8.memory[0x0234], 0);
+    }
+
+    #[test]
+    fn rom_size_limit_follows_memory_size() {
+        let rom = vec![0; 0x2000];
+        let mut chip8 = Chip8::new();
+        assert!(chip8.load_rom_bytes(&rom).is_err());
+
+        chip8.set_variant(Variant::XoChip);
+        assert!(chip8.load_rom_bytes(&rom).is_ok());
+
+        chip8.set_variant(Variant::Chip8);
+        assert_eq!(chip8.get_memory_size(), 0x1000);
+    }
+
+    #[test]
+    fn add_i_wraps_at_4k() {
+        let mut chip8 = load(&[
+            0xAF, 0xFF, // LD I, 0xFFF
+            0x60, 0x02, // LD V0, 2
+            0xF0, 0x1E, // ADD I, V0
+        ]);
+        for _ in 0..3 {
+            chip8.step();
+        }
+        assert_eq!(chip8.reg_i, 0x001);
+        assert_eq!(chip8.reg_v[FLAG], 1);
+    }
+
+    #[test]
+    fn seeded_machines_are_reproducible() {
+        let rom = [
+            0xC0, 0xFF, // RND V0, 0xFF
+            0xC1, 0xFF, // RND V1, 0xFF
+        ];
+        let mut a = Chip8::with_seed(1234);
+        let mut b = Chip8::with_seed(1234);
+        a.load_rom_bytes(&rom).unwrap();
+        b.load_rom_bytes(&rom).unwrap();
+        for _ in 0..2 {
+            a.step();
+            b.step();
+        }
+        assert_eq!(a.reg_v, b.reg_v);
+    }
+
+    #[test]
+    fn run_frame_steps_and_updates_timers() {
+        let mut chip8 = load(&[
+            0x60, 0x05, // LD V0, 5
+            0xF0, 0x15, // LD DT, V0
+            0x70, 0x01, // ADD V0, 1
+            0x12, 0x04, // JP 0x204
+        ]);
+        chip8.instructions_per_second = 240;
+        chip8.run_frame();
+        assert_eq!(chip8.reg_v[0], 6);
+        assert_eq!(chip8.reg_dt, 4);
+    }
+
+    #[test]
+    fn display_is_dirty_only_after_drawing() {
+        let mut chip8 = load(&[
+            0x60, 0x00, // LD V0, 0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x70, 0x01, // ADD V0, 1
+        ]);
+        // Loading clears the display.
+        assert!(chip8.take_display_dirty());
+        chip8.step();
+        assert!(!chip8.take_display_dirty());
+        chip8.step();
+        assert!(chip8.take_display_dirty());
+        assert!(!chip8.take_display_dirty());
+        chip8.step();
+        assert!(!chip8.take_display_dirty());
+    }
+
+    #[test]
+    fn sys_is_ignored() {
+        let mut chip8 = load(&[
+            0x60, 0x07, // LD V0, 7
+            0x01, 0x23, // SYS 0x123
+        ]);
+        chip8.run_cycles(2);
+        assert_eq!(chip8.reg_pc, 0x204);
+        assert_eq!(chip8.reg_sp, 0);
+        assert_eq!(chip8.reg_v[0], 7);
+        assert!(!chip8.is_halted());
+    }
+
+    #[test]
+    fn display_ascii_shows_lit_pixels() {
+        let mut chip8 = load(&[
+            0x60, 0x00, // LD V0, 0
+            0xF0, 0x29, // LD F, V0
+            0xD0, 0x05, // DRW V0, V0, 5
+        ]);
+        chip8.run_cycles(3);
+        let ascii = chip8.display_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines[0].starts_with("####...."));
+        assert!(lines[1].starts_with("#..#...."));
+        assert_eq!(lines[5], ".".repeat(64));
+    }
+
+    #[test]
+    fn tick_timers_runs_at_60hz() {
+        let mut chip8 = load(&[
+            0x60, 0x0A, // LD V0, 10
+            0xF0, 0x15, // LD DT, V0
+        ]);
+        chip8.run_cycles(2);
+
+        chip8.tick_timers(Duration::from_millis(16));
+        assert_eq!(chip8.reg_dt, 10);
+        // A slow frame catches up on the update it missed.
+        chip8.tick_timers(Duration::from_millis(18));
+        assert_eq!(chip8.reg_dt, 8);
+        // The leftover 0.67ms carries over.
+        chip8.tick_timers(Duration::from_millis(8));
+        assert_eq!(chip8.reg_dt, 8);
+        chip8.tick_timers(Duration::from_millis(8));
+        assert_eq!(chip8.reg_dt, 7);
+    }
+
+    #[test]
+    fn tick_timers_keeps_sound_edges() {
+        let mut chip8 = load(&[
+            0x60, 0x03, // LD V0, 3
+            0xF0, 0x18, // LD ST, V0
+        ]);
+        chip8.run_cycles(2);
+
+        chip8.tick_timers(Duration::from_millis(40));
+        assert!(chip8.sound_started());
+        chip8.tick_timers(Duration::from_millis(5));
+        assert!(!chip8.sound_started());
+        chip8.tick_timers(Duration::from_millis(30));
+        assert!(chip8.sound_stopped());
+    }
+
+    #[test]
+    fn run_for_keeps_to_the_instruction_rate() {
+        // ADD V0, 1 over and over, so V0 counts the instructions run.
+        let mut chip8 = load(&[0x70, 0x01].repeat(200));
+        chip8.instructions_per_second = 600;
+
+        // 600 instructions a second is 0.6 a millisecond, which adds up.
+        for _ in 0..100 {
+            chip8.run_for(Duration::from_millis(1));
+        }
+        assert_eq!(chip8.reg_v[0], 60);
+        // The same time in one go runs as many.
+        chip8.run_for(Duration::from_millis(100));
+        assert_eq!(chip8.reg_v[0], 120);
+    }
+
+    #[test]
+    fn run_frame_carries_over_part_instructions() {
+        let mut chip8 = load(&[0x70, 0x01].repeat(200));
+        // A frame and a half's worth of instructions.
+        chip8.instructions_per_second = 90;
+        chip8.run_frame();
+        assert_eq!(chip8.reg_v[0], 1);
+        chip8.run_frame();
+        assert_eq!(chip8.reg_v[0], 3);
+        assert_eq!(chip8.cycles_per_frame(), 1);
+    }
+
+    #[test]
+    fn sound_edges_fire_once_per_transition() {
+        let mut chip8 = load(&[
+            0x60, 0x03, // LD V0, 3
+            0xF0, 0x18, // LD ST, V0
+            0x12, 0x04, // JP 0x204
+        ]);
+        let mut started = 0;
+        let mut stopped = 0;
+
+        for _ in 0..10 {
+            chip8.step();
+            chip8.update_timers();
+            if chip8.sound_started() {
+                started += 1;
             }
+            if chip8.sound_stopped() {
+                stopped += 1;
+            }
+        }
+
+        assert_eq!(started, 1);
+        assert_eq!(stopped, 1);
+        assert!(!chip8.get_sound_active());
+    }
+
+    #[test]
+    fn unknown_opcode_faults_until_skipped() {
+        let mut chip8 = load(&[
+            0x80, 0x0F, // 0x200: not an instruction
+            0x60, 0x01, // 0x202: LD V0, 1
+        ]);
+        chip8.step();
+        let fault = Fault {
+            kind: FaultKind::UnknownOpcode,
+            pc: 0x200,
+            opcode: 0x800F,
+        };
+        assert_eq!(chip8.get_fault(), Some(fault));
+
+        // Nothing runs while faulted.
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0x200);
+
+        chip8.skip_fault();
+        chip8.step();
+        assert_eq!(chip8.get_fault(), None);
+        assert_eq!(chip8.reg_v[0], 1);
+    }
+
+    #[test]
+    fn machine_code_calls_fault_only_in_strict_mode() {
+        let program = [
+            0x01, 0x23, // 0x200: SYS 0x123
+            0x60, 0x01, // 0x202: LD V0, 1
+            0x04, 0x56, // 0x204: SYS 0x456
+        ];
+
+        let mut chip8 = load(&program);
+        chip8.run_cycles(3);
+        assert_eq!(chip8.get_fault(), None);
+        assert_eq!(chip8.reg_v[0], 1);
+        assert_eq!(chip8.get_skipped_opcodes(), 2);
+
+        let mut chip8 = load(&program);
+        chip8.strict_opcodes = true;
+        chip8.run_cycles(3);
+        let fault = Fault {
+            kind: FaultKind::Unimplemented,
+            pc: 0x200,
+            opcode: 0x0123,
+        };
+        assert_eq!(chip8.get_fault(), Some(fault));
+        assert_eq!(chip8.reg_v[0], 0);
+        chip8.skip_fault();
+        chip8.run_cycles(2);
+        assert_eq!(chip8.reg_v[0], 1);
+        assert_eq!(chip8.get_fault().unwrap().pc, 0x204);
+        assert_eq!(chip8.get_skipped_opcodes(), 0);
+
+        // CLS and RET are implemented.
+        let mut chip8 = load(&[0x00, 0xE0]);
+        chip8.strict_opcodes = true;
+        chip8.step();
+        assert_eq!(chip8.get_fault(), None);
+
+        chip8.load_rom_bytes(&program).unwrap();
+        chip8.strict_opcodes = false;
+        chip8.step();
+        assert_eq!(chip8.get_skipped_opcodes(), 1);
+        chip8.load_rom_bytes(&program).unwrap();
+        assert_eq!(chip8.get_skipped_opcodes(), 0);
+    }
+
+    #[test]
+    fn odd_jumps_fault_or_warn() {
+        let program = [
+            0x12, 0x03, // 0x200: JP 0x203
+            0x00, 0x60, // 0x202: 0x203 is LD V0, 0x12
+            0x12, 0x12, // 0x204: 0x205 is JP 0x213
+            0x13, 0x00, // 0x206:
+            0x00, 0x00, // 0x208:
+            0x00, 0x00, // 0x20A:
+            0x00, 0x00, // 0x20C:
+            0x00, 0x00, // 0x20E:
+            0x00, 0x00, // 0x210:
+            0x00, 0x70, // 0x212: 0x213 is ADD V0, 1
+            0x01, 0x00, // 0x214:
+        ];
+
+        // Each odd address is noted once, however often it's run.
+        let mut chip8 = load(&program);
+        chip8.run_cycles(4);
+        assert_eq!(chip8.get_fault(), None);
+        assert_eq!(chip8.reg_v[0], 0x13);
+        assert_eq!(chip8.get_odd_pcs(), &[0x203, 0x205, 0x213]);
+        chip8.reg_pc = 0x203;
+        chip8.run_cycles(3);
+        assert_eq!(chip8.reg_v[0], 0x13);
+        assert_eq!(chip8.get_odd_pcs(), &[0x203, 0x205, 0x213]);
+        chip8.load_rom_bytes(&program).unwrap();
+        assert!(chip8.get_odd_pcs().is_empty());
+
+        let mut chip8 = load(&program);
+        chip8.fault_on_odd_pc = true;
+        chip8.run_cycles(4);
+        let fault = Fault {
+            kind: FaultKind::OddPc,
+            pc: 0x203,
+            opcode: 0x6012,
+        };
+        assert_eq!(chip8.get_fault(), Some(fault));
+        assert_eq!(chip8.reg_v[0], 0);
+        assert!(chip8.get_odd_pcs().is_empty());
+
+        // Skipping would only carry on from another odd address.
+        chip8.skip_fault();
+        assert_eq!(chip8.get_fault(), Some(fault));
+    }
+
+    #[test]
+    fn pc_wraps_at_the_top_of_xochip_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.set_variant(Variant::XoChip);
+        chip8.load_rom_bytes(&[0x70, 0x01]).unwrap(); // 0x200: ADD V0, 1
+
+        // LD V0, 5 at 0xFFFE carries on from 0x0000.
+        chip8.poke(0xFFFE, 0x60);
+        chip8.poke(0xFFFF, 0x05);
+        chip8.reg_pc = 0xFFFE;
+        chip8.step();
+        assert_eq!((chip8.reg_v[0], chip8.reg_pc), (5, 0x0000));
+
+        // SE V0, 5 at 0xFFFC skips the instruction at 0xFFFE.
+        chip8.poke(0xFFFC, 0x30);
+        chip8.poke(0xFFFD, 0x05);
+        chip8.reg_pc = 0xFFFC;
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0x0000);
+
+        // As does it when that's a 4 byte LD I, long addr.
+        chip8.poke(0xFFFE, 0xF0);
+        chip8.poke(0xFFFF, 0x00);
+        chip8.reg_pc = 0xFFFC;
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0x0002);
+
+        // And LD I, long addr itself, with the address at 0x0000.
+        chip8.reg_pc = 0xFFFE;
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0x0002);
+
+        // LD V0, K at 0xFFFE waits there, and carries on from 0x0000 once a key's down.
+        chip8.poke(0xFFFE, 0xF0);
+        chip8.poke(0xFFFF, 0x0A);
+        chip8.reg_pc = 0xFFFE;
+        chip8.step();
+        assert_eq!(chip8.reg_pc, 0xFFFE);
+        chip8.set_key_pressed(7);
+        chip8.step();
+        assert_eq!((chip8.reg_v[0], chip8.reg_pc), (7, 0x0000));
+        assert_eq!(chip8.get_fault(), None);
+    }
+
+    #[test]
+    fn stack_faults() {
+        let mut chip8 = load(&[0x00, 0xEE]); // RET
+        chip8.step();
+        assert_eq!(chip8.get_fault().unwrap().kind, FaultKind::StackUnderflow);
+
+        let mut chip8 = load(&[0x22, 0x00]); // CALL 0x200
+        chip8.run_cycles(20);
+        let fault = chip8.get_fault().unwrap();
+        assert_eq!(fault.kind, FaultKind::StackOverflow);
+        assert_eq!(chip8.reg_sp, 15);
+    }
+
+    #[test]
+    fn call_stack_follows_calls_and_returns() {
+        let program = [
+            0x22, 0x06, // 0x200: CALL 0x206
+            0x12, 0x02, // 0x202: JP 0x202
+            0x00, 0x00, // 0x204:
+            0x22, 0x0A, // 0x206: CALL 0x20A
+            0x00, 0xEE, // 0x208: RET
+            0x00, 0xEE, // 0x20A: RET
+        ];
+        let mut chip8 = load(&program);
+        chip8.run_cycles(2);
+        let frame = |call_site, callee, return_address, overwritten| CallFrame {
+            call_site,
+            callee,
+            return_address,
+            overwritten,
+        };
+        assert_eq!(
+            chip8.get_call_stack(),
+            [
+                frame(0x200, 0x206, 0x202, false),
+                frame(0x206, 0x20A, 0x208, false)
+            ]
+        );
+
+        // The callees aren't saved, but they're worked out again.
+        let state = chip8.save_state();
+        let mut loaded = load(&program);
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.get_call_stack(), chip8.get_call_stack());
+
+        // Writing over a CALL is flagged.
+        chip8.poke(0x206, 0x60);
+        assert!(chip8.get_call_stack()[1].overwritten);
+
+        chip8.step();
+        assert_eq!(chip8.get_call_stack(), [frame(0x200, 0x206, 0x202, false)]);
+    }
+
+    #[test]
+    fn jump_to_self_ends_program() {
+        let mut chip8 = load(&[
+            0x60, 0x01, // 0x200: LD V0, 1
+            0x12, 0x02, // 0x202: JP 0x202
+        ]);
+        chip8.run_cycles(2);
+        let fault = chip8.get_fault().unwrap();
+        assert_eq!((fault.kind, fault.pc), (FaultKind::ProgramEnded, 0x202));
+
+        // There's nothing to carry on with.
+        chip8.skip_fault();
+        assert!(chip8.get_fault().is_some());
+
+        chip8.load_rom_bytes(&[0x12, 0x02]).unwrap();
+        assert_eq!(chip8.get_fault(), None);
+    }
+
+    #[test]
+    fn disassembles_opcodes() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1234), "JP 0x234");
+        assert_eq!(disassemble(0x6A05), "LD VA, 0x05");
+        assert_eq!(disassemble(0x8126), "SHR V1, V2");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0x5122), "SAVE V1 - V2");
+        assert_eq!(disassemble(0x800F), "DW 0x800F");
+        assert_eq!(disassemble(0xFF99), "DW 0xFF99");
+    }
+
+    #[test]
+    fn memory_dump_round_trip() {
+        let path = std::env::temp_dir().join("chipper-memory-dump.bin");
+        let path = path.to_str().unwrap();
+        let mut chip8 = load(&[
+            0x60, 0x42, // 0x200: LD V0, 0x42
+            0xA3, 0x00, // 0x202: LD I, 0x300
+            0xF0, 0x55, // 0x204: LD [I], V0
+        ]);
+        chip8.run_cycles(3);
+        chip8.dump_memory(path).unwrap();
+
+        let mut loaded = Chip8::new();
+        loaded.load_memory(path).unwrap();
+        assert_eq!(loaded.memory, chip8.memory);
+        assert_eq!(loaded.memory[0x300], 0x42);
+
+        // A 4k image doesn't fit XO-CHIP's 64k memory.
+        loaded.set_variant(Variant::XoChip);
+        assert!(loaded.load_memory(path).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn draws_per_frame_can_be_limited() {
+        let rom = [
+            0x70, 0x01, // 0x200: ADD V0, 1
+            0xD1, 0x11, // 0x202: DRW V1, V1, 1
+            0x12, 0x00, // 0x204: JP 0x200
+        ];
+        let mut chip8 = load(&rom);
+        chip8.run_cycles(30);
+        assert_eq!(chip8.reg_v[0], 10);
+
+        let mut chip8 = load(&rom);
+        chip8.max_draws_per_frame = Some(1);
+        chip8.run_cycles(30);
+        assert_eq!(chip8.reg_v[0], 2);
+        assert_eq!(chip8.reg_pc, 0x202);
+
+        // The next frame carries on from the DRW.
+        chip8.run_cycles(30);
+        assert_eq!(chip8.reg_v[0], 3);
+    }
+
+    #[test]
+    fn finds_charset_bytes() {
+        let chip8 = Chip8::new();
+        assert_eq!(chip8.find_bytes(&CHARSET[0..5]), [0x000]);
+        // Both halves of an 8, and parts of 6, 9 and A.
+        assert_eq!(
+            chip8.find_bytes(&[0xF0, 0x90, 0xF0]),
+            [0x020, 0x028, 0x02A, 0x02D, 0x032]
+        );
+        assert!(chip8.find_bytes(&[0xDE, 0xAD]).is_empty());
+        assert!(chip8.find_bytes(&[]).is_empty());
+    }
+
+    #[test]
+    fn poke_writes_memory() {
+        let mut chip8 = load(&[
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x65, // LD V0, [I]
+        ]);
+        chip8.poke(0x300, 0x42);
+        assert_eq!(chip8.peek(0x300), 0x42);
+        chip8.run_cycles(2);
+        assert_eq!(chip8.get_v(0), 0x42);
+
+        // Addresses wrap like the program's own.
+        chip8.poke(0x1300, 0x24);
+        assert_eq!(chip8.peek(0x300), 0x24);
+    }
+
+    // The community quirks test ROM isn't bundled, so this does the same kind of
+    // check for the quirks that are emulated. It shifts 8 right with VY set to 4,
+    // then stores and loads VA to see whether I moves, and draws both results.
+    const QUIRKS_PROBE: [u8; 16] = [
+        0x61, 0x08, // 0x200: LD V1, 0x08
+        0x62, 0x04, // 0x202: LD V2, 0x04
+        0x81, 0x26, // 0x204: SHR V1, V2
+        0x60, 0x0A, // 0x206: LD V0, 0x0A
+        0xA3, 0x00, // 0x208: LD I, 0x300
+        0xF0, 0x55, // 0x20A: LD [I], V0
+        0xF0, 0x65, // 0x20C: LD V0, [I]
+        0x14, 0x00, // 0x20E: JP 0x400
+    ];
+
+    // Puts a routine at 0x400 that draws the digits in V1 and V0 side by side,
+    // then ends.
+    fn draw_digits(chip8: &mut Chip8) {
+        let draw = [
+            0x63, 0x00, // 0x400: LD V3, 0
+            0xF1, 0x29, // 0x402: LD F, V1
+            0xD3, 0x35, // 0x404: DRW V3, V3, 5
+            0x64, 0x05, // 0x406: LD V4, 5
+            0xF0, 0x29, // 0x408: LD F, V0
+            0xD4, 0x35, // 0x40A: DRW V4, V3, 5
+            0x14, 0x0C, // 0x40C: JP 0x40C
+        ];
+        for (i, &byte) in draw.iter().enumerate() {
+            chip8.poke(0x400 + i as u16, byte);
+        }
+    }
+
+    fn run_probe(profile: Option<QuirkProfile>, v1: u8, v0: u8) -> String {
+        let mut chip8 = Chip8::with_seed(1);
+        let rom: Vec<u8> = match profile {
+            Some(_) => QUIRKS_PROBE.to_vec(),
+            // Just draws the expected digits.
+            None => vec![0x61, v1, 0x60, v0, 0x14, 0x00],
+        };
+        chip8.load_rom_bytes(&rom).unwrap();
+        if let Some(profile) = profile {
+            profile.apply(&mut chip8);
+        }
+        draw_digits(&mut chip8);
+        chip8.run_until(1000, |c| c.get_fault().is_some());
+        assert_eq!(chip8.get_fault().unwrap().kind, FaultKind::ProgramEnded);
+        chip8.display_ascii()
+    }
+
+    #[test]
+    fn quirk_profiles_pass_quirks_probe() {
+        // The VIP shifts VY into VX and moves I on loads and stores, so it ends up
+        // reading back 0 from past the stored value. SUPER-CHIP does neither.
+        for &profile in &QuirkProfile::ALL {
+            let (v1, v0) = match profile {
+                QuirkProfile::SuperChip => (0x4, 0xA),
+                _ => (0x2, 0x0),
+            };
+            assert_eq!(
+                run_probe(Some(profile), v1, v0),
+                run_probe(None, v1, v0),
+                "{}",
+                profile.name()
+            );
         }
     }
+
+    #[test]
+    fn chip8_is_send() {
+        // The frontend runs the machine on its own thread.
+        fn assert_send<T: Send>() {}
+        assert_send::<Chip8>();
+    }
 }
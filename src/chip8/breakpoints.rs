@@ -0,0 +1,489 @@
+//! Breakpoints. The program stops before running the instruction at a breakpoint's
+//! address, optionally only when a condition on the registers and memory holds.
+
+use super::Chip8;
+use std::fmt;
+
+/// Something in the machine that a condition looks at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    V(usize),
+    I,
+    Dt,
+    St,
+    /// The byte at an address.
+    Memory(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// The two character comparators come first, so that <= isn't taken for <.
+const COMPARATORS: [(&str, Comparator); 6] = [
+    ("==", Comparator::Equal),
+    ("!=", Comparator::NotEqual),
+    ("<=", Comparator::LessOrEqual),
+    (">=", Comparator::GreaterOrEqual),
+    ("<", Comparator::Less),
+    (">", Comparator::Greater),
+];
+
+impl Comparator {
+    pub fn symbol(self: &Self) -> &'static str {
+        COMPARATORS
+            .iter()
+            .find(|(_, comparator)| comparator == self)
+            .map_or("", |(symbol, _)| symbol)
+    }
+
+    fn compare(self: &Self, a: u16, b: u16) -> bool {
+        match self {
+            Comparator::Equal => a == b,
+            Comparator::NotEqual => a != b,
+            Comparator::Less => a < b,
+            Comparator::LessOrEqual => a <= b,
+            Comparator::Greater => a > b,
+            Comparator::GreaterOrEqual => a >= b,
+        }
+    }
+}
+
+/// Compares part of the machine with a value, such as V3 == 10.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+    pub operand: Operand,
+    pub comparator: Comparator,
+    pub value: u16,
+}
+
+/// Comparisons that all have to hold. An empty condition always holds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Condition {
+    pub comparisons: Vec<Comparison>,
+}
+
+impl Condition {
+    /// Parses comparisons joined with &&, such as "v3 == 10 && i > 300". The
+    /// operands are V0-VF, I, DT, ST and bytes of memory written as [3A0]. Numbers
+    /// are in hex, with or without 0x. Empty text is a condition that always holds.
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        if text.trim().is_empty() {
+            return Ok(Condition::default());
+        }
+        let comparisons = text
+            .split("&&")
+            .map(parse_comparison)
+            .collect::<Result<Vec<Comparison>, String>>()?;
+        Ok(Condition { comparisons })
+    }
+
+    pub fn holds(self: &Self, chip8: &Chip8) -> bool {
+        self.comparisons.iter().all(|comparison| {
+            let value = match comparison.operand {
+                Operand::V(x) => chip8.reg_v[x] as u16,
+                Operand::I => chip8.reg_i,
+                Operand::Dt => chip8.reg_dt as u16,
+                Operand::St => chip8.reg_st as u16,
+                Operand::Memory(addr) => chip8.peek(addr) as u16,
+            };
+            comparison.comparator.compare(value, comparison.value)
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, comparison) in self.comparisons.iter().enumerate() {
+            if i > 0 {
+                write!(f, " && ")?;
+            }
+            match comparison.operand {
+                Operand::V(x) => write!(f, "V{:X}", x)?,
+                Operand::I => write!(f, "I")?,
+                Operand::Dt => write!(f, "DT")?,
+                Operand::St => write!(f, "ST")?,
+                Operand::Memory(addr) => write!(f, "[{:03X}]", addr)?,
+            }
+            write!(
+                f,
+                " {} {:X}",
+                comparison.comparator.symbol(),
+                comparison.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_comparison(text: &str) -> Result<Comparison, String> {
+    let text = text.trim();
+    let (at, symbol, comparator) = COMPARATORS
+        .iter()
+        .filter_map(|&(symbol, comparator)| text.find(symbol).map(|at| (at, symbol, comparator)))
+        .next()
+        .ok_or_else(|| format!("{} isn't a comparison, like V3 == 10", text))?;
+    let (left, right) = (text[..at].trim(), text[at + symbol.len()..].trim());
+
+    let lower = left.to_ascii_lowercase();
+    let operand = match lower.as_str() {
+        "i" => Operand::I,
+        "dt" => Operand::Dt,
+        "st" => Operand::St,
+        _ if lower.starts_with('v') && lower.len() == 2 => parse_hex(&lower[1..])
+            .map(|x| Operand::V(x as usize))
+            .ok_or_else(|| format!("{} isn't a register", left))?,
+        _ if lower.starts_with('[') && lower.ends_with(']') => {
+            parse_hex(&lower[1..lower.len() - 1])
+                .map(Operand::Memory)
+                .ok_or_else(|| format!("{} isn't an address", left))?
+        }
+        _ => return Err(format!("{} isn't V0-VF, I, DT, ST or [address]", left)),
+    };
+    let value = parse_hex(right).ok_or_else(|| format!("{} isn't a hex number", right))?;
+    Ok(Comparison {
+        operand,
+        comparator,
+        value,
+    })
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Why run_to() stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// The PC got to the address.
+    Reached,
+    /// A breakpoint was hit first, at the given address.
+    Breakpoint(u16),
+    /// The program faulted first. See get_fault().
+    Fault,
+    /// The program exited first.
+    Halted,
+    /// The address wasn't reached within the number of steps.
+    OutOfSteps,
+}
+
+/// Stops the program before the instruction at the address, if the condition holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub condition: Condition,
+}
+
+impl Breakpoint {
+    /// Parses a breakpoint written as it's displayed, an address in hex optionally
+    /// followed by if and a condition, such as "2A4 if V3 == 10".
+    pub fn parse(text: &str) -> Result<Breakpoint, String> {
+        let (address, condition) = match text.find(" if ") {
+            Some(at) => (&text[..at], &text[at + 4..]),
+            None => (text, ""),
+        };
+        Ok(Breakpoint {
+            address: parse_hex(address.trim())
+                .ok_or_else(|| format!("{} isn't an address", address.trim()))?,
+            condition: Condition::parse(condition)?,
+        })
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}", self.address)?;
+        if !self.condition.comparisons.is_empty() {
+            write!(f, " if {}", self.condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl Chip8 {
+    /// Adds a breakpoint, replacing any that's already at the address.
+    pub fn set_breakpoint(self: &mut Self, address: u16, condition: Condition) {
+        self.clear_breakpoint(address);
+        self.breakpoints.push(Breakpoint { address, condition });
+        self.breakpoints
+            .sort_by_key(|breakpoint| breakpoint.address);
+    }
+
+    pub fn clear_breakpoint(self: &mut Self, address: u16) {
+        self.breakpoints
+            .retain(|breakpoint| breakpoint.address != address);
+    }
+
+    pub fn clear_all_breakpoints(self: &mut Self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn get_breakpoints(self: &Self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// The address of the breakpoint the program has stopped at, if it has. Nothing
+    /// more is run until it's carried on from.
+    pub fn get_breakpoint_hit(self: &Self) -> Option<u16> {
+        self.breakpoint_hit
+    }
+
+    /// Carries on from a breakpoint, starting with the instruction it stopped at.
+    pub fn continue_from_breakpoint(self: &mut Self) {
+        if self.breakpoint_hit.take().is_some() {
+            self.leaving_breakpoint = true;
+        }
+    }
+
+    /// Runs until the PC next gets to an address, without needing a breakpoint there,
+    /// carrying on from a breakpoint if it's stopped at one. At least one instruction
+    /// is run, so if it's already there it goes round again. It gives up after
+    /// max_steps instructions, as the address may never be reached. Timers aren't
+    /// updated.
+    pub fn run_to(self: &mut Self, address: u16, max_steps: u64) -> StopReason {
+        self.run_until_reached(max_steps, |chip8| chip8.reg_pc == address)
+    }
+
+    /// Steps over a CALL, running until the subroutine returns, or else runs one
+    /// instruction. A subroutine that calls itself has to return all the way back.
+    /// As with run_to(), it gives up after max_steps instructions, in case the
+    /// subroutine never returns.
+    pub fn step_over(self: &mut Self, max_steps: u64) -> StopReason {
+        let pc = self.reg_pc;
+        if self.read_opcode(pc) & 0xF000 != 0x2000 {
+            return self.run_until_reached(1, |_| true);
+        }
+        let (return_address, sp) = (pc.wrapping_add(2), self.reg_sp);
+        self.run_until_reached(max_steps, |chip8| {
+            chip8.reg_pc == return_address && chip8.reg_sp == sp
+        })
+    }
+
+    // Runs until reached says it's got where it's going, or it stops for some other
+    // reason first.
+    fn run_until_reached<F: Fn(&Chip8) -> bool>(
+        self: &mut Self,
+        max_steps: u64,
+        reached: F,
+    ) -> StopReason {
+        self.continue_from_breakpoint();
+        let mut reason = StopReason::OutOfSteps;
+        for _ in 0..max_steps {
+            self.execute();
+            if self.fault.is_some() {
+                reason = StopReason::Fault;
+            } else if let Some(address) = self.breakpoint_hit {
+                reason = StopReason::Breakpoint(address);
+            } else if self.halted {
+                reason = StopReason::Halted;
+            } else if reached(self) {
+                reason = StopReason::Reached;
+            } else {
+                continue;
+            }
+            break;
+        }
+        self.publish_display();
+        reason
+    }
+
+    /// Checks for a breakpoint before the instruction at pc runs, and stops at it if
+    /// there is one. The instruction that was stopped at doesn't stop it again.
+    pub(super) fn stop_at_breakpoint(self: &mut Self, pc: u16) -> bool {
+        let leaving = std::mem::replace(&mut self.leaving_breakpoint, false);
+        if leaving || self.breakpoints.is_empty() {
+            return false;
+        }
+        let hit = self
+            .breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.address == pc && breakpoint.condition.holds(self));
+        if hit {
+            self.breakpoint_hit = Some(pc);
+        }
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(operand: Operand, comparator: Comparator, value: u16) -> Comparison {
+        Comparison {
+            operand,
+            comparator,
+            value,
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let condition = Condition::parse("v3 == 0x10 && I > 300 && [3a0]!=ff").unwrap();
+        assert_eq!(
+            condition.comparisons,
+            [
+                comparison(Operand::V(3), Comparator::Equal, 0x10),
+                comparison(Operand::I, Comparator::Greater, 0x300),
+                comparison(Operand::Memory(0x3A0), Comparator::NotEqual, 0xFF),
+            ]
+        );
+        assert_eq!(condition.to_string(), "V3 == 10 && I > 300 && [3A0] != FF");
+        assert_eq!(Condition::parse(" ").unwrap(), Condition::default());
+
+        assert!(Condition::parse("v3").is_err());
+        assert!(Condition::parse("vg == 1").is_err());
+        assert!(Condition::parse("pc == 200").is_err());
+        assert!(Condition::parse("v3 == 1 &&").is_err());
+        assert!(Condition::parse("[3a0 == 1").is_err());
+    }
+
+    #[test]
+    fn breakpoints_round_trip_as_text() {
+        for text in &["2A4", "2A4 if V3 == 10 && [3A0] != FF"] {
+            assert_eq!(Breakpoint::parse(text).unwrap().to_string(), *text);
+        }
+        let breakpoint = Breakpoint::parse(" 0x300 if i>2 ").unwrap();
+        assert_eq!(breakpoint.address, 0x300);
+        assert_eq!(breakpoint.to_string(), "300 if I > 2");
+
+        assert!(Breakpoint::parse("").is_err());
+        assert!(Breakpoint::parse("start").is_err());
+        assert!(Breakpoint::parse("300 if v3").is_err());
+    }
+
+    #[test]
+    fn each_comparator_compares() {
+        let mut chip8 = Chip8::new();
+        chip8.reg_v[3] = 0x10;
+        let results = |text: &str| -> Vec<bool> {
+            ["f", "10", "11"]
+                .iter()
+                .map(|value| {
+                    Condition::parse(&format!("v3 {} {}", text, value))
+                        .unwrap()
+                        .holds(&chip8)
+                })
+                .collect()
+        };
+        assert_eq!(results("=="), [false, true, false]);
+        assert_eq!(results("!="), [true, false, true]);
+        assert_eq!(results("<"), [false, false, true]);
+        assert_eq!(results("<="), [false, true, true]);
+        assert_eq!(results(">"), [true, false, false]);
+        assert_eq!(results(">="), [true, true, false]);
+    }
+
+    #[test]
+    fn every_comparison_has_to_hold() {
+        let mut chip8 = Chip8::new();
+        chip8.reg_v[3] = 0x10;
+        chip8.reg_i = 0x301;
+        chip8.reg_dt = 5;
+        chip8.poke(0x3A0, 7);
+        let holds = |text: &str| Condition::parse(text).unwrap().holds(&chip8);
+        assert!(holds(""));
+        assert!(holds("v3 == 10 && i > 300"));
+        assert!(!holds("v3 == 10 && i > 301"));
+        assert!(!holds("v3 == 11 && i > 300"));
+        assert!(holds("dt == 5 && st == 0 && [3a0] == 7"));
+    }
+
+    #[test]
+    fn runs_to_an_address() {
+        let program = [
+            0x70, 0x01, // 0x200: ADD V0, 1
+            0x30, 0x05, // 0x202: SE V0, 5
+            0x12, 0x00, // 0x204: JP 0x200
+            0x12, 0x06, // 0x206: JP 0x206
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&program).unwrap();
+        assert_eq!(chip8.run_to(0x206, 100), StopReason::Reached);
+        assert_eq!(chip8.get_v(0), 5);
+
+        // It's run round to again, but it never gets to 0x208.
+        chip8.load_rom_bytes(&program).unwrap();
+        assert_eq!(chip8.run_to(0x200, 100), StopReason::Reached);
+        assert_eq!(chip8.get_v(0), 1);
+        assert_eq!(chip8.run_to(0x208, 10), StopReason::OutOfSteps);
+        assert_eq!(chip8.get_v(0), 5);
+
+        // Faults and breakpoints come first.
+        assert_eq!(chip8.run_to(0x208, 100), StopReason::Fault);
+        assert_eq!(chip8.get_pc(), 0x206);
+        chip8.load_rom_bytes(&program).unwrap();
+        chip8.set_breakpoint(0x204, Condition::default());
+        assert_eq!(chip8.run_to(0x206, 100), StopReason::Breakpoint(0x204));
+        assert_eq!(chip8.run_to(0x206, 100), StopReason::Breakpoint(0x204));
+        assert_eq!(chip8.get_v(0), 2);
+    }
+
+    #[test]
+    fn steps_over_calls() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x22, 0x08, // 0x200: CALL 0x208
+                0x60, 0x07, // 0x202: LD V0, 7
+                0x22, 0x10, // 0x204: CALL 0x210
+                0x00, 0x00, // 0x206:
+                0x71, 0x01, // 0x208: ADD V1, 1
+                0x31, 0x03, // 0x20A: SE V1, 3
+                0x22, 0x08, // 0x20C: CALL 0x208, until V1 is 3
+                0x00, 0xEE, // 0x20E: RET
+                0x72, 0x01, // 0x210: ADD V2, 1
+                0x12, 0x10, // 0x212: JP 0x210
+            ])
+            .unwrap();
+        // The subroutine calls itself, and all of the calls return.
+        assert_eq!(chip8.step_over(100), StopReason::Reached);
+        assert_eq!(chip8.get_pc(), 0x202);
+        assert_eq!((chip8.get_v(1), chip8.get_sp()), (3, 0));
+
+        // Anything else is a single step.
+        assert_eq!(chip8.step_over(100), StopReason::Reached);
+        assert_eq!((chip8.get_pc(), chip8.get_v(0)), (0x204, 7));
+
+        // A subroutine that never returns is given up on.
+        assert_eq!(chip8.step_over(100), StopReason::OutOfSteps);
+        assert_eq!(chip8.get_v(2), 50);
+    }
+
+    #[test]
+    fn stops_at_breakpoints_whose_condition_holds() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x70, 0x01, // 0x200: ADD V0, 1
+                0x12, 0x00, // 0x202: JP 0x200
+            ])
+            .unwrap();
+        chip8.set_breakpoint(0x202, Condition::parse("v0 == 3").unwrap());
+        chip8.run_cycles(100);
+        assert_eq!(chip8.get_breakpoint_hit(), Some(0x202));
+        assert_eq!(chip8.get_v(0), 3);
+        assert_eq!(chip8.get_pc(), 0x202);
+
+        // Carrying on runs the instruction that was stopped at, and the condition
+        // doesn't hold again.
+        chip8.continue_from_breakpoint();
+        chip8.run_cycles(10);
+        assert_eq!(chip8.get_breakpoint_hit(), None);
+
+        chip8.set_breakpoint(0x202, Condition::default());
+        chip8.run_cycles(10);
+        assert_eq!(chip8.get_breakpoint_hit(), Some(0x202));
+        chip8.clear_breakpoint(0x202);
+        chip8.continue_from_breakpoint();
+        chip8.run_cycles(10);
+        assert_eq!(chip8.get_breakpoint_hit(), None);
+    }
+}
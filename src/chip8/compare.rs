@@ -0,0 +1,198 @@
+//! Running two machines side by side, such as the same ROM with different quirks,
+//! to find the first instruction where they stop agreeing.
+
+use super::Chip8;
+
+/// The first part of the machine that's different between two of them, in the
+/// order they're checked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difference {
+    Pc,
+    V(usize),
+    I,
+    Sp,
+    Stack,
+    Dt,
+    St,
+    Flags,
+    /// The first address that's different. Only the memory both machines have is
+    /// compared.
+    Memory(u16),
+    Display,
+    /// One has faulted, halted or stopped at a breakpoint and the other hasn't.
+    Stopped,
+}
+
+impl Difference {
+    pub fn description(self: &Self) -> String {
+        match self {
+            Difference::Pc => "PC".to_string(),
+            Difference::V(x) => format!("V{:X}", x),
+            Difference::I => "I".to_string(),
+            Difference::Sp => "SP".to_string(),
+            Difference::Stack => "stack".to_string(),
+            Difference::Dt => "DT".to_string(),
+            Difference::St => "ST".to_string(),
+            Difference::Flags => "flag registers".to_string(),
+            Difference::Memory(addr) => format!("memory at {:03X}", addr),
+            Difference::Display => "display".to_string(),
+            Difference::Stopped => "whether it's stopped".to_string(),
+        }
+    }
+}
+
+/// Where two machines run by compare_run() first differed. step is how many
+/// instructions had been run, the last being the one at pc.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Divergence {
+    pub step: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub difference: Difference,
+}
+
+impl Chip8 {
+    /// Compares the state of the program in two machines, returning the first
+    /// difference. The settings, such as the quirks, aren't compared.
+    pub fn first_difference(self: &Self, other: &Chip8) -> Option<Difference> {
+        if self.reg_pc != other.reg_pc {
+            return Some(Difference::Pc);
+        }
+        if let Some(x) = (0..16).find(|&x| self.reg_v[x] != other.reg_v[x]) {
+            return Some(Difference::V(x));
+        }
+        let sp = self.reg_sp as usize;
+        let size = self.memory.len().min(other.memory.len());
+        let difference = if self.reg_i != other.reg_i {
+            Difference::I
+        } else if self.reg_sp != other.reg_sp {
+            Difference::Sp
+        } else if self.stack[..=sp] != other.stack[..=sp] {
+            Difference::Stack
+        } else if self.reg_dt != other.reg_dt {
+            Difference::Dt
+        } else if self.reg_st != other.reg_st {
+            Difference::St
+        } else if self.reg_flags != other.reg_flags {
+            Difference::Flags
+        } else if self.memory[..size] != other.memory[..size] {
+            let addr = (0..size).find(|&addr| self.memory[addr] != other.memory[addr]);
+            Difference::Memory(addr.unwrap_or(0) as u16)
+        } else if self.hires != other.hires || self.display[..] != other.display[..] {
+            Difference::Display
+        } else if self.is_stopped() != other.is_stopped() {
+            Difference::Stopped
+        } else {
+            return None;
+        };
+        Some(difference)
+    }
+
+    // Whether nothing more will be run until something's done about it.
+    fn is_stopped(self: &Self) -> bool {
+        self.halted || self.fault.is_some() || self.breakpoint_hit.is_some()
+    }
+}
+
+/// Runs two machines an instruction at a time, for up to max_steps, until they
+/// differ. The timers are updated every a.cycles_per_frame() instructions in both,
+/// about as often as run_frame() would. Returns None if they didn't differ, including when both stop.
+pub fn compare_run(a: &mut Chip8, b: &mut Chip8, max_steps: u64) -> Option<Divergence> {
+    let divergence = |step, pc, opcode, difference| Divergence {
+        step,
+        pc,
+        opcode,
+        difference,
+    };
+    if let Some(difference) = a.first_difference(b) {
+        return Some(divergence(0, a.reg_pc, a.read_opcode(a.reg_pc), difference));
+    }
+    let cycles_per_frame = a.cycles_per_frame().max(1) as u64;
+    for step in 1..=max_steps {
+        if a.is_stopped() && b.is_stopped() {
+            break;
+        }
+        let pc = a.reg_pc;
+        let opcode = a.read_opcode(pc);
+        a.step();
+        b.step();
+        if step % cycles_per_frame == 0 {
+            a.update_timers();
+            b.update_timers();
+        }
+        if let Some(difference) = a.first_difference(b) {
+            return Some(divergence(step, pc, opcode, difference));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::QuirkProfile;
+
+    fn machine(rom: &[u8], profile: QuirkProfile) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.set_variant(profile.variant());
+        profile.apply(&mut chip8);
+        chip8.load_rom_bytes(rom).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn finds_where_the_shift_quirk_matters() {
+        let rom = [
+            0x60, 0x01, // 0x200: LD V0, 1
+            0x61, 0x08, // 0x202: LD V1, 8
+            0x70, 0x01, // 0x204: ADD V0, 1
+            0x30, 0x04, // 0x206: SE V0, 4
+            0x12, 0x04, // 0x208: JP 0x204
+            0x80, 0x16, // 0x20A: SHR V0, V1
+            0x12, 0x0A, // 0x20C: JP 0x20A
+        ];
+        let mut a = machine(&rom, QuirkProfile::CosmacVip);
+        let mut b = machine(&rom, QuirkProfile::SuperChip);
+        let divergence = compare_run(&mut a, &mut b, 1000).unwrap();
+        // V0 is counted up to 4 first, which both agree on.
+        assert_eq!(
+            divergence,
+            Divergence {
+                step: 11,
+                pc: 0x20A,
+                opcode: 0x8016,
+                difference: Difference::V(0),
+            }
+        );
+        assert_eq!((a.get_v(0), b.get_v(0)), (4, 2));
+    }
+
+    #[test]
+    fn the_same_quirks_never_differ() {
+        let rom = [
+            0xC0, 0xFF, // 0x200: RND V0, 0xFF
+            0xF0, 0x15, // 0x202: LD DT, V0
+            0x12, 0x00, // 0x204: JP 0x200
+        ];
+        let mut a = machine(&rom, QuirkProfile::CosmacVip);
+        let mut b = machine(&rom, QuirkProfile::CosmacVip);
+        assert_eq!(compare_run(&mut a, &mut b, 1000), None);
+
+        // A different seed is a difference in the state, not the settings.
+        let mut a = machine(&rom, QuirkProfile::CosmacVip);
+        let mut b = machine(&rom, QuirkProfile::CosmacVip);
+        b.reseed(2);
+        let divergence = compare_run(&mut a, &mut b, 1000).unwrap();
+        assert_eq!(divergence.pc, 0x200);
+        assert_eq!(divergence.difference, Difference::V(0));
+    }
+
+    #[test]
+    fn differences_in_memory_are_found() {
+        let a = machine(&[0x12, 0x00], QuirkProfile::CosmacVip);
+        let mut b = a.clone();
+        assert_eq!(a.first_difference(&b), None);
+        b.poke(0x300, 1);
+        assert_eq!(a.first_difference(&b), Some(Difference::Memory(0x300)));
+    }
+}
@@ -0,0 +1,491 @@
+//! Input recording. A session is recorded as the keys pressed and released on each
+//! frame, along with everything else that decides what the program does, so that it
+//! can be played back and come out exactly the same. A recording can also be run
+//! straight through with Chip8::run_replay(), for tests and tools.
+
+use super::state::{invalid, put_u16, put_u64, Reader};
+use super::{Chip8, Variant};
+use std::io;
+
+const MAGIC: &[u8] = b"CH8I";
+
+/// Bumped whenever the layout changes.
+const VERSION: u8 = 7;
+
+/// A key going down or up, on the given frame of the session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub tick: u64,
+    pub key: u8,
+    pub down: bool,
+}
+
+/// A recorded session. It can only be played back on the ROM it was recorded on,
+/// with the same settings, as anything else would play out differently.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputLog {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub variant: Variant,
+    pub shift_using_vy: bool,
+    pub increment_i_on_ld: bool,
+    pub wrap_sprites_x: bool,
+    pub wrap_sprites_y: bool,
+    pub vf_reset: bool,
+    pub jump_using_vx: bool,
+    pub wait_for_vblank: bool,
+    pub strict_opcodes: bool,
+    pub or_sprites: bool,
+    pub fault_on_odd_pc: bool,
+    pub instructions_per_second: u32,
+    pub max_draws_per_frame: Option<usize>,
+    /// How many frames the session ran for.
+    pub ticks: u64,
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    fn new(chip8: &Chip8, seed: u64) -> InputLog {
+        InputLog {
+            rom_hash: chip8.rom_hash,
+            seed,
+            variant: chip8.variant,
+            shift_using_vy: chip8.shift_using_vy,
+            increment_i_on_ld: chip8.increment_i_on_ld,
+            wrap_sprites_x: chip8.wrap_sprites_x,
+            wrap_sprites_y: chip8.wrap_sprites_y,
+            vf_reset: chip8.vf_reset,
+            jump_using_vx: chip8.jump_using_vx,
+            wait_for_vblank: chip8.wait_for_vblank,
+            strict_opcodes: chip8.strict_opcodes,
+            or_sprites: chip8.or_sprites,
+            fault_on_odd_pc: chip8.fault_on_odd_pc,
+            instructions_per_second: chip8.instructions_per_second,
+            max_draws_per_frame: chip8.max_draws_per_frame,
+            ticks: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Checks that the log can be played back on the machine, saying what's
+    /// different if it can't.
+    pub fn check(self: &Self, chip8: &Chip8) -> io::Result<()> {
+        let expected = InputLog::new(chip8, self.seed);
+        let mismatch = if self.rom_hash != expected.rom_hash {
+            "ROM"
+        } else if self.variant != expected.variant {
+            "variant"
+        } else if self.shift_using_vy != expected.shift_using_vy {
+            "shift quirk"
+        } else if self.increment_i_on_ld != expected.increment_i_on_ld {
+            "load/store quirk"
+        } else if self.wrap_sprites_x != expected.wrap_sprites_x
+            || self.wrap_sprites_y != expected.wrap_sprites_y
+        {
+            "sprite wrapping quirk"
+        } else if self.vf_reset != expected.vf_reset {
+            "VF reset quirk"
+        } else if self.jump_using_vx != expected.jump_using_vx {
+            "jump quirk"
+        } else if self.wait_for_vblank != expected.wait_for_vblank {
+            "vblank quirk"
+        } else if self.strict_opcodes != expected.strict_opcodes {
+            "unimplemented instruction setting"
+        } else if self.or_sprites != expected.or_sprites {
+            "sprite drawing mode"
+        } else if self.fault_on_odd_pc != expected.fault_on_odd_pc {
+            "odd PC setting"
+        } else if self.instructions_per_second != expected.instructions_per_second {
+            "clock speed"
+        } else if self.max_draws_per_frame != expected.max_draws_per_frame {
+            "sprites per frame limit"
+        } else {
+            return Ok(());
+        };
+        Err(invalid(&format!(
+            "The input recording was made with a different {}",
+            mismatch
+        )))
+    }
+
+    pub fn to_bytes(self: &Self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.events.len() * 10);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        put_u64(&mut bytes, self.rom_hash);
+        put_u64(&mut bytes, self.seed);
+        bytes.push(self.variant as u8);
+        bytes.push(self.shift_using_vy as u8);
+        bytes.push(self.increment_i_on_ld as u8);
+        bytes.push(self.wrap_sprites_x as u8);
+        bytes.push(self.wrap_sprites_y as u8);
+        bytes.push(self.vf_reset as u8);
+        bytes.push(self.jump_using_vx as u8);
+        bytes.push(self.wait_for_vblank as u8);
+        bytes.push(self.strict_opcodes as u8);
+        bytes.push(self.or_sprites as u8);
+        bytes.push(self.fault_on_odd_pc as u8);
+        put_u64(&mut bytes, self.instructions_per_second as u64);
+        put_u64(
+            &mut bytes,
+            self.max_draws_per_frame.map_or(0, |max| max as u64 + 1),
+        );
+        put_u64(&mut bytes, self.ticks);
+        put_u64(&mut bytes, self.events.len() as u64);
+        for event in &self.events {
+            put_u64(&mut bytes, event.tick);
+            put_u16(&mut bytes, event.key as u16 | (event.down as u16) << 8);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<InputLog> {
+        let mut reader = Reader {
+            data: bytes,
+            pos: 0,
+        };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("Not an input recording"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid(
+                "The input recording is from a different version of Chipper",
+            ));
+        }
+        let rom_hash = reader.u64()?;
+        let seed = reader.u64()?;
+        let variant = match reader.u8()? {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(invalid("Unknown variant")),
+        };
+        let shift_using_vy = reader.u8()? != 0;
+        let increment_i_on_ld = reader.u8()? != 0;
+        let wrap_sprites_x = reader.u8()? != 0;
+        let wrap_sprites_y = reader.u8()? != 0;
+        let vf_reset = reader.u8()? != 0;
+        let jump_using_vx = reader.u8()? != 0;
+        let wait_for_vblank = reader.u8()? != 0;
+        let strict_opcodes = reader.u8()? != 0;
+        let or_sprites = reader.u8()? != 0;
+        let fault_on_odd_pc = reader.u8()? != 0;
+        let instructions_per_second = reader.u64()? as u32;
+        let max_draws_per_frame = match reader.u64()? {
+            0 => None,
+            max => Some(max as usize - 1),
+        };
+        let ticks = reader.u64()?;
+        let count = reader.u64()?;
+        let mut events: Vec<InputEvent> = Vec::new();
+        for _ in 0..count {
+            let tick = reader.u64()?;
+            let key = reader.u16()?;
+            // Anything else would play back differently from how it was recorded.
+            if key & 0xFF > 0xF || key >> 8 > 1 {
+                return Err(invalid("The input recording has an unknown key"));
+            }
+            if tick > ticks || matches!(events.last(), Some(last) if tick < last.tick) {
+                return Err(invalid("The input recording's events are out of order"));
+            }
+            events.push(InputEvent {
+                tick,
+                key: key as u8,
+                down: key >> 8 != 0,
+            });
+        }
+        if reader.pos != bytes.len() {
+            return Err(invalid("The input recording is too long"));
+        }
+        Ok(InputLog {
+            rom_hash,
+            seed,
+            variant,
+            shift_using_vy,
+            increment_i_on_ld,
+            wrap_sprites_x,
+            wrap_sprites_y,
+            vf_reset,
+            jump_using_vx,
+            wait_for_vblank,
+            strict_opcodes,
+            or_sprites,
+            fault_on_odd_pc,
+            instructions_per_second,
+            max_draws_per_frame,
+            ticks,
+            events,
+        })
+    }
+}
+
+/// Records the input to a machine a frame at a time. All of the input has to go
+/// through the recorder, and the machine has to be run with run_frame(), while
+/// recording.
+pub struct InputRecorder {
+    log: InputLog,
+    outside_changes: u64,
+}
+
+impl InputRecorder {
+    /// Starts recording. The ROM should have just been loaded, as playing back
+    /// starts from there. The RND sequence is started over from seed, any key
+    /// that's held is let go of, and any part instruction owed is dropped.
+    pub fn start(chip8: &mut Chip8, seed: u64) -> InputRecorder {
+        chip8.reseed(seed);
+        chip8.set_key_pressed(0xff);
+        chip8.cycle_accum = 0;
+        InputRecorder {
+            log: InputLog::new(chip8, seed),
+            outside_changes: chip8.get_outside_changes(),
+        }
+    }
+
+    pub fn key_down(self: &mut Self, chip8: &mut Chip8, key: u8) {
+        self.push(chip8, key, true);
+    }
+
+    pub fn key_up(self: &mut Self, chip8: &mut Chip8, key: u8) {
+        self.push(chip8, key, false);
+    }
+
+    fn push(self: &mut Self, chip8: &mut Chip8, key: u8, down: bool) {
+        let event = InputEvent {
+            tick: self.log.ticks,
+            key,
+            down,
+        };
+        apply(chip8, event);
+        self.log.events.push(event);
+    }
+
+    pub fn run_frame(self: &mut Self, chip8: &mut Chip8) {
+        chip8.run_frame();
+        self.log.ticks += 1;
+    }
+
+    /// Returns true if the machine has been changed since recording started other
+    /// than through the recorder, such as by being reset or having a state loaded.
+    /// The recording can't be played back past that, so it should be finished.
+    pub fn is_interrupted(self: &Self, chip8: &Chip8) -> bool {
+        chip8.get_outside_changes() != self.outside_changes
+    }
+
+    pub fn finish(self: Self) -> InputLog {
+        self.log
+    }
+}
+
+/// Plays a recording back, a frame at a time.
+pub struct InputPlayer {
+    log: InputLog,
+    next_event: usize,
+    tick: u64,
+    outside_changes: u64,
+}
+
+impl InputPlayer {
+    /// Starts playing back. The ROM should have just been loaded, as it was when
+    /// recording started. Fails if the machine isn't set up as it was for the
+    /// recording.
+    pub fn start(chip8: &mut Chip8, log: InputLog) -> io::Result<InputPlayer> {
+        log.check(chip8)?;
+        chip8.reseed(log.seed);
+        chip8.set_key_pressed(0xff);
+        chip8.cycle_accum = 0;
+        Ok(InputPlayer {
+            log,
+            next_event: 0,
+            tick: 0,
+            outside_changes: chip8.get_outside_changes(),
+        })
+    }
+
+    /// Runs the next frame with the input that was recorded for it. Does nothing
+    /// once the end of the recording is reached.
+    pub fn run_frame(self: &mut Self, chip8: &mut Chip8) {
+        if self.is_finished() {
+            return;
+        }
+        while let Some(&event) = self.log.events.get(self.next_event) {
+            if event.tick > self.tick {
+                break;
+            }
+            apply(chip8, event);
+            self.next_event += 1;
+        }
+        chip8.run_frame();
+        self.tick += 1;
+    }
+
+    /// Returns true if the machine has been changed since playing back started other
+    /// than through the player, as for InputRecorder::is_interrupted(). What's
+    /// recorded no longer matches it, so playing back should be stopped.
+    pub fn is_interrupted(self: &Self, chip8: &Chip8) -> bool {
+        chip8.get_outside_changes() != self.outside_changes
+    }
+
+    pub fn is_finished(self: &Self) -> bool {
+        self.tick >= self.log.ticks
+    }
+
+    /// Returns how far through the recording it's got, in frames, and how long it is.
+    pub fn progress(self: &Self) -> (u64, u64) {
+        (self.tick, self.log.ticks)
+    }
+}
+
+/// What a replay came out as.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayResult {
+    /// How many frames were run.
+    pub ticks: u64,
+    pub display_hash: u64,
+    /// The machine at the end, from save_state().
+    pub state: Vec<u8>,
+}
+
+impl Chip8 {
+    /// Plays a recording back for the given number of frames, all in one go, and
+    /// returns how it ended up. The ROM should have just been loaded, as for
+    /// InputPlayer::start(), and it fails in the same way if the machine isn't set up
+    /// as it was for the recording. Frames after the end of the recording are run
+    /// with the keys left as they were.
+    pub fn run_replay(self: &mut Self, log: &InputLog, ticks: u64) -> io::Result<ReplayResult> {
+        let mut player = InputPlayer::start(self, log.clone())?;
+        for _ in 0..ticks {
+            if player.is_finished() {
+                self.run_frame();
+            } else {
+                player.run_frame(self);
+            }
+        }
+        Ok(ReplayResult {
+            ticks,
+            display_hash: self.get_display_hash(),
+            state: self.save_state(),
+        })
+    }
+}
+
+fn apply(chip8: &mut Chip8, event: InputEvent) {
+    if event.down {
+        chip8.set_key_pressed(event.key);
+    } else {
+        chip8.set_key_released(event.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a random digit wherever the key that's held says, over and over.
+    const KEY_SPRITES: [u8; 12] = [
+        0xC0, 0x0F, // 0x200: RND V0, 0x0F
+        0xF0, 0x29, // 0x202: LD F, V0
+        0xF1, 0x0A, // 0x204: LD V1, K
+        0x81, 0x1E, // 0x206: SHL V1
+        0xD1, 0x15, // 0x208: DRW V1, V1, 5
+        0x12, 0x00, // 0x20A: JP 0x200
+    ];
+
+    fn record(seed: u64) -> (InputLog, u64) {
+        let mut chip8 = Chip8::with_seed(99);
+        chip8.load_rom_bytes(&KEY_SPRITES).unwrap();
+        let mut recorder = InputRecorder::start(&mut chip8, seed);
+        for key in 0..8 {
+            recorder.run_frame(&mut chip8);
+            recorder.key_down(&mut chip8, key);
+            recorder.run_frame(&mut chip8);
+            recorder.run_frame(&mut chip8);
+            recorder.key_up(&mut chip8, key);
+        }
+        recorder.run_frame(&mut chip8);
+        (recorder.finish(), chip8.get_display_hash())
+    }
+
+    #[test]
+    fn replay_draws_the_same() {
+        let (log, display_hash) = record(5);
+        assert_eq!(log.ticks, 25);
+        assert_eq!(log.events.len(), 16);
+
+        // A different seed to start with, so RND has to be reseeded to match.
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_rom_bytes(&KEY_SPRITES).unwrap();
+        let mut player = InputPlayer::start(&mut chip8, log).unwrap();
+        while !player.is_finished() {
+            player.run_frame(&mut chip8);
+        }
+        assert_eq!(player.progress(), (25, 25));
+        assert_eq!(chip8.get_display_hash(), display_hash);
+        assert_ne!(chip8.get_display_hash(), Chip8::new().get_display_hash());
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let (log, _) = record(5);
+        let bytes = log.to_bytes();
+        assert_eq!(InputLog::from_bytes(&bytes).unwrap(), log);
+        assert!(InputLog::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(InputLog::from_bytes(b"CH8S").is_err());
+    }
+
+    #[test]
+    fn replays_are_byte_identical() {
+        let (log, display_hash) = record(5);
+        let replay = |seed: u64, ticks: u64| {
+            let mut chip8 = Chip8::with_seed(seed);
+            chip8.load_rom_bytes(&KEY_SPRITES).unwrap();
+            chip8.run_replay(&log, ticks).unwrap()
+        };
+        let result = replay(1, log.ticks);
+        assert_eq!(result.ticks, 25);
+        assert_eq!(result.display_hash, display_hash);
+        assert_eq!(replay(2, log.ticks), result);
+
+        // Running on past the end carries on the same way too.
+        let longer = replay(3, log.ticks + 10);
+        assert_eq!(longer.ticks, 35);
+        assert_eq!(replay(4, log.ticks + 10), longer);
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert!(chip8.run_replay(&log, 10).is_err());
+    }
+
+    #[test]
+    fn refuses_events_that_cant_be_played_back() {
+        let (log, _) = record(5);
+        let mut unknown_key = log.clone();
+        unknown_key.events[3].key = 0x10;
+        let mut out_of_order = log.clone();
+        out_of_order.events.swap(2, 3);
+        let mut too_late = log;
+        too_late.events[15].tick = 26;
+        for bad in &[unknown_key, out_of_order, too_late] {
+            assert!(InputLog::from_bytes(&bad.to_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn refuses_other_rom_or_quirks() {
+        let (log, _) = record(5);
+
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert!(InputPlayer::start(&mut chip8, log.clone()).is_err());
+
+        chip8.load_rom_bytes(&KEY_SPRITES).unwrap();
+        chip8.shift_using_vy = true;
+        let error = InputPlayer::start(&mut chip8, log.clone()).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The input recording was made with a different shift quirk"
+        );
+
+        chip8.shift_using_vy = false;
+        assert!(InputPlayer::start(&mut chip8, log).is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+//! Running a program until what it draws stops changing, for test ROMs that show
+//! their results on the display and then sit in a loop, or stop.
+
+use super::Chip8;
+
+impl Chip8 {
+    /// Runs frames until the display has stayed the same for stable_frames frames in
+    /// a row, and returns how many frames that took. Returns None if it's still
+    /// changing after max_frames.
+    pub fn run_until_settled(self: &mut Self, stable_frames: u64, max_frames: u64) -> Option<u64> {
+        let mut last = self.display_key();
+        let mut unchanged = 0;
+        for frame in 0..max_frames {
+            if unchanged == stable_frames {
+                return Some(frame);
+            }
+            self.run_frame();
+            let now = self.display_key();
+            if now == last {
+                unchanged += 1;
+            } else {
+                unchanged = 0;
+                last = now;
+            }
+        }
+        if unchanged == stable_frames {
+            Some(max_frames)
+        } else {
+            None
+        }
+    }
+
+    // What's on the display, as the resolution and a hash of the pixels.
+    fn display_key(self: &Self) -> (bool, u64) {
+        (self.published_hires, self.get_display_hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the top of a 0, erases it once the delay timer has run out, and stops.
+    const DRAW_THEN_ERASE: [u8; 16] = [
+        0x60, 0x14, // 0x200: LD V0, 20
+        0xF0, 0x15, // 0x202: LD DT, V0
+        0xD1, 0x11, // 0x204: DRW V1, V1, 1
+        0xF0, 0x07, // 0x206: LD V0, DT
+        0x30, 0x00, // 0x208: SE V0, 0
+        0x12, 0x06, // 0x20A: JP 0x206
+        0xD1, 0x11, // 0x20C: DRW V1, V1, 1
+        0x12, 0x0E, // 0x20E: JP 0x20E
+    ];
+
+    fn load(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(program).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn settles_once_the_display_stops_changing() {
+        // The sprite is drawn on the first frame and erased on the 21st.
+        let mut chip8 = load(&DRAW_THEN_ERASE);
+        assert_eq!(chip8.run_until_settled(30, 100), Some(51));
+        assert_eq!(chip8.get_display_hash(), Chip8::new().get_display_hash());
+
+        // Too short a wait settles while the sprite's still up.
+        let mut chip8 = load(&DRAW_THEN_ERASE);
+        assert_eq!(chip8.run_until_settled(10, 100), Some(11));
+        assert_ne!(chip8.get_display_hash(), Chip8::new().get_display_hash());
+
+        // Settling on the last frame counts.
+        let mut chip8 = load(&DRAW_THEN_ERASE);
+        assert_eq!(chip8.run_until_settled(30, 51), Some(51));
+        let mut chip8 = load(&DRAW_THEN_ERASE);
+        assert_eq!(chip8.run_until_settled(30, 50), None);
+    }
+
+    #[test]
+    fn gives_up_on_a_display_that_keeps_changing() {
+        let mut chip8 = load(&[
+            0xD1, 0x11, // 0x200: DRW V1, V1, 1
+            0x12, 0x00, // 0x202: JP 0x200
+        ]);
+        chip8.instructions_per_second = 120;
+        assert_eq!(chip8.run_until_settled(2, 1000), None);
+    }
+
+    #[test]
+    fn a_blank_display_that_stays_blank_is_settled() {
+        let mut chip8 = load(&[0x12, 0x00]);
+        assert_eq!(chip8.run_until_settled(5, 100), Some(5));
+        assert_eq!(chip8.run_until_settled(0, 100), Some(0));
+    }
+}
@@ -0,0 +1,377 @@
+//! Save states. A state is a snapshot of the machine as bytes, which can be saved
+//! to a file and loaded back in later to carry on from the same point.
+
+use super::{
+    Chip8, Variant, MAX_DISPLAY_HEIGHT, MAX_DISPLAY_WIDTH, MEMORY_SIZE, ROMTOP, XOCHIP_MEMORY_SIZE,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use std::io;
+
+const MAGIC: &[u8] = b"CH8S";
+
+/// Bumped whenever the layout changes. Older states are refused rather than
+/// loaded wrongly.
+const VERSION: u8 = 1;
+
+impl Chip8 {
+    /// Returns a snapshot of the machine for load_state(). It covers everything the
+    /// program can see, but not the settings, which belong to the front-end, or the
+    /// key being held, which belongs to the user.
+    pub fn save_state(self: &Self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.memory.len() + self.display.len() + 256);
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+        put_u64(&mut state, self.rom_hash);
+        state.push(self.variant as u8);
+        state.extend_from_slice(&self.memory);
+        state.extend_from_slice(&self.reg_v);
+        for &address in &self.stack {
+            put_u16(&mut state, address);
+        }
+        put_u16(&mut state, self.reg_sp);
+        put_u16(&mut state, self.reg_i);
+        put_u16(&mut state, self.reg_pc);
+        state.push(self.reg_dt);
+        state.push(self.reg_st);
+        state.push(self.sound_active as u8);
+        state.extend_from_slice(&self.display);
+        state.push(self.hires as u8);
+        state.push(self.plane_mask);
+        state.extend_from_slice(&self.reg_flags);
+        state.extend_from_slice(&self.audio_pattern);
+        state.push(self.pitch);
+        state.push(self.halted as u8);
+        put_u64(&mut state, self.rom_size as u64);
+        put_u64(&mut state, self.rng_seed);
+        put_u64(&mut state, self.rng_draws);
+        state
+    }
+
+    /// Restores a snapshot from save_state(). The state has to be from the ROM that's
+    /// loaded. If it can't be loaded the machine is left as it was.
+    pub fn load_state(self: &mut Self, state: &[u8]) -> io::Result<()> {
+        let snapshot = self.read_state(state)?;
+
+        self.set_variant(snapshot.variant);
+        self.memory.copy_from_slice(snapshot.memory);
+        self.reg_v.copy_from_slice(snapshot.reg_v);
+        self.stack = snapshot.stack;
+        // What was called isn't saved, but it's whatever the CALLs before the return
+        // addresses call, unless they've been written over since.
+        for i in 1..=snapshot.reg_sp as usize {
+            let call_site = self.stack[i].wrapping_sub(2);
+            self.callees[i] = self.read_opcode(call_site) & 0x0fff;
+        }
+        self.reg_sp = snapshot.reg_sp;
+        self.reg_i = snapshot.reg_i;
+        self.reg_pc = snapshot.reg_pc;
+        self.reg_dt = snapshot.reg_dt;
+        self.reg_st = snapshot.reg_st;
+        self.sound_active = snapshot.sound_active;
+        self.display.copy_from_slice(snapshot.display);
+        self.display_dirty = true;
+        self.hires = snapshot.hires;
+        self.plane_mask = snapshot.plane_mask;
+        self.reg_flags.copy_from_slice(snapshot.reg_flags);
+        self.audio_pattern.copy_from_slice(snapshot.audio_pattern);
+        self.pitch = snapshot.pitch;
+        self.halted = snapshot.halted;
+        self.fault = None;
+        self.breakpoint_hit = None;
+        self.rom_size = snapshot.rom_size;
+
+        // Each number drawn uses one word of the stream, so the RNG can be moved
+        // straight to where it was rather than drawing them all again, which could
+        // take forever for a state that's been tampered with.
+        self.rng = ChaCha20Rng::seed_from_u64(snapshot.rng_seed);
+        self.rng.set_word_pos(snapshot.rng_draws as u128);
+        self.rng_seed = snapshot.rng_seed;
+        self.rng_draws = snapshot.rng_draws;
+        self.outside_changes += 1;
+        self.publish_display();
+
+        Ok(())
+    }
+
+    /// Goes back to an earlier copy of the machine made with clone(), which is quicker
+    /// than a save state for keeping lots of them, as for rewinding. The settings,
+    /// the key being held, the access counts, the code writes, the trace and the
+    /// breakpoints are kept as they are now.
+    pub fn restore(self: &mut Self, mut earlier: Chip8) {
+        earlier.shift_using_vy = self.shift_using_vy;
+        earlier.increment_i_on_ld = self.increment_i_on_ld;
+        earlier.wrap_sprites_x = self.wrap_sprites_x;
+        earlier.wrap_sprites_y = self.wrap_sprites_y;
+        earlier.vf_reset = self.vf_reset;
+        earlier.jump_using_vx = self.jump_using_vx;
+        earlier.wait_for_vblank = self.wait_for_vblank;
+        earlier.strict_opcodes = self.strict_opcodes;
+        earlier.or_sprites = self.or_sprites;
+        earlier.fault_on_odd_pc = self.fault_on_odd_pc;
+        earlier.instructions_per_second = self.instructions_per_second;
+        earlier.max_draws_per_frame = self.max_draws_per_frame;
+        earlier.key_pressed = self.key_pressed;
+        earlier.access_counts = self.access_counts.take();
+        earlier.code_writes = self.code_writes.take();
+        earlier.trace = self.trace.take();
+        earlier.breakpoints = std::mem::take(&mut self.breakpoints);
+        earlier.breakpoint_hit = None;
+        earlier.leaving_breakpoint = false;
+        earlier.display_dirty = true;
+        earlier.outside_changes = self.outside_changes + 1;
+        *self = earlier;
+        self.publish_display();
+    }
+
+    /// Checks that load_state() would accept a state, without loading it.
+    pub fn check_state(self: &Self, state: &[u8]) -> io::Result<()> {
+        self.read_state(state).map(|_| ())
+    }
+
+    // Reads and checks the whole of a state, so that loading it can't fail halfway.
+    fn read_state<'a>(self: &Self, state: &'a [u8]) -> io::Result<Snapshot<'a>> {
+        let mut reader = Reader {
+            data: state,
+            pos: 0,
+        };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("Not a save state"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid(
+                "The save state is from a different version of Chipper",
+            ));
+        }
+        if reader.u64()? != self.rom_hash {
+            return Err(invalid("The save state is for a different ROM"));
+        }
+        let (variant, memory_size) = match reader.u8()? {
+            0 => (Variant::Chip8, MEMORY_SIZE),
+            1 => (Variant::SuperChip, MEMORY_SIZE),
+            2 => (Variant::XoChip, XOCHIP_MEMORY_SIZE),
+            _ => return Err(invalid("Unknown variant")),
+        };
+        let memory = reader.bytes(memory_size)?;
+        let reg_v = reader.bytes(16)?;
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let snapshot = Snapshot {
+            variant,
+            memory,
+            reg_v,
+            stack,
+            reg_sp: reader.u16()?,
+            reg_i: reader.u16()?,
+            reg_pc: reader.u16()?,
+            reg_dt: reader.u8()?,
+            reg_st: reader.u8()?,
+            sound_active: reader.u8()? != 0,
+            display: reader.bytes(MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT)?,
+            hires: reader.u8()? != 0,
+            plane_mask: reader.u8()?,
+            reg_flags: reader.bytes(16)?,
+            audio_pattern: reader.bytes(16)?,
+            pitch: reader.u8()?,
+            halted: reader.u8()? != 0,
+            rom_size: reader.u64()? as usize,
+            rng_seed: reader.u64()?,
+            rng_draws: reader.u64()?,
+        };
+        if reader.pos != state.len() {
+            return Err(invalid("The save state is too long"));
+        }
+        if snapshot.reg_sp as usize >= stack.len() || snapshot.rom_size > memory_size - ROMTOP {
+            return Err(invalid("The save state is corrupt"));
+        }
+        Ok(snapshot)
+    }
+}
+
+// A state that's been read, which borrows the bigger parts from the bytes.
+struct Snapshot<'a> {
+    variant: Variant,
+    memory: &'a [u8],
+    reg_v: &'a [u8],
+    stack: [u16; 16],
+    reg_sp: u16,
+    reg_i: u16,
+    reg_pc: u16,
+    reg_dt: u8,
+    reg_st: u8,
+    sound_active: bool,
+    display: &'a [u8],
+    hires: bool,
+    plane_mask: u8,
+    reg_flags: &'a [u8],
+    audio_pattern: &'a [u8],
+    pitch: u8,
+    halted: bool,
+    rom_size: usize,
+    rng_seed: u64,
+    rng_draws: u64,
+}
+
+pub(super) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(super) fn put_u16(state: &mut Vec<u8>, value: u16) {
+    state.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(super) fn put_u64(state: &mut Vec<u8>, value: u64) {
+    state.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(super) struct Reader<'a> {
+    pub(super) data: &'a [u8],
+    pub(super) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn bytes(self: &mut Self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("The save state is too short"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(super) fn u8(self: &mut Self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(super) fn u16(self: &mut Self) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(super) fn u64(self: &mut Self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a random sprite at a random place over and over.
+    const RANDOM_SPRITES: [u8; 10] = [
+        0xC0, 0xFF, // 0x200: RND V0, 0xFF
+        0xC1, 0xFF, // 0x202: RND V1, 0xFF
+        0xF0, 0x29, // 0x204: LD F, V0
+        0xD0, 0x15, // 0x206: DRW V0, V1, 5
+        0x12, 0x00, // 0x208: JP 0x200
+    ];
+
+    #[test]
+    fn state_round_trip() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        chip8.run_cycles(101);
+        let state = chip8.save_state();
+
+        chip8.run_cycles(500);
+        let expected = chip8.display_ascii();
+        let expected_v = chip8.reg_v;
+
+        // A different seed, so the RND sequence has to come from the state.
+        let mut loaded = Chip8::with_seed(8);
+        loaded.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.reg_pc, 0x202);
+        loaded.run_cycles(500);
+        assert_eq!(loaded.display_ascii(), expected);
+        assert_eq!(loaded.reg_v, expected_v);
+    }
+
+    #[test]
+    fn any_number_of_draws_loads_at_once() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        chip8.run_cycles(101);
+        let mut state = chip8.save_state();
+
+        // The draw count is the last thing in a state. Drawing that many numbers
+        // again would never finish.
+        let draws = state.len() - 8;
+        state[draws..].copy_from_slice(&(1u64 << 60).to_le_bytes());
+        chip8.load_state(&state).unwrap();
+        chip8.run_cycles(5);
+        assert_eq!(chip8.rng_draws, (1 << 60) + 2);
+    }
+
+    #[test]
+    fn restores_earlier_copy() {
+        let mut chip8 = Chip8::with_seed(3);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        chip8.run_cycles(101);
+        let earlier = chip8.clone();
+        let expected = earlier.save_state();
+
+        chip8.run_cycles(500);
+        chip8.instructions_per_second = 3000;
+        assert_ne!(chip8.save_state(), expected);
+
+        chip8.restore(earlier.clone());
+        assert_eq!(chip8.save_state(), expected);
+        assert_eq!(chip8.instructions_per_second, 3000);
+
+        // The copy carries on exactly as the original would have, RND included.
+        let mut original = earlier;
+        original.run_cycles(500);
+        chip8.run_cycles(500);
+        assert_eq!(chip8.save_state(), original.save_state());
+    }
+
+    #[test]
+    fn keeps_variant_and_memory_size() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.set_variant(Variant::XoChip);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        let state = chip8.save_state();
+
+        let mut loaded = Chip8::with_seed(1);
+        loaded.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.get_variant(), Variant::XoChip);
+        assert_eq!(loaded.get_memory_size(), XOCHIP_MEMORY_SIZE);
+    }
+
+    #[test]
+    fn refuses_state_for_another_rom() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        let state = chip8.save_state();
+
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        chip8.run_cycles(1);
+        assert!(chip8.check_state(&state).is_err());
+        let error = chip8.load_state(&state).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(chip8.reg_pc, 0x200);
+    }
+
+    #[test]
+    fn refuses_broken_states() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_rom_bytes(&RANDOM_SPRITES).unwrap();
+        let state = chip8.save_state();
+
+        assert!(chip8.load_state(&state[..state.len() - 1]).is_err());
+        assert!(chip8.load_state(b"not a state").is_err());
+        let mut long = state.clone();
+        long.push(0);
+        assert!(chip8.load_state(&long).is_err());
+        assert!(chip8.check_state(&long).is_err());
+        assert!(chip8.check_state(&state).is_ok());
+        assert!(chip8.load_state(&state).is_ok());
+    }
+}
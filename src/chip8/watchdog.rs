@@ -0,0 +1,142 @@
+//! A hard limit on how long a program is run for, for automated testing, where a
+//! ROM that never finishes mustn't hang the run. Unlike a breakpoint or a fault it
+//! doesn't look at what the program is doing, only at how long it's been going.
+
+use super::Chip8;
+use std::time::{Duration, Instant};
+
+// The clock is only looked at every so many instructions, as it's slow next to
+// running one. It has to be a power of two, as it's used as a mask.
+const CLOCK_CHECK_CYCLES: u64 = 1024;
+
+/// Counts instructions and time across any number of the watched run functions,
+/// and stops them once either limit is reached. Time starts from when it's made.
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    max_cycles: u64,
+    deadline: Instant,
+    cycles: u64,
+    fired: bool,
+}
+
+impl Watchdog {
+    pub fn new(max_cycles: u64, max_time: Duration) -> Watchdog {
+        Watchdog {
+            max_cycles,
+            deadline: Instant::now() + max_time,
+            cycles: 0,
+            fired: false,
+        }
+    }
+
+    /// How many instructions have been run under the watchdog.
+    pub fn get_cycles(self: &Self) -> u64 {
+        self.cycles
+    }
+
+    /// Whether either limit has been reached. Nothing more is run once it has.
+    pub fn has_fired(self: &Self) -> bool {
+        self.fired
+    }
+
+    // Counts an instruction about to be run, unless a limit has been reached.
+    fn allow(self: &mut Self) -> bool {
+        if !self.fired {
+            self.fired = self.cycles >= self.max_cycles
+                || self.cycles & (CLOCK_CHECK_CYCLES - 1) == 0 && Instant::now() >= self.deadline;
+        }
+        if self.fired {
+            return false;
+        }
+        self.cycles += 1;
+        true
+    }
+}
+
+impl Chip8 {
+    /// run_cycles(), but stopping early if the watchdog fires. Returns false if it
+    /// did.
+    pub fn run_cycles_watched(self: &mut Self, cycles: usize, watchdog: &mut Watchdog) -> bool {
+        let mut draws = 0;
+        for _ in 0..cycles {
+            if self.out_of_draws(&mut draws) {
+                break;
+            }
+            if !watchdog.allow() {
+                break;
+            }
+            self.execute();
+        }
+        self.publish_display();
+        !watchdog.has_fired()
+    }
+
+    /// Steps until pred holds, however long that takes, unless the watchdog fires
+    /// first. Returns whether pred held, as run_until() does.
+    pub fn run_until_watched<F: Fn(&Chip8) -> bool>(
+        self: &mut Self,
+        watchdog: &mut Watchdog,
+        pred: F,
+    ) -> bool {
+        while !pred(self) {
+            if !watchdog.allow() {
+                self.publish_display();
+                return false;
+            }
+            self.execute();
+        }
+        self.publish_display();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts up in V0 forever.
+    fn runaway() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x70, 0x01, // 0x200: ADD V0, 1
+                0x12, 0x00, // 0x202: JP 0x200
+            ])
+            .unwrap();
+        chip8
+    }
+
+    #[test]
+    fn stops_after_max_cycles_over_several_runs() {
+        let mut chip8 = runaway();
+        let mut watchdog = Watchdog::new(25, Duration::from_secs(60));
+        assert!(chip8.run_cycles_watched(10, &mut watchdog));
+        assert!(chip8.run_cycles_watched(10, &mut watchdog));
+        assert!(!chip8.run_cycles_watched(10, &mut watchdog));
+        assert!(watchdog.has_fired());
+        assert_eq!(watchdog.get_cycles(), 25);
+        assert_eq!(chip8.get_v(0), 13);
+
+        assert!(!chip8.run_cycles_watched(10, &mut watchdog));
+        assert_eq!(watchdog.get_cycles(), 25);
+    }
+
+    #[test]
+    fn a_program_that_never_finishes_runs_out_of_time() {
+        let mut chip8 = runaway();
+        let started = Instant::now();
+        let mut watchdog = Watchdog::new(u64::MAX, Duration::from_millis(20));
+        assert!(!chip8.run_until_watched(&mut watchdog, |chip8| chip8.is_halted()));
+        assert!(watchdog.has_fired());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn pred_holding_is_reported() {
+        let mut chip8 = runaway();
+        let mut watchdog = Watchdog::new(100, Duration::from_secs(60));
+        assert!(chip8.run_until_watched(&mut watchdog, |chip8| chip8.get_v(0) == 3));
+        assert!(!watchdog.has_fired());
+        assert_eq!(watchdog.get_cycles(), 5);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::audio::Waveform;
+use crate::keymap::Layout;
+use crate::palette;
+use crate::roms::DEFAULT_ROM_DIR;
+use chipper::chip8::{self, Chip8};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const CONFIG_FILE: &str = "chipper.json";
+
+/// The display scales that can be set, from the command line or the config file.
+pub const MIN_DISPLAY_SCALE: u32 = 1;
+pub const MAX_DISPLAY_SCALE: u32 = 32;
+
+/// The clock speeds that can be set, which are 1 to 1000 instructions a frame.
+pub const MIN_INSTRUCTIONS_PER_SECOND: u32 = 60;
+pub const MAX_INSTRUCTIONS_PER_SECOND: u32 = 60_000;
+
+/// Lines that can be drawn over the display for a retro look.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Overlay {
+    Off,
+    /// Lines between all of the pixels.
+    Grid,
+    /// Lines between the rows of pixels.
+    Scanlines,
+}
+
+impl Overlay {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Overlay::Off => "Off",
+            Overlay::Grid => "Grid",
+            Overlay::Scanlines => "Scanlines",
+        }
+    }
+}
+
+/// The user settings, which are persisted to chipper.json in the working directory.
+/// Any setting missing from the file takes its default value, so older config files
+/// keep working as settings are added.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub shift_using_vy: bool,
+    pub increment_i_on_ld: bool,
+    pub wrap_sprites_x: bool,
+    pub wrap_sprites_y: bool,
+    pub vf_reset: bool,
+    pub jump_using_vx: bool,
+    pub wait_for_vblank: bool,
+    /// Stops programs on instructions that aren't implemented, rather than skipping
+    /// them.
+    pub strict_opcodes: bool,
+    /// Experimental. Draws sprites with OR rather than XOR.
+    pub or_sprites: bool,
+    /// Stops programs that run code from an odd address, rather than warning.
+    pub fault_on_odd_pc: bool,
+    pub rom_dir: String,
+    /// The ROM in the ROM folder that's run at startup. Empty for the built-in boot
+    /// ROM, which is also run if it's not there.
+    pub power_on_rom: String,
+    /// Starts with the ROM that was running at exit instead, carrying on from its
+    /// autosave without asking.
+    pub resume_last_session: bool,
+    /// The ROM that was running at exit. Empty for the built-in ROMs.
+    pub last_rom: String,
+    /// The filenames of the ROMs that are starred, which are listed first.
+    pub favorite_roms: BTreeSet<String>,
+    pub auto_reload_rom: bool,
+    pub title_shows_paused: bool,
+    pub pause_when_unfocused: bool,
+    /// While paused, the UI is only updated when something happens, rather than at
+    /// the refresh rate.
+    pub idle_sleep: bool,
+    pub display_scale: u32,
+    /// Keeps the display at a whole multiple of its size when the window's resized,
+    /// so that every pixel is the same size, rather than filling as much as it can.
+    pub integer_scaling: bool,
+    /// The display is only drawn every this many frames, to save time on slow
+    /// machines. 1 draws every frame. Emulation isn't affected.
+    pub frame_skip: u32,
+    /// How many times a second the UI and display are updated. Programs run at the
+    /// same speed whatever it is.
+    pub refresh_rate: u32,
+    /// The clock speed, unless the ROM's metadata says otherwise. It doesn't depend
+    /// on the refresh rate.
+    pub instructions_per_second: u32,
+    /// Display colors as RGB. They're always opaque.
+    pub pixel_on_color: [u8; 3],
+    pub pixel_off_color: [u8; 3],
+    /// XO-CHIP pixels can be lit in the second plane, or in both. Lit only in the
+    /// first is the pixel on color.
+    pub plane2_color: [u8; 3],
+    pub both_planes_color: [u8; 3],
+    /// The name of the preset the colors came from, or "Custom".
+    pub palette: String,
+    pub crt_effect: bool,
+    /// How strong the CRT scanlines and vignette are, from 0 to 1.
+    pub crt_intensity: f32,
+    pub overlay: Overlay,
+    /// How dark the overlay lines are, from 0 to 1.
+    pub overlay_intensity: f32,
+    pub recording_scale: u32,
+    /// The volume of the tone, as a percentage.
+    pub volume: u32,
+    pub muted: bool,
+    pub waveform: Waveform,
+    /// The most sprites drawn each frame, or 0 for no limit.
+    pub max_draws_per_frame: u32,
+    /// How many seconds can be rewound, or 0 to turn rewinding off. Each second
+    /// takes about 1MB, or 5MB for XO-CHIP programs.
+    pub rewind_seconds: u32,
+    /// Keeps the breakpoints for each ROM, to be set again whenever it's loaded.
+    pub save_breakpoints: bool,
+    /// The saved breakpoints, by ROM filename, written as they're shown in the
+    /// debugger, such as "2A4 if V3 == 10".
+    pub breakpoints: BTreeMap<String, Vec<String>>,
+    /// Which keyboard keys are the keypad.
+    pub keyboard_layout: Layout,
+    /// The settings from before they were overridden for the session, and the
+    /// overrides, so that the overrides aren't saved unless they're changed.
+    #[serde(skip)]
+    overridden: Option<Box<(Config, Config)>>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            shift_using_vy: false,
+            increment_i_on_ld: false,
+            wrap_sprites_x: true,
+            wrap_sprites_y: true,
+            vf_reset: false,
+            jump_using_vx: false,
+            wait_for_vblank: false,
+            strict_opcodes: false,
+            or_sprites: false,
+            fault_on_odd_pc: false,
+            rom_dir: DEFAULT_ROM_DIR.to_string(),
+            power_on_rom: String::new(),
+            resume_last_session: false,
+            last_rom: String::new(),
+            favorite_roms: BTreeSet::new(),
+            auto_reload_rom: true,
+            title_shows_paused: true,
+            pause_when_unfocused: true,
+            idle_sleep: true,
+            display_scale: 8,
+            integer_scaling: true,
+            frame_skip: 1,
+            refresh_rate: 60,
+            instructions_per_second: chip8::INSTRUCTIONS_PER_SECOND,
+            pixel_on_color: palette::PRESETS[0].on,
+            pixel_off_color: palette::PRESETS[0].off,
+            plane2_color: [204, 0, 0],
+            both_planes_color: [0, 102, 204],
+            palette: palette::PRESETS[0].name.to_string(),
+            crt_effect: false,
+            crt_intensity: 0.5,
+            overlay: Overlay::Off,
+            overlay_intensity: 0.3,
+            recording_scale: 2,
+            volume: 100,
+            muted: false,
+            waveform: Waveform::Square,
+            max_draws_per_frame: 0,
+            rewind_seconds: 10,
+            save_breakpoints: false,
+            breakpoints: BTreeMap::new(),
+            keyboard_layout: Layout::Qwerty,
+            overridden: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults if it's missing or broken.
+    pub fn load() -> Config {
+        Config::load_from(Path::new(CONFIG_FILE))
+    }
+
+    pub fn save(self: &Self) -> io::Result<()> {
+        self.save_to(Path::new(CONFIG_FILE))
+    }
+
+    pub fn load_from(path: &Path) -> Config {
+        let mut config = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!("Ignoring invalid config file: {}", e);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        };
+        // The window size is worked out from the scale, so anything too big would
+        // overflow it.
+        config.display_scale = config
+            .display_scale
+            .clamp(MIN_DISPLAY_SCALE, MAX_DISPLAY_SCALE);
+        // Nothing would run at all at 0.
+        config.instructions_per_second = config
+            .instructions_per_second
+            .clamp(MIN_INSTRUCTIONS_PER_SECOND, MAX_INSTRUCTIONS_PER_SECOND);
+        config
+    }
+
+    pub fn save_to(self: &Self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_save())?;
+        fs::write(path, json)
+    }
+
+    /// Changes settings for this session only, such as from the command line. Only
+    /// the display scale, the clock speed, the quirks and muting can be overridden.
+    /// Anything else that's changed is saved as usual.
+    pub fn override_for_session<F: FnOnce(&mut Config)>(self: &mut Self, set: F) {
+        let before = self.to_save();
+        set(self);
+        let mut overrides = self.clone();
+        overrides.overridden = None;
+        self.overridden = Some(Box::new((before, overrides)));
+    }
+
+    // Returns the settings to save, which are the ones from before they were
+    // overridden unless they've been changed since.
+    fn to_save(self: &Self) -> Config {
+        let mut config = self.clone();
+        config.overridden = None;
+        if let Some(overridden) = &self.overridden {
+            let (before, overrides) = &**overridden;
+            if config.display_scale == overrides.display_scale {
+                config.display_scale = before.display_scale;
+            }
+            if config.instructions_per_second == overrides.instructions_per_second {
+                config.instructions_per_second = before.instructions_per_second;
+            }
+            if config.shift_using_vy == overrides.shift_using_vy {
+                config.shift_using_vy = before.shift_using_vy;
+            }
+            if config.increment_i_on_ld == overrides.increment_i_on_ld {
+                config.increment_i_on_ld = before.increment_i_on_ld;
+            }
+            if config.wrap_sprites_x == overrides.wrap_sprites_x {
+                config.wrap_sprites_x = before.wrap_sprites_x;
+            }
+            if config.wrap_sprites_y == overrides.wrap_sprites_y {
+                config.wrap_sprites_y = before.wrap_sprites_y;
+            }
+            if config.vf_reset == overrides.vf_reset {
+                config.vf_reset = before.vf_reset;
+            }
+            if config.jump_using_vx == overrides.jump_using_vx {
+                config.jump_using_vx = before.jump_using_vx;
+            }
+            if config.wait_for_vblank == overrides.wait_for_vblank {
+                config.wait_for_vblank = before.wait_for_vblank;
+            }
+            if config.muted == overrides.muted {
+                config.muted = before.muted;
+            }
+        }
+        config
+    }
+
+    /// Returns the colors for each value a pixel can have, which is which planes it's
+    /// lit in: neither, the first, the second, or both.
+    pub fn display_colors(self: &Self) -> [[u8; 3]; 4] {
+        [
+            self.pixel_off_color,
+            self.pixel_on_color,
+            self.plane2_color,
+            self.both_planes_color,
+        ]
+    }
+
+    /// Applies the settings that live in the Chip8 itself.
+    pub fn apply(self: &Self, chip8: &mut Chip8) {
+        chip8.shift_using_vy = self.shift_using_vy;
+        chip8.increment_i_on_ld = self.increment_i_on_ld;
+        chip8.wrap_sprites_x = self.wrap_sprites_x;
+        chip8.wrap_sprites_y = self.wrap_sprites_y;
+        chip8.vf_reset = self.vf_reset;
+        chip8.jump_using_vx = self.jump_using_vx;
+        chip8.wait_for_vblank = self.wait_for_vblank;
+        chip8.strict_opcodes = self.strict_opcodes;
+        chip8.or_sprites = self.or_sprites;
+        chip8.fault_on_odd_pc = self.fault_on_odd_pc;
+        chip8.instructions_per_second = self.instructions_per_second;
+        chip8.max_draws_per_frame = match self.max_draws_per_frame {
+            0 => None,
+            max_draws => Some(max_draws as usize),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_dir_is_persisted() {
+        let path = std::env::temp_dir().join("chipper-config-rom-dir.json");
+        let config = Config {
+            rom_dir: "/home/me/chip8".to_string(),
+            ..Config::default()
+        };
+        config.save_to(&path).unwrap();
+
+        let loaded = Config::load_from(&path);
+        assert_eq!(loaded.rom_dir, "/home/me/chip8");
+        assert_eq!(loaded, config);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_settings_take_defaults() {
+        let path = std::env::temp_dir().join("chipper-config-partial.json");
+        fs::write(&path, r#"{ "shift_using_vy": true }"#).unwrap();
+
+        let loaded = Config::load_from(&path);
+        assert!(loaded.shift_using_vy);
+        assert_eq!(loaded.rom_dir, DEFAULT_ROM_DIR);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn display_scale_is_kept_in_range() {
+        let path = std::env::temp_dir().join("chipper-config-scale.json");
+        fs::write(&path, r#"{"display_scale": 4294967295}"#).unwrap();
+        assert_eq!(Config::load_from(&path).display_scale, MAX_DISPLAY_SCALE);
+        fs::write(&path, r#"{"display_scale": 0}"#).unwrap();
+        assert_eq!(Config::load_from(&path).display_scale, MIN_DISPLAY_SCALE);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn clock_speed_is_kept_in_range() {
+        let path = std::env::temp_dir().join("chipper-config-clock.json");
+        fs::write(&path, r#"{"instructions_per_second": 0}"#).unwrap();
+        let config = Config::load_from(&path);
+        assert_eq!(config.instructions_per_second, MIN_INSTRUCTIONS_PER_SECOND);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let path = std::env::temp_dir().join("chipper-config-missing.json");
+        assert_eq!(Config::load_from(&path), Config::default());
+    }
+
+    #[test]
+    fn session_overrides_are_not_saved() {
+        let path = std::env::temp_dir().join("chipper-config-overrides.json");
+        let mut config = Config::default();
+        config.override_for_session(|c| {
+            c.display_scale = 12;
+            c.muted = true;
+        });
+        config.save_to(&path).unwrap();
+        assert_eq!(Config::load_from(&path), Config::default());
+
+        // Unless they're changed for real.
+        config.display_scale = 16;
+        config.save_to(&path).unwrap();
+        let loaded = Config::load_from(&path);
+        assert_eq!(loaded.display_scale, 16);
+        assert!(!loaded.muted);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
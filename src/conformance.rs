@@ -0,0 +1,260 @@
+use crate::args;
+use chipper::chip8::Chip8;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// How many frames the display has to stay the same for before a ROM's taken to
+/// have finished, unless the manifest says otherwise.
+pub const STABLE_FRAMES: u64 = 60;
+
+/// How many frames a ROM gets to settle in, unless the manifest says otherwise. A
+/// minute at 60Hz.
+pub const MAX_FRAMES: u64 = 3600;
+
+/// The test ROMs for chipper check, and what each of them should draw, as a JSON
+/// file such as:
+///
+/// ```text
+/// {
+///     "roms": [
+///         {
+///             "rom": "../roms/BC_test.ch8",
+///             "display_hash": "1F2E3D4C5B6A7988",
+///             "quirks": "schip",
+///             "ipf": 10,
+///             "seed": 0,
+///             "memory": { "1FF": 1 },
+///             "stable_frames": 60,
+///             "max_frames": 3600
+///         }
+///     ]
+/// }
+/// ```
+///
+/// The ROM paths are relative to the manifest. The hash is Chip8::get_display_hash()
+/// in hex, once the display has settled. Everything after it is optional. Without
+/// quirks the ROM is run with the variant it's detected as, and the default quirks.
+/// Memory is written before the ROM starts, by hex address, for test ROMs that can
+/// be told what to test that way instead of with a menu, such as the community
+/// quirks test ROM's platform at 1FF.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Manifest {
+    pub roms: Vec<TestRom>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct TestRom {
+    pub rom: String,
+    pub display_hash: String,
+    pub quirks: Option<String>,
+    pub ipf: Option<usize>,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub memory: BTreeMap<String, u8>,
+    pub stable_frames: Option<u64>,
+    pub max_frames: Option<u64>,
+}
+
+impl TestRom {
+    /// Runs the ROM until its display settles, and says whether it drew what it
+    /// should have. Fails if it can't be run at all.
+    pub fn check(self: &Self, dir: &Path) -> Result<Outcome, String> {
+        let expected = u64::from_str_radix(&self.display_hash, 16)
+            .map_err(|_| format!("{} isn't a display hash", self.display_hash))?;
+        let path = dir.join(&self.rom);
+        let rom =
+            fs::read(&path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+
+        let mut chip8 = Chip8::with_seed(self.seed);
+        chip8
+            .load_rom_bytes(&rom)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+        if self.quirks.is_some() {
+            let profile = args::quirk_profile("quirks", self.quirks.clone())?;
+            chip8.set_variant(profile.variant());
+            profile.apply(&mut chip8);
+        }
+        if let Some(ipf) = self.ipf {
+            chip8.instructions_per_second = ipf as u32 * 60;
+        }
+        for (address, value) in &self.memory {
+            let address = u16::from_str_radix(address, 16)
+                .map_err(|_| format!("{} isn't a memory address", address))?;
+            chip8.poke(address, *value);
+        }
+
+        let max_frames = self.max_frames.unwrap_or(MAX_FRAMES);
+        let settled =
+            chip8.run_until_settled(self.stable_frames.unwrap_or(STABLE_FRAMES), max_frames);
+        let hash = chip8.get_display_hash();
+        Ok(Outcome {
+            passed: settled.is_some() && hash == expected,
+            frames: settled,
+            hash,
+            screen: chip8.display_ascii(),
+        })
+    }
+}
+
+/// How a test ROM got on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub passed: bool,
+    /// How many frames the display took to settle, if it did.
+    pub frames: Option<u64>,
+    pub hash: u64,
+    pub screen: String,
+}
+
+/// Runs the test ROMs in the manifest, or only the one with the given file name,
+/// and returns a report on them, and whether they all passed. Fails if the manifest
+/// can't be read, or a ROM can't be run.
+pub fn check(manifest_path: &str, only: Option<&str>) -> Result<(String, bool), String> {
+    let json = fs::read_to_string(manifest_path)
+        .map_err(|e| format!("Failed to read {}: {}", manifest_path, e))?;
+    let manifest: Manifest = serde_json::from_str(&json)
+        .map_err(|e| format!("{} isn't a valid manifest: {}", manifest_path, e))?;
+    let dir = Path::new(manifest_path).parent().unwrap_or(Path::new("."));
+
+    let roms: Vec<&TestRom> = manifest
+        .roms
+        .iter()
+        .filter(|test| only.is_none() || only == Some(file_name(&test.rom).as_str()))
+        .collect();
+    if roms.is_empty() {
+        return Err(format!(
+            "{} has no ROM {}",
+            manifest_path,
+            only.unwrap_or("")
+        ));
+    }
+
+    let mut report = String::new();
+    let mut passes = 0;
+    for test in &roms {
+        let outcome = test.check(dir)?;
+        let name = file_name(&test.rom);
+        match outcome.frames {
+            Some(frames) if outcome.passed => {
+                writeln!(report, "PASS {} (settled after {} frames)", name, frames).unwrap();
+                passes += 1;
+                continue;
+            }
+            Some(_) => writeln!(
+                report,
+                "FAIL {}: the display hash is {:016X}, not {}",
+                name, outcome.hash, test.display_hash
+            )
+            .unwrap(),
+            None => writeln!(
+                report,
+                "FAIL {}: the display was still changing after {} frames",
+                name,
+                test.max_frames.unwrap_or(MAX_FRAMES)
+            )
+            .unwrap(),
+        }
+        report += &outcome.screen;
+    }
+    write!(report, "{} of {} passed", passes, roms.len()).unwrap();
+    Ok((report, passes == roms.len()))
+}
+
+// The file name of a ROM path in the manifest, which is how it's picked out on the
+// command line.
+fn file_name(rom: &str) -> String {
+    Path::new(rom)
+        .file_name()
+        .map_or(rom.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_bundled_test_roms_pass() {
+        let (report, passed) = check("tests/conformance.json", None).unwrap();
+        assert!(passed, "{}", report);
+        assert!(report.starts_with("PASS ChipperTest.ch8"));
+        assert!(report.ends_with("2 of 2 passed"));
+
+        let (report, passed) = check("tests/conformance.json", Some("BC_test.ch8")).unwrap();
+        assert!(passed);
+        assert_eq!(report.lines().count(), 2);
+        assert!(check("tests/conformance.json", Some("PONG.ch8")).is_err());
+    }
+
+    #[test]
+    fn shows_what_a_failing_rom_drew() {
+        let dir = std::env::temp_dir().join(format!("chipper-conformance-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("blank.ch8"), [0x12, 0x00]).unwrap();
+        let manifest = dir.join("manifest.json");
+        fs::write(
+            &manifest,
+            r#"{"roms": [{"rom": "blank.ch8", "display_hash": "0", "stable_frames": 5}]}"#,
+        )
+        .unwrap();
+
+        let (report, passed) = check(manifest.to_str().unwrap(), None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!passed);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("FAIL blank.ch8: the display hash is "));
+        assert_eq!(lines[1], ".".repeat(64));
+        assert_eq!(lines.len(), 34);
+        assert_eq!(lines[33], "0 of 1 passed");
+    }
+
+    #[test]
+    fn parses_a_manifest() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"roms": [{"rom": "a.ch8", "display_hash": "AB", "quirks": "vip", "ipf": 20}]}"#,
+        )
+        .unwrap();
+        let test = &manifest.roms[0];
+        assert_eq!(
+            (test.rom.as_str(), test.display_hash.as_str()),
+            ("a.ch8", "AB")
+        );
+        assert_eq!(
+            (test.ipf, test.seed, test.stable_frames),
+            (Some(20), 0, None)
+        );
+        assert!(test.memory.is_empty());
+        assert!(serde_json::from_str::<Manifest>(r#"{"roms": [{"rom": "a.ch8"}]}"#).is_err());
+    }
+
+    #[test]
+    fn writes_memory_before_the_rom_starts() {
+        let dir = std::env::temp_dir().join(format!("chipper-memory-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Draws the font sprite for the digit at 1FF, then stops.
+        let rom = [0xA1, 0xFF, 0xF0, 0x65, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08];
+        fs::write(dir.join("digit.ch8"), rom).unwrap();
+        let test: TestRom = serde_json::from_str(
+            r#"{"rom": "digit.ch8", "display_hash": "0", "memory": {"1FF": 3}, "stable_frames": 5}"#,
+        )
+        .unwrap();
+        let three = test.check(&dir).unwrap();
+        let test = TestRom {
+            memory: BTreeMap::new(),
+            ..test
+        };
+        let zero = test.check(&dir).unwrap();
+        let test = TestRom {
+            memory: [("XYZ".to_string(), 1)].iter().cloned().collect(),
+            ..test
+        };
+        let bad_address = test.check(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_ne!(three.hash, zero.hash);
+        assert_eq!(bad_address, Err("XYZ isn't a memory address".to_string()));
+    }
+}
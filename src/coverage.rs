@@ -0,0 +1,182 @@
+use chipper::chip8::{self, Chip8};
+use std::fmt::Write;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Coverage reports are saved here, relative to the working directory.
+pub const COVERAGE_DIR: &str = "./coverage";
+
+/// Returns the runs of consecutive addresses whose count is non-zero, or zero if
+/// executed is false, in order. start is the address of the first count.
+pub fn coalesce(counts: &[u32], start: usize, executed: bool) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, &count) in counts.iter().enumerate() {
+        if (count > 0) != executed {
+            continue;
+        }
+        let addr = start + i;
+        match ranges.last_mut() {
+            Some(range) if range.end == addr => range.end = addr + 1,
+            _ => ranges.push(addr..addr + 1),
+        }
+    }
+    ranges
+}
+
+/// Writes a report of which bytes of the ROM have been executed since the access
+/// counts were last reset: the ranges that have and haven't, how much of the ROM
+/// that is, and the ROM disassembled with how many times each instruction ran.
+/// Returns None if access tracking is off, as there's nothing to report.
+pub fn report(chip8: &Chip8, rom_name: &str) -> Option<String> {
+    let executes = &chip8.get_access_counts()?.executes;
+    let rom = chip8.get_rom_range();
+    let counts = &executes[rom.clone()];
+    let covered = counts.iter().filter(|&&c| c > 0).count();
+
+    let mut out = String::new();
+    writeln!(out, "Coverage of {}", rom_name).unwrap();
+    writeln!(
+        out,
+        "{} of {} bytes executed ({:.1}%)",
+        covered,
+        counts.len(),
+        covered as f32 * 100f32 / counts.len().max(1) as f32
+    )
+    .unwrap();
+    for &(executed, title) in &[(true, "Executed"), (false, "Never executed")] {
+        writeln!(out, "\n{}:", title).unwrap();
+        for range in coalesce(counts, rom.start, executed) {
+            writeln!(
+                out,
+                "  {:04X}-{:04X}  {} bytes",
+                range.start,
+                range.end - 1,
+                range.len()
+            )
+            .unwrap();
+        }
+    }
+
+    // Which kinds of instruction ran, from all of memory, not just the ROM.
+    if let Some(opcodes) = chip8.coverage_report() {
+        let total = opcodes.executed.len() + opcodes.not_executed.len();
+        for &(kinds, title) in &[
+            (&opcodes.executed, "Instructions executed"),
+            (&opcodes.not_executed, "Instructions never executed"),
+        ] {
+            writeln!(out, "\n{} ({} of {}):", title, kinds.len(), total).unwrap();
+            for kind in kinds.iter() {
+                writeln!(out, "  {}", kind).unwrap();
+            }
+        }
+    }
+
+    writeln!(out, "\nDisassembly:").unwrap();
+    writeln!(out, "      hits  addr  code  instruction").unwrap();
+    let mut addr = rom.start;
+    while addr < rom.end {
+        let hits = executes[addr];
+        // Execution can start on an odd address, after a byte of data, so the
+        // disassembly is kept in step with it.
+        let misaligned = hits == 0 && addr + 1 < rom.end && executes[addr + 1] > 0;
+        if misaligned || addr + 1 == rom.end {
+            let byte = chip8.peek(addr as u16);
+            writeln!(out, "{:10}  {:04X}  {:02X}", hits, addr, byte).unwrap();
+            addr += 1;
+        } else {
+            let opcode = chip8.peek_opcode(addr as u16);
+            writeln!(
+                out,
+                "{:10}  {:04X}  {:04X}  {}",
+                hits,
+                addr,
+                opcode,
+                chip8::disassemble(opcode)
+            )
+            .unwrap();
+            addr += 2;
+        }
+    }
+    Some(out)
+}
+
+/// Saves the coverage report for the ROM in COVERAGE_DIR, and returns where. There's
+/// one per ROM, so a new one replaces the last.
+pub fn save_report(chip8: &Chip8, rom_name: &str) -> Result<String, String> {
+    let report = report(chip8, rom_name)
+        .ok_or("the memory access heatmap has to be on to see what's executed")?;
+    let stem = Path::new(rom_name)
+        .file_stem()
+        .map_or("coverage".into(), |s| s.to_string_lossy());
+    let path = format!("{}/{}.txt", COVERAGE_DIR, stem);
+    fs::create_dir_all(COVERAGE_DIR)
+        .and_then(|()| fs::write(&path, report))
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesces_runs_of_counts() {
+        let counts = [0, 3, 1, 0, 0, 7, 0, 2, 2];
+        assert_eq!(
+            coalesce(&counts, 0x200, true),
+            [0x201..0x203, 0x205..0x206, 0x207..0x209]
+        );
+        assert_eq!(
+            coalesce(&counts, 0x200, false),
+            [0x200..0x201, 0x203..0x205, 0x206..0x207]
+        );
+        assert_eq!(
+            coalesce(&[1, 1, 1], 0, true),
+            vec![Range { start: 0, end: 3 }]
+        );
+        assert!(coalesce(&[1, 1, 1], 0, false).is_empty());
+        assert!(coalesce(&[], 0, true).is_empty());
+    }
+
+    #[test]
+    fn reports_what_ran() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x60, 0x05, // 0x200: LD V0, 5
+                0x12, 0x07, // 0x202: JP 0x207
+                0xAA, 0xBB, 0xCC, // 0x204: data
+                0x12, 0x07, // 0x207: JP 0x207, which stops it
+            ])
+            .unwrap();
+        assert!(report(&chip8, "test.ch8").is_none());
+
+        chip8.set_access_tracking(true);
+        chip8.run_cycles(4);
+        let report = report(&chip8, "test.ch8").unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[1], "6 of 9 bytes executed (66.7%)");
+        assert_eq!(lines[4], "  0200-0203  4 bytes");
+        assert_eq!(lines[5], "  0207-0208  2 bytes");
+        assert_eq!(lines[8], "  0204-0206  3 bytes");
+        assert_eq!(lines[10], "Instructions executed (2 of 51):");
+        assert_eq!(lines[11..13], ["  1nnn JP", "  6xkk LD"]);
+        assert_eq!(lines[14], "Instructions never executed (49 of 51):");
+
+        let disassembly = lines
+            .iter()
+            .position(|&line| line == "Disassembly:")
+            .unwrap();
+        assert_eq!(
+            lines[disassembly + 2..],
+            [
+                format!("         1  0200  6005  {}", chip8::disassemble(0x6005)),
+                format!("         1  0202  1207  {}", chip8::disassemble(0x1207)),
+                format!("         0  0204  AABB  {}", chip8::disassemble(0xAABB)),
+                "         0  0206  CC".to_string(),
+                format!("         1  0207  1207  {}", chip8::disassemble(0x1207)),
+            ]
+        );
+    }
+}
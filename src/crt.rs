@@ -0,0 +1,52 @@
+use egui::{color, Srgba};
+
+/// The CRT texture has 4x4 texels per hi-res pixel (8x8 lo-res), so there's room
+/// to darken the bottom of each row of pixels.
+pub const CRT_WIDTH: usize = 512;
+pub const CRT_HEIGHT: usize = 256;
+
+// How dark the scanlines and the corners get at full intensity.
+const SCANLINE_DARKNESS: f32 = 0.6;
+const VIGNETTE_DARKNESS: f32 = 0.4;
+
+/// Converts the display to texels for the CRT texture, with darkened scanlines
+/// between the rows of pixels and a vignette towards the edges. Intensity goes
+/// from 0 (no effect) to 1. It's applied to the palette colors, so any palette
+/// works with it. colors has a color for each pixel value, as Config::display_colors()
+/// does. The texels replace the contents of the buffer.
+pub fn crt_texels(
+    texels: &mut Vec<Srgba>,
+    display: &[u8],
+    width: usize,
+    height: usize,
+    colors: &[Srgba; 4],
+    intensity: f32,
+) {
+    let intensity = intensity.max(0f32).min(1f32);
+    let texels_per_row = CRT_HEIGHT / height;
+    texels.clear();
+
+    for y in 0..CRT_HEIGHT {
+        let scanline = if y % texels_per_row == texels_per_row - 1 {
+            1f32 - SCANLINE_DARKNESS * intensity
+        } else {
+            1f32
+        };
+        let dy = y as f32 / CRT_HEIGHT as f32 * 2f32 - 1f32;
+
+        for x in 0..CRT_WIDTH {
+            let dx = x as f32 / CRT_WIDTH as f32 * 2f32 - 1f32;
+            let vignette = 1f32 - VIGNETTE_DARKNESS * intensity * (dx * dx + dy * dy) / 2f32;
+            let brightness = scanline * vignette;
+
+            let pixel = display[(y * height / CRT_HEIGHT) * width + x * width / CRT_WIDTH];
+            let c = colors[pixel as usize & 3];
+            texels.push(color::srgba(
+                (c.0[0] as f32 * brightness) as u8,
+                (c.0[1] as f32 * brightness) as u8,
+                (c.0[2] as f32 * brightness) as u8,
+                255,
+            ));
+        }
+    }
+}
@@ -0,0 +1,830 @@
+use crate::cheats::{self, Cheat};
+use crate::config::Config;
+use crate::recorder::Recording;
+use crate::rewind::RewindBuffer;
+use crate::toasts::{Toast, ToastQueue};
+use crate::trace::Trace;
+use chipper::chip8::{Chip8, InputLog, InputPlayer, InputRecorder, StopReason, Variant};
+use std::cell::Cell;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const FRAME_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// If the worker falls further behind than this it gives up on catching up, rather
+/// than running a burst of frames.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// How many instructions stepping can be undone for. A snapshot is a few KB, or up
+/// to about 80KB with XO-CHIP's memory, and they're only kept while stepping.
+pub const STEP_UNDO_DEPTH: usize = 1000;
+
+/// How many instructions running to an address goes through before giving up. It's
+/// run in one go on the emulation thread, so this keeps it to a few milliseconds.
+pub const RUN_TO_MAX_STEPS: u64 = 1_000_000;
+
+/// The machine and everything that's updated along with it every frame. It belongs to
+/// the emulation thread, and the UI changes it by sending commands.
+pub struct Machine {
+    pub chip8: Chip8,
+    pub paused: bool,
+    /// Paused because the window lost focus. It's kept apart from paused so that
+    /// getting focus back doesn't resume a machine the user paused.
+    pub unfocused: bool,
+    pub recording: Option<Recording>,
+    /// Set while the instructions are being traced to a file.
+    pub trace: Option<Trace>,
+    pub rewind: RewindBuffer,
+    /// Set while the rewind key is held. Each frame goes back a frame instead of
+    /// running one, and it carries on from wherever it's got to once it's let go.
+    pub rewinding: bool,
+    /// The machine from before each instruction stepped through while paused, for
+    /// undoing them one at a time. It's cleared as soon as a frame is run.
+    pub step_undo: RewindBuffer,
+    /// Set while the input is being recorded, or played back, in which case the
+    /// user's input is ignored. Rewinding is off while either is going on, as it'd
+    /// throw the recording out.
+    pub input_recorder: Option<InputRecorder>,
+    pub input_player: Option<InputPlayer>,
+    /// What was recorded before the machine was changed from outside, which ends the
+    /// recording, for the UI to save. See end_interrupted_input().
+    pub interrupted_input: Option<InputLog>,
+    /// Set when playing back is stopped because the machine was changed from outside.
+    pub replay_interrupted: bool,
+    /// The cheats for the ROM that's running. They're kept when it's reset.
+    pub cheats: Vec<Cheat>,
+    /// How many frames have been run, for telling whether it's keeping up.
+    pub frames_run: u64,
+    /// How many frames were given up on because it fell too far behind.
+    pub frames_skipped: u64,
+    /// How many commands from the UI have been done.
+    pub commands_done: u64,
+}
+
+/// A copy of the machine as the UI shows it, sent after a frame so that the UI can be
+/// built without holding up the emulator. It isn't written back: anything the UI
+/// changes is sent as a command.
+pub struct MachineView {
+    pub chip8: Chip8,
+    pub paused: bool,
+    pub unfocused: bool,
+    pub rewinding: bool,
+    /// Set once rewinding has gone as far back as it can.
+    pub rewound: bool,
+    /// How many steps can be undone.
+    pub step_undo_len: usize,
+    pub recording: bool,
+    pub tracing: bool,
+    /// Set once the trace has got to its limit, and isn't written to any more.
+    pub trace_full: bool,
+    pub recording_input: bool,
+    /// How far through playing back input it's got, and how long it is, if it is.
+    pub replay_progress: Option<(u64, u64)>,
+    pub cheats: Vec<Cheat>,
+    pub frames_run: u64,
+    pub frames_skipped: u64,
+    pub commands_done: u64,
+}
+
+/// Loads a ROM on to the machine, returning the variant it looks like it was written
+/// for if that's an extension of the one that's set.
+pub type LoadFn = Box<dyn FnOnce(&mut Chip8, &mut ToastQueue) -> Option<Variant> + Send>;
+
+/// Changes the machine, pushing anything it has to say on to the toasts.
+pub type EditFn = Box<dyn FnOnce(&mut Machine, &mut ToastQueue) + Send>;
+
+/// What the UI asks the emulation thread to do to the machine. They're done in the
+/// order they're sent, as soon as they come in.
+pub enum Command {
+    /// Presses a key on the keypad, or lets go of whatever's held for 0xff.
+    KeyDown(u8),
+    KeyUp(u8),
+    Pause(bool),
+    /// Pauses or resumes as the window loses or gets back focus.
+    Unfocused(bool),
+    /// Starts rewinding, unless it can't at the moment, or stops.
+    Rewind(bool),
+    StepFrame,
+    StepInstruction,
+    UndoStep,
+    /// These say why they stopped with Event::Stopped.
+    StepOver,
+    RunTo(u16),
+    /// Loads a ROM, or the same one again to reset it. The variant it looks like it
+    /// was written for is sent back as Event::Loaded.
+    Load(LoadFn),
+    /// Puts the settings in the config that the machine uses on to it.
+    Settings(Box<Config>),
+    /// Anything else done to the machine, from the debugger and the other tools.
+    /// Its toasts are sent on to the UI.
+    Edit(EditFn),
+    /// Asks for an Event::View after the next frame. It isn't counted as a command
+    /// done, as the view can't be up to date with it.
+    SendView,
+}
+
+/// What the emulation thread sends back to the UI.
+pub enum Event {
+    /// A copy of the machine after a frame, when one's been asked for.
+    View(Box<MachineView>),
+    /// Why stepping over or running to an address stopped, or None if it couldn't
+    /// run at the moment.
+    Stopped(Option<StopReason>),
+    /// The variant a ROM that's been loaded looks like it was written for, if that's
+    /// an extension of the one that's set.
+    Loaded(Option<Variant>),
+    /// What was recorded before the machine was changed from outside, which ended the
+    /// recording, for the UI to save.
+    InputInterrupted(InputLog),
+    Toast(Toast),
+}
+
+impl Machine {
+    pub fn new(chip8: Chip8) -> Machine {
+        Machine {
+            chip8,
+            paused: false,
+            unfocused: false,
+            recording: None,
+            trace: None,
+            rewind: RewindBuffer::new(0),
+            rewinding: false,
+            step_undo: RewindBuffer::new(STEP_UNDO_DEPTH),
+            input_recorder: None,
+            input_player: None,
+            interrupted_input: None,
+            replay_interrupted: false,
+            cheats: Vec::new(),
+            frames_run: 0,
+            frames_skipped: 0,
+            commands_done: 0,
+        }
+    }
+
+    pub fn is_running(self: &Self) -> bool {
+        !self.paused && !self.unfocused && !self.rewinding
+    }
+
+    /// Returns true once the trace has got to its limit, after which it isn't
+    /// written to any more.
+    pub fn is_trace_full(self: &Self) -> bool {
+        match &self.trace {
+            Some(trace) => trace.is_full(),
+            None => false,
+        }
+    }
+
+    pub fn can_rewind(self: &Self) -> bool {
+        self.input_recorder.is_none() && self.input_player.is_none()
+    }
+
+    /// Copies out what the UI shows.
+    pub fn view(self: &Self) -> MachineView {
+        MachineView {
+            chip8: self.chip8.clone(),
+            paused: self.paused,
+            unfocused: self.unfocused,
+            rewinding: self.rewinding,
+            rewound: self.rewind.is_empty(),
+            step_undo_len: self.step_undo.len(),
+            recording: self.recording.is_some(),
+            tracing: self.trace.is_some(),
+            trace_full: self.is_trace_full(),
+            recording_input: self.input_recorder.is_some(),
+            replay_progress: self.input_player.as_ref().map(|player| player.progress()),
+            cheats: self.cheats.clone(),
+            frames_run: self.frames_run,
+            frames_skipped: self.frames_skipped,
+            commands_done: self.commands_done,
+        }
+    }
+
+    /// Ends recording or playing back input if the machine has been changed since it
+    /// started other than through the keypad, such as by being reset, loading a ROM
+    /// or a state, or having memory poked. The recording doesn't have the change in
+    /// it, so it'd play out differently from there.
+    pub fn end_interrupted_input(self: &mut Self) {
+        if let Some(recorder) = &self.input_recorder {
+            if recorder.is_interrupted(&self.chip8) {
+                self.interrupted_input = self.input_recorder.take().map(|r| r.finish());
+            }
+        }
+        if let Some(player) = &self.input_player {
+            if player.is_interrupted(&self.chip8) {
+                self.input_player = None;
+                self.replay_interrupted = true;
+            }
+        }
+    }
+
+    /// Presses a key on the keypad, or lets go of whatever's held for 0xff.
+    pub fn key_down(self: &mut Self, key: u8) {
+        self.end_interrupted_input();
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.key_down(&mut self.chip8, key);
+        } else if self.input_player.is_none() {
+            self.chip8.set_key_pressed(key);
+        }
+    }
+
+    pub fn key_up(self: &mut Self, key: u8) {
+        self.end_interrupted_input();
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.key_up(&mut self.chip8, key);
+        } else if self.input_player.is_none() {
+            self.chip8.set_key_released(key);
+        }
+    }
+
+    /// Runs a single frame while paused, for watching the display change a frame at
+    /// a time. It's a 60th of a second's instructions, and the timers tick once.
+    pub fn step_frame(self: &mut Self) {
+        if self.paused && !self.rewinding {
+            self.advance_frame(None);
+        }
+    }
+
+    /// Runs a single instruction while paused, keeping the machine from before it so
+    /// that it can be undone. It's off while recording or playing back input, as the
+    /// recordings are made a whole frame at a time.
+    pub fn step_instruction(self: &mut Self) {
+        if self.paused && !self.rewinding && self.can_rewind() {
+            self.step_undo.push(&self.chip8);
+            self.chip8.continue_from_breakpoint();
+            self.chip8.step();
+            self.write_trace();
+        }
+    }
+
+    /// Runs until the PC gets to an address, while paused, for skipping over code
+    /// without setting a breakpoint. It can be undone like a step. Returns None if it
+    /// can't be done at the moment.
+    pub fn run_to(self: &mut Self, address: u16) -> Option<StopReason> {
+        self.run_debugger(|chip8| chip8.run_to(address, RUN_TO_MAX_STEPS))
+    }
+
+    /// Steps over a CALL while paused, or steps an instruction. It can be undone like
+    /// a step. Returns None if it can't be done at the moment.
+    pub fn step_over(self: &mut Self) -> Option<StopReason> {
+        self.run_debugger(|chip8| chip8.step_over(RUN_TO_MAX_STEPS))
+    }
+
+    // Runs the machine for the debugger while paused, keeping the machine from before
+    // so that it can be undone.
+    fn run_debugger<F: FnOnce(&mut Chip8) -> StopReason>(
+        self: &mut Self,
+        run: F,
+    ) -> Option<StopReason> {
+        if !self.paused || self.rewinding || !self.can_rewind() {
+            return None;
+        }
+        self.step_undo.push(&self.chip8);
+        let reason = run(&mut self.chip8);
+        self.write_trace();
+        Some(reason)
+    }
+
+    /// Passes the instructions run since last time on to the trace, if there is one.
+    pub fn write_trace(self: &mut Self) {
+        if let Some(trace) = &self.trace {
+            trace.write(self.chip8.take_trace());
+        }
+    }
+
+    /// Puts the machine back to before the last instruction that was stepped, display
+    /// and all. Stepping forward again runs it again, as nothing's kept to redo.
+    /// Returns false if there's nothing to undo.
+    pub fn undo_step(self: &mut Self) -> bool {
+        match self.step_undo.pop() {
+            Some(snapshot) => {
+                self.chip8.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Runs for the time that's passed since the last frame, or goes back a frame when
+    // rewinding, unless paused.
+    fn run_frame(self: &mut Self, elapsed: Duration) {
+        if self.rewinding && !self.paused {
+            self.step_undo.clear();
+            if let Some(snapshot) = self.rewind.pop() {
+                self.chip8.restore(snapshot);
+            }
+            return;
+        }
+        if self.is_running() {
+            self.advance_frame(Some(elapsed));
+        }
+    }
+
+    // Runs one frame. Given the time that's passed, it runs for that long at the clock
+    // speed, so how often frames are run doesn't change how fast the program goes, and
+    // a late frame makes up for it. Otherwise it runs a 60th of a second's worth.
+    fn advance_frame(self: &mut Self, elapsed: Option<Duration>) {
+        self.step_undo.clear();
+        self.end_interrupted_input();
+        // Running again, or stepping, carries on from a breakpoint. It pauses when
+        // one's hit, with the rest of the frame left to run.
+        self.chip8.continue_from_breakpoint();
+        // Recordings are made a whole frame at a time, so the timers always tick
+        // once a frame while recording or playing back.
+        if let Some(player) = &mut self.input_player {
+            player.run_frame(&mut self.chip8);
+        } else if let Some(recorder) = &mut self.input_recorder {
+            recorder.run_frame(&mut self.chip8);
+        } else {
+            self.rewind.push(&self.chip8);
+            match elapsed {
+                Some(elapsed) => self.chip8.run_for(elapsed),
+                None => self.chip8.run_frame(),
+            }
+        }
+        // After the frame, so that the program's own writes are undone. They're left
+        // alone while recording or playing back, as they'd change the machine behind
+        // the recording's back.
+        if self.can_rewind() {
+            cheats::apply_frozen(&self.cheats, &mut self.chip8);
+        }
+        self.frames_run += 1;
+        self.write_trace();
+        if self.chip8.get_breakpoint_hit().is_some() {
+            self.paused = true;
+        }
+
+        if let Some(recording) = &self.recording {
+            recording.capture(
+                self.chip8.get_display_data(),
+                self.chip8.get_display_width(),
+                self.chip8.get_display_height(),
+            );
+        }
+    }
+
+    // Does a command from the UI, sending back anything that comes of it.
+    fn obey(self: &mut Self, command: Command, events: &Sender<Event>) {
+        let mut toasts = ToastQueue::default();
+        self.commands_done += 1;
+        match command {
+            Command::KeyDown(key) => self.key_down(key),
+            Command::KeyUp(key) => self.key_up(key),
+            Command::Pause(paused) => self.paused = paused,
+            Command::Unfocused(unfocused) => self.unfocused = unfocused,
+            Command::Rewind(rewinding) => self.rewinding = rewinding && self.can_rewind(),
+            Command::StepFrame => self.step_frame(),
+            Command::StepInstruction => self.step_instruction(),
+            Command::UndoStep => {
+                self.undo_step();
+            }
+            Command::StepOver => {
+                let _ = events.send(Event::Stopped(self.step_over()));
+            }
+            Command::RunTo(address) => {
+                self.paused = true;
+                let _ = events.send(Event::Stopped(self.run_to(address)));
+            }
+            Command::Load(load) => {
+                let variant = load(&mut self.chip8, &mut toasts);
+                let _ = events.send(Event::Loaded(variant));
+            }
+            Command::Settings(config) => {
+                config.apply(&mut self.chip8);
+                self.rewind
+                    .set_capacity(config.rewind_seconds as usize * 60);
+            }
+            Command::Edit(edit) => edit(self, &mut toasts),
+            // It's for after the next frame, so run() sees to it.
+            Command::SendView => {}
+        }
+        for toast in toasts.drain() {
+            let _ = events.send(Event::Toast(toast));
+        }
+    }
+
+    // Tells the UI when playing back input has finished, or recording or playing it
+    // back has been stopped by the machine being changed. It's checked every frame,
+    // running or not, so that a change made while paused is seen to straight away.
+    fn report_input(self: &mut Self, events: &Sender<Event>) {
+        self.end_interrupted_input();
+        if let Some(true) = self.input_player.as_ref().map(|p| p.is_finished()) {
+            self.input_player = None;
+            let _ = events.send(Event::Toast(Toast::info("Input replay finished")));
+        }
+        if let Some(log) = self.interrupted_input.take() {
+            let _ = events.send(Event::InputInterrupted(log));
+        }
+        if self.replay_interrupted {
+            self.replay_interrupted = false;
+            let _ = events.send(Event::Toast(Toast::warning(
+                "Input replay stopped, as the machine was changed",
+            )));
+        }
+    }
+}
+
+/// Runs the machine at 60 frames a second on its own thread, so that it keeps going
+/// when the UI thread stalls, which happens while a window is dragged on Windows.
+///
+/// The thread owns the machine. The UI sends it commands, and builds itself from the
+/// latest MachineView the thread has sent back, so neither ever waits for the other.
+/// A view is only asked for once the last one has come, so they don't pile up while
+/// the UI is stalled.
+pub struct Emulator {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    view: MachineView,
+    view_asked_for: bool,
+    // Not counting SendView, to compare with the view's commands_done.
+    commands_sent: Cell<u64>,
+    // Set when the display has changed in any of the views since take_display_dirty()
+    // was last called.
+    display_dirty: bool,
+    worker: JoinHandle<Machine>,
+}
+
+impl Emulator {
+    /// Starts running the machine. Whether the tone should be playing is sent to
+    /// sound whenever it changes, for the audio callback.
+    pub fn start(machine: Machine, sound: Sender<bool>) -> Emulator {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let view = machine.view();
+        let worker = thread::spawn(move || run(machine, &command_receiver, &event_sender, &sound));
+
+        Emulator {
+            commands,
+            events,
+            view,
+            view_asked_for: false,
+            commands_sent: Cell::new(0),
+            display_dirty: true,
+            worker,
+        }
+    }
+
+    pub fn send(self: &Self, command: Command) {
+        if !matches!(command, Command::SendView) {
+            self.commands_sent.set(self.commands_sent.get() + 1);
+        }
+        self.commands
+            .send(command)
+            .expect("The emulator thread panicked");
+    }
+
+    /// Sends a Command::Edit.
+    pub fn edit<F>(self: &Self, edit: F)
+    where
+        F: FnOnce(&mut Machine, &mut ToastQueue) + Send + 'static,
+    {
+        self.send(Command::Edit(Box::new(edit)));
+    }
+
+    /// Takes what's been sent back since the last call, keeping the latest view and
+    /// returning the other events in the order they came. It asks for the next view
+    /// if the last one has come. Should be called once a frame.
+    pub fn receive(self: &mut Self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for event in self.events.try_iter() {
+            match event {
+                Event::View(mut view) => {
+                    self.display_dirty |= view.chip8.take_display_dirty();
+                    self.view = *view;
+                    self.view_asked_for = false;
+                }
+                event => events.push(event),
+            }
+        }
+        if !self.view_asked_for {
+            self.send(Command::SendView);
+            self.view_asked_for = true;
+        }
+        events
+    }
+
+    /// The machine as of the latest view. It's a frame or so behind, and commands
+    /// that have been sent since it was taken don't show until the next one.
+    pub fn view(self: &Self) -> &MachineView {
+        &self.view
+    }
+
+    /// Returns true if the view shows what's come of all of the commands sent so far.
+    pub fn is_view_current(self: &Self) -> bool {
+        self.view.commands_done == self.commands_sent.get()
+    }
+
+    /// Returns whether the display has changed since the last call, and clears it.
+    pub fn take_display_dirty(self: &mut Self) -> bool {
+        std::mem::replace(&mut self.display_dirty, false)
+    }
+
+    /// Stops the emulation thread, once it's done the commands that have been sent,
+    /// and hands back the machine.
+    pub fn stop(self: Self) -> Machine {
+        drop(self.commands);
+        self.worker.join().expect("The emulator thread panicked")
+    }
+}
+
+/// Keeps the emulation thread to 60 frames a second. Frames that are late are run
+/// straight away to catch up, unless it's so far behind that it gives up on them,
+/// which are counted as skipped.
+pub struct FramePacer {
+    next_frame: Instant,
+    pub frames_skipped: u64,
+}
+
+impl FramePacer {
+    pub fn new(now: Instant) -> FramePacer {
+        FramePacer {
+            next_frame: now,
+            frames_skipped: 0,
+        }
+    }
+
+    /// Called after each frame is run. Returns how long to wait for the next one.
+    pub fn next(self: &mut Self, now: Instant) -> Duration {
+        self.next_frame += FRAME_PERIOD;
+        if self.next_frame > now {
+            return self.next_frame - now;
+        }
+        let lag = now - self.next_frame;
+        if lag > MAX_LAG {
+            self.frames_skipped += (lag.as_nanos() / FRAME_PERIOD.as_nanos()) as u64;
+            self.next_frame = now;
+        }
+        Duration::from_secs(0)
+    }
+}
+
+// The emulation thread. It runs until the UI hangs up, then hands back the machine.
+// The UI may have gone by the time anything's sent back, so that's allowed to fail.
+fn run(
+    mut machine: Machine,
+    commands: &Receiver<Command>,
+    events: &Sender<Event>,
+    sound: &Sender<bool>,
+) -> Machine {
+    let mut pacer = FramePacer::new(Instant::now());
+    let mut last_tick = Instant::now();
+    let mut view_wanted = false;
+    let mut sound_active = false;
+
+    loop {
+        // Time passes whether the emulator is paused or not, so it's measured every
+        // frame and only used when running. After a stall, no more than MAX_LAG is
+        // made up for.
+        let elapsed = last_tick.elapsed().min(MAX_LAG);
+        last_tick = Instant::now();
+        machine.run_frame(elapsed);
+        machine.frames_skipped = pacer.frames_skipped;
+        machine.report_input(events);
+        let sound_now = machine.chip8.get_sound_active() && machine.is_running();
+        if sound_now != sound_active {
+            sound_active = sound_now;
+            let _ = sound.send(sound_active);
+        }
+        // Each view says whether the display has changed since the last one.
+        if view_wanted {
+            view_wanted = false;
+            let _ = events.send(Event::View(Box::new(machine.view())));
+            machine.chip8.take_display_dirty();
+        }
+
+        // Commands are done as they come in while waiting for the next frame.
+        let next_frame = Instant::now() + pacer.next(Instant::now());
+        loop {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            match commands.recv_timeout(timeout) {
+                Ok(Command::SendView) => view_wanted = true,
+                Ok(command) => machine.obey(command, events),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return machine,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chipper::chip8::Condition;
+
+    #[test]
+    fn stepping_back_and_forward_again_comes_out_the_same() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8
+            .load_rom_bytes(&[
+                0xC0, 0xFF, // 0x200: RND V0, 0xFF
+                0xF0, 0x29, // 0x202: LD F, V0
+                0xD1, 0x25, // 0x204: DRW V1, V2, 5
+                0x71, 0x05, // 0x206: ADD V1, 5
+                0x12, 0x00, // 0x208: JP 0x200
+            ])
+            .unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.paused = true;
+        let start = machine.chip8.save_state();
+        let mut states = Vec::new();
+        for _ in 0..12 {
+            machine.step_instruction();
+            states.push(machine.chip8.save_state());
+        }
+
+        for _ in 0..5 {
+            assert!(machine.undo_step());
+        }
+        assert_eq!(machine.chip8.save_state(), states[6]);
+        for _ in 0..5 {
+            machine.step_instruction();
+        }
+        assert_eq!(machine.chip8.save_state(), states[11]);
+
+        while machine.undo_step() {}
+        assert_eq!(machine.chip8.save_state(), start);
+    }
+
+    #[test]
+    fn undoing_steps_goes_back_an_instruction_at_a_time() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.step_instruction();
+        assert_eq!(machine.step_undo.len(), 0, "only while paused");
+
+        machine.paused = true;
+        for _ in 0..STEP_UNDO_DEPTH * 2 + 1 {
+            machine.step_instruction();
+        }
+        assert_eq!(machine.step_undo.len(), STEP_UNDO_DEPTH);
+        assert_eq!(machine.chip8.get_v(0), STEP_UNDO_DEPTH as u8 + 1);
+        assert!(machine.undo_step());
+        assert_eq!(machine.chip8.get_pc(), 0x200);
+        assert_eq!(machine.chip8.get_v(0), STEP_UNDO_DEPTH as u8);
+        assert!(machine.undo_step());
+        assert_eq!(machine.chip8.get_pc(), 0x202);
+        assert_eq!(machine.chip8.get_v(0), STEP_UNDO_DEPTH as u8);
+
+        // Running a frame is as far back as it goes.
+        machine.step_frame();
+        assert!(!machine.undo_step());
+    }
+
+    #[test]
+    fn breakpoints_pause_the_machine() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.set_breakpoint(0x202, Condition::default());
+        let mut machine = Machine::new(chip8);
+        machine.run_frame(FRAME_PERIOD);
+        assert!(machine.paused);
+        assert_eq!(machine.chip8.get_v(0), 1);
+
+        // Stepping carries on from it.
+        machine.step_instruction();
+        machine.step_instruction();
+        assert_eq!(machine.chip8.get_pc(), 0x202);
+        assert_eq!(machine.chip8.get_breakpoint_hit(), None);
+        machine.step_instruction();
+        assert_eq!(machine.chip8.get_breakpoint_hit(), Some(0x202));
+        assert_eq!(machine.chip8.get_v(0), 2);
+    }
+
+    #[test]
+    fn resetting_while_recording_input_ends_the_recording() {
+        // ADD V0, 1; JP 0x200.
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.input_recorder = Some(InputRecorder::start(&mut machine.chip8, 3));
+        machine.run_frame(FRAME_PERIOD);
+        machine.key_down(5);
+        machine.run_frame(FRAME_PERIOD);
+        assert!(machine.interrupted_input.is_none());
+
+        machine.chip8.load_rom_bytes(&rom).unwrap();
+        machine.key_up(5);
+        machine.run_frame(FRAME_PERIOD);
+        assert!(machine.input_recorder.is_none());
+        let log = machine.interrupted_input.take().unwrap();
+        assert_eq!(log.ticks, 2);
+        assert_eq!(log.events.len(), 1, "nothing after the reset is recorded");
+    }
+
+    #[test]
+    fn frozen_cheats_are_left_alone_while_recording_input() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.cheats.push(Cheat {
+            address: 0x300,
+            value: 0x42,
+            enabled: true,
+            frozen: true,
+        });
+        machine.input_recorder = Some(InputRecorder::start(&mut machine.chip8, 3));
+        machine.run_frame(FRAME_PERIOD);
+        assert_eq!(machine.chip8.peek(0x300), 0);
+        assert!(machine.input_recorder.is_some());
+
+        machine.input_recorder = None;
+        machine.run_frame(FRAME_PERIOD);
+        assert_eq!(machine.chip8.peek(0x300), 0x42);
+    }
+
+    #[test]
+    fn the_view_is_kept_as_it_was_while_the_machine_runs_on() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.run_frame(FRAME_PERIOD);
+        let view = machine.view();
+        machine.run_frame(FRAME_PERIOD);
+        assert_eq!(view.frames_run, 1);
+        assert_eq!(machine.frames_run, 2);
+        assert_ne!(view.chip8.get_v(0), machine.chip8.get_v(0));
+    }
+
+    #[test]
+    fn commands_are_all_done_before_the_thread_stops() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.paused = true;
+        let emulator = Emulator::start(machine, mpsc::channel().0);
+        emulator.send(Command::StepInstruction);
+        emulator.send(Command::KeyDown(5));
+        emulator.edit(|machine, _| machine.chip8.poke(0x300, 0x42));
+        emulator.send(Command::Unfocused(true));
+
+        let machine = emulator.stop();
+        assert_eq!(machine.chip8.get_v(0), 1);
+        assert_eq!(machine.chip8.get_key_pressed(), Some(5));
+        assert_eq!(machine.chip8.peek(0x300), 0x42);
+        assert!(machine.paused && machine.unfocused);
+    }
+
+    #[test]
+    fn views_and_events_are_sent_back() {
+        // ADD V0, 1; JP 0x200.
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut machine = Machine::new(chip8);
+        machine.paused = true;
+        let mut emulator = Emulator::start(machine, mpsc::channel().0);
+        emulator.send(Command::StepInstruction);
+        emulator.send(Command::StepOver);
+        emulator.edit(|_, toasts| toasts.push(Toast::info("Edited")));
+
+        assert!(!emulator.is_view_current());
+        let mut events = emulator.receive();
+        let give_up = Instant::now() + Duration::from_secs(5);
+        while emulator.view().step_undo_len < 2 && Instant::now() < give_up {
+            thread::sleep(Duration::from_millis(1));
+            events.extend(emulator.receive());
+        }
+        assert_eq!(emulator.view().chip8.get_pc(), 0x200);
+        assert!(emulator.is_view_current());
+        assert!(emulator.take_display_dirty());
+        match events.as_slice() {
+            [Event::Stopped(Some(StopReason::Reached)), Event::Toast(toast)] => {
+                assert_eq!(toast.message, "Edited");
+            }
+            _ => panic!("unexpected events"),
+        }
+        emulator.stop();
+    }
+
+    #[test]
+    fn pacer_waits_for_next_frame() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(start);
+        let wait = pacer.next(start + Duration::from_millis(5));
+        assert_eq!(wait, FRAME_PERIOD - Duration::from_millis(5));
+        assert_eq!(pacer.frames_skipped, 0);
+    }
+
+    #[test]
+    fn pacer_catches_up_then_skips() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(start);
+        // A little behind, so the next frame is run straight away.
+        let now = start + FRAME_PERIOD * 3;
+        assert_eq!(pacer.next(now), Duration::from_secs(0));
+        assert_eq!(pacer.next(now), Duration::from_secs(0));
+        assert_eq!(pacer.frames_skipped, 0);
+
+        // A second behind is too far, so it starts over from now.
+        let now = start + Duration::from_secs(1) + FRAME_PERIOD * 3;
+        assert_eq!(pacer.next(now), Duration::from_secs(0));
+        assert_eq!(pacer.frames_skipped, 60);
+        assert_eq!(pacer.next(now), FRAME_PERIOD);
+    }
+}
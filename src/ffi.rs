@@ -0,0 +1,171 @@
+//! A C API for the Chip8 core, for embedding it in non-Rust applications. See
+//! examples/c for the matching header and a small example program.
+//!
+//! A machine is created with chipper_new() and must be freed with chipper_free().
+//! Every other function takes the pointer returned by chipper_new(), which must not
+//! be used after it has been freed. Passing a null pointer is always safe and does
+//! nothing.
+
+use crate::chip8::Chip8;
+use std::slice;
+
+/// Creates a new Chip8 with the given RND seed. The caller owns the returned
+/// pointer and must free it with chipper_free().
+#[no_mangle]
+pub extern "C" fn chipper_new(seed: u64) -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8::with_seed(seed)))
+}
+
+/// Frees a Chip8 created by chipper_new().
+///
+/// # Safety
+/// `chip8` must be null or a pointer from chipper_new() that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chipper_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Loads a ROM and resets the machine. The ROM is copied, so the buffer only needs
+/// to live for the duration of the call. Returns 0 on success, or -1 if the
+/// arguments are null or the ROM is too big for the memory of the current variant.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new(), and `rom` must be
+/// null or point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chipper_load_rom(chip8: *mut Chip8, rom: *const u8, len: usize) -> i32 {
+    if chip8.is_null() || rom.is_null() {
+        return -1;
+    }
+    match (*chip8).load_rom_bytes(slice::from_raw_parts(rom, len)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Executes a single instruction. Timers aren't updated.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_step(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.step();
+    }
+}
+
+/// Runs one 60Hz frame worth of instructions and updates the timers.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_run_frame(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.run_frame();
+    }
+}
+
+/// Returns the display, one byte per pixel in row order, and is
+/// chipper_display_width() * chipper_display_height() bytes long. The pointer is
+/// borrowed from the machine: it's only valid until the next call that takes a
+/// mutable `chip8`, and must not be freed. Returns null if `chip8` is null.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_display(chip8: *const Chip8) -> *const u8 {
+    match chip8.as_ref() {
+        Some(chip8) => chip8.get_display_data().as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_display_width(chip8: *const Chip8) -> usize {
+    chip8.as_ref().map_or(0, |chip8| chip8.get_display_width())
+}
+
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_display_height(chip8: *const Chip8) -> usize {
+    chip8.as_ref().map_or(0, |chip8| chip8.get_display_height())
+}
+
+/// Presses `key` (0x0 - 0xF) on the keypad if `pressed` is non-zero, or releases
+/// it otherwise.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_set_key(chip8: *mut Chip8, key: u8, pressed: i32) {
+    if let Some(chip8) = chip8.as_mut() {
+        if pressed != 0 {
+            chip8.set_key_pressed(key);
+        } else {
+            chip8.set_key_released(key);
+        }
+    }
+}
+
+/// Returns 1 while the tone should be playing, or 0 otherwise.
+///
+/// # Safety
+/// `chip8` must be null or a live pointer from chipper_new().
+#[no_mangle]
+pub unsafe extern "C" fn chipper_sound_active(chip8: *const Chip8) -> i32 {
+    chip8
+        .as_ref()
+        .map_or(0, |chip8| chip8.get_sound_active() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        // LD V0, 0; LD F, V0; DRW V0, V0, 5; JP 0x206
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+        unsafe {
+            let chip8 = chipper_new(1);
+            assert_eq!(chipper_load_rom(chip8, rom.as_ptr(), rom.len()), 0);
+            chipper_run_frame(chip8);
+
+            let width = chipper_display_width(chip8);
+            let height = chipper_display_height(chip8);
+            assert_eq!((width, height), (64, 32));
+            let display = slice::from_raw_parts(chipper_display(chip8), width * height);
+            // The top row of the "0" glyph is 0xF0.
+            assert_eq!(&display[..5], &[1, 1, 1, 1, 0]);
+
+            chipper_set_key(chip8, 0xa, 1);
+            chipper_set_key(chip8, 0xa, 0);
+            assert_eq!(chipper_sound_active(chip8), 0);
+            chipper_free(chip8);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let big_rom = vec![0; 0x1000];
+        unsafe {
+            let chip8 = chipper_new(1);
+            assert_eq!(chipper_load_rom(chip8, std::ptr::null(), 0), -1);
+            assert_eq!(chipper_load_rom(chip8, big_rom.as_ptr(), big_rom.len()), -1);
+            chipper_free(chip8);
+
+            assert_eq!(
+                chipper_load_rom(std::ptr::null_mut(), big_rom.as_ptr(), 2),
+                -1
+            );
+            assert!(chipper_display(std::ptr::null()).is_null());
+            chipper_step(std::ptr::null_mut());
+            chipper_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use egui::{Rect, Srgba};
+use std::ptr;
+
+/// Draws the Chip8 display straight to the window by blitting a texture into the
+/// display area with nearest-neighbour scaling. That's a lot less work than going
+/// through egui's texture and tessellation every frame for what's a single quad,
+/// and the pixels stay crisp at any scale.
+///
+/// The blit has to happen after egui has painted, as egui clears the window first.
+/// Anything egui draws over the display area ends up underneath it.
+pub struct GlDisplay {
+    texture: u32,
+    framebuffer: u32,
+    width: i32,
+    height: i32,
+    bytes: Vec<u8>,
+}
+
+impl GlDisplay {
+    /// Creates the texture for a display of the given size. The GL context has to be
+    /// current and the gl functions loaded.
+    pub fn new(width: usize, height: usize) -> GlDisplay {
+        let mut texture = 0;
+        let mut framebuffer = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ptr::null(),
+            );
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            // The blit reads from the texture through a framebuffer.
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+            gl::FramebufferTexture2D(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture,
+                0,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+
+        GlDisplay {
+            texture,
+            framebuffer,
+            width: width as i32,
+            height: height as i32,
+            bytes: Vec::with_capacity(width * height * 4),
+        }
+    }
+
+    /// Uploads the texels, one per pixel in row order.
+    pub fn update(self: &mut Self, texels: &[Srgba]) {
+        self.bytes.clear();
+        for texel in texels {
+            self.bytes.extend_from_slice(&texel.0);
+        }
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                self.bytes.as_ptr() as *const _,
+            );
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    /// Draws the display into rect, which is in egui points like the rest of the UI.
+    /// window_height is the height of the window in the same units as the painter.
+    pub fn draw(self: &Self, rect: Rect, pixels_per_point: f32, window_height: u32) {
+        let left = (rect.left() * pixels_per_point).round() as i32;
+        let right = (rect.right() * pixels_per_point).round() as i32;
+        // GL counts up from the bottom of the window, so the image is flipped over.
+        let top = window_height as i32 - (rect.top() * pixels_per_point).round() as i32;
+        let bottom = window_height as i32 - (rect.bottom() * pixels_per_point).round() as i32;
+
+        unsafe {
+            // egui leaves these on, and they'd clip and color convert the blit.
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::Disable(gl::FRAMEBUFFER_SRGB);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.width,
+                self.height,
+                left,
+                top,
+                right,
+                bottom,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for GlDisplay {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
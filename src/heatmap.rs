@@ -0,0 +1,38 @@
+use chipper::chip8::{Access, AccessCounts};
+use egui::{color, Srgba};
+
+/// The heatmap shows the standard 4k of memory as a 64x64 grid, one texel per
+/// address in row order.
+pub const HEATMAP_WIDTH: usize = 64;
+pub const HEATMAP_HEIGHT: usize = 64;
+
+/// Converts the access counts to texels for the heatmap texture. Each kind of access
+/// has its own color channel - red for writes, green for execution and blue for
+/// reads - so addresses that are accessed in more than one way show up as a mix.
+/// Brightness is on a log scale relative to the busiest address, as a program's
+/// main loop would otherwise drown out everything else.
+pub fn heatmap_texels(counts: &AccessCounts) -> Vec<Srgba> {
+    let size = HEATMAP_WIDTH * HEATMAP_HEIGHT;
+    let intensities: Vec<Vec<u8>> = [Access::Write, Access::Execute, Access::Read]
+        .iter()
+        .map(|access| {
+            let counts = &counts.get(*access)[..size];
+            let max = (*counts.iter().max().unwrap_or(&0) as f32).ln_1p();
+            counts
+                .iter()
+                .map(|&c| {
+                    if c == 0 {
+                        0
+                    } else {
+                        // Anything that's been touched at all should be visible.
+                        (64f32 + 191f32 * (c as f32).ln_1p() / max) as u8
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    (0..size)
+        .map(|a| color::srgba(intensities[0][a], intensities[1][a], intensities[2][a], 255))
+        .collect()
+}
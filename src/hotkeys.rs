@@ -0,0 +1,224 @@
+use crate::keymap::Layout;
+use sdl2::keyboard::Keycode::{self, *};
+
+/// Everything that can be done from the keyboard, apart from the keypad.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Pause,
+    Reset,
+    StepFrame,
+    StepOver,
+    Rewind,
+    PreviousRom,
+    NextRom,
+    TestRom,
+    SaveState,
+    LoadState,
+    RecordGif,
+    DisplayOnly,
+    ShowKeypad,
+    Mute,
+    Help,
+}
+
+/// What the shortcuts are grouped under in the help.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+    Emulation,
+    Roms,
+    States,
+    Display,
+    Sound,
+}
+
+pub const CATEGORIES: [Category; 5] = [
+    Category::Emulation,
+    Category::Roms,
+    Category::States,
+    Category::Display,
+    Category::Sound,
+];
+
+impl Category {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Category::Emulation => "Emulation",
+            Category::Roms => "ROMs",
+            Category::States => "Save states",
+            Category::Display => "Display",
+            Category::Sound => "Sound",
+        }
+    }
+}
+
+/// The keys for an action, and what it does.
+pub struct Binding {
+    pub action: Action,
+    pub keys: &'static [Keycode],
+    pub category: Category,
+    pub description: &'static str,
+}
+
+/// Every shortcut. The input handling and the help both go by this, so the help
+/// can't go out of date.
+pub const BINDINGS: [Binding; 15] = [
+    Binding {
+        action: Action::Pause,
+        keys: &[Escape],
+        category: Category::Emulation,
+        description: "Pause menu",
+    },
+    Binding {
+        action: Action::Reset,
+        keys: &[F2],
+        category: Category::Emulation,
+        description: "Reset",
+    },
+    Binding {
+        action: Action::StepFrame,
+        keys: &[F6],
+        category: Category::Emulation,
+        description: "Step a frame while paused",
+    },
+    Binding {
+        action: Action::StepOver,
+        keys: &[F10],
+        category: Category::Emulation,
+        description: "Step over a CALL while paused",
+    },
+    Binding {
+        action: Action::Rewind,
+        keys: &[Backspace],
+        category: Category::Emulation,
+        description: "Rewind, while held",
+    },
+    Binding {
+        action: Action::PreviousRom,
+        keys: &[PageUp],
+        category: Category::Roms,
+        description: "Previous ROM",
+    },
+    Binding {
+        action: Action::NextRom,
+        keys: &[PageDown],
+        category: Category::Roms,
+        description: "Next ROM",
+    },
+    Binding {
+        action: Action::TestRom,
+        keys: &[F12],
+        category: Category::Roms,
+        description: "Run the diagnostic ROM",
+    },
+    Binding {
+        action: Action::SaveState,
+        keys: &[F5],
+        category: Category::States,
+        description: "Save state",
+    },
+    Binding {
+        action: Action::LoadState,
+        keys: &[F8],
+        category: Category::States,
+        description: "Load state",
+    },
+    Binding {
+        action: Action::RecordGif,
+        keys: &[F9],
+        category: Category::Display,
+        description: "Start/stop recording a GIF",
+    },
+    Binding {
+        action: Action::DisplayOnly,
+        keys: &[Tab],
+        category: Category::Display,
+        description: "Display only",
+    },
+    Binding {
+        action: Action::ShowKeypad,
+        keys: &[F3],
+        category: Category::Display,
+        description: "Show the keypad, while held",
+    },
+    Binding {
+        action: Action::Mute,
+        keys: &[M],
+        category: Category::Sound,
+        description: "Mute",
+    },
+    Binding {
+        action: Action::Help,
+        keys: &[F1, Slash],
+        category: Category::Display,
+        description: "Show these shortcuts",
+    },
+];
+
+impl Binding {
+    /// The keys that work with the keyboard layout. The keypad comes first, so a
+    /// key it uses can't also be a shortcut.
+    pub fn active_keys(self: &Self, layout: Layout) -> Vec<Keycode> {
+        self.keys
+            .iter()
+            .copied()
+            .filter(|&key| layout.chip8_key(key).is_none())
+            .collect()
+    }
+
+    /// The names of the active keys, such as "F1 or /".
+    pub fn key_names(self: &Self, layout: Layout) -> String {
+        let names: Vec<String> = self
+            .active_keys(layout)
+            .iter()
+            .map(|key| key.name())
+            .collect();
+        names.join(" or ")
+    }
+}
+
+/// Returns the action for a key, if it's a shortcut with the keyboard layout.
+pub fn action(keycode: Keycode, layout: Layout) -> Option<Action> {
+    BINDINGS
+        .iter()
+        .find(|binding| binding.active_keys(layout).contains(&keycode))
+        .map(|binding| binding.action)
+}
+
+/// Returns the shortcuts in a category that have a key with the keyboard layout.
+pub fn active_bindings(category: Category, layout: Layout) -> Vec<&'static Binding> {
+    BINDINGS
+        .iter()
+        .filter(|binding| binding.category == category)
+        .filter(|binding| !binding.active_keys(layout).is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::LAYOUTS;
+
+    #[test]
+    fn keys_are_only_bound_once() {
+        for (i, binding) in BINDINGS.iter().enumerate() {
+            for other in &BINDINGS[i + 1..] {
+                for key in binding.keys {
+                    assert!(!other.keys.contains(key), "{}", key.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keypad_takes_precedence() {
+        assert_eq!(action(F1, Layout::Qwerty), Some(Action::Help));
+        assert_eq!(action(M, Layout::Qwerty), Some(Action::Mute));
+        assert_eq!(action(Num1, Layout::Qwerty), None);
+        // Every category has something in it, whatever the layout.
+        for &layout in &LAYOUTS {
+            for &category in &CATEGORIES {
+                assert!(!active_bindings(category, layout).is_empty());
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use sdl2::keyboard::Keycode::{self, *};
+use serde::{Deserialize, Serialize};
+
+/// The Chip8 keypad, row by row as it's laid out on the COSMAC VIP:
+///
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// How the keyboard maps onto the keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    /// Each key is the key with the same hex digit, 0-9 and A-F.
+    Hex,
+    /// The keypad is the 4x4 block of keys on the left of the keyboard, which is
+    /// 1234/QWER/ASDF/ZXCV on a QWERTY keyboard.
+    Qwerty,
+    Azerty,
+    Dvorak,
+}
+
+pub const LAYOUTS: [Layout; 4] = [Layout::Qwerty, Layout::Azerty, Layout::Dvorak, Layout::Hex];
+
+impl Layout {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Layout::Hex => "Hex (0-9, A-F)",
+            Layout::Qwerty => "QWERTY",
+            Layout::Azerty => "AZERTY",
+            Layout::Dvorak => "Dvorak",
+        }
+    }
+
+    // The keys for each keypad key, in the same order as KEYPAD.
+    fn keys(self: &Self) -> [Keycode; 16] {
+        match self {
+            Layout::Hex => [
+                Num1, Num2, Num3, C, Num4, Num5, Num6, D, Num7, Num8, Num9, E, A, Num0, B, F,
+            ],
+            Layout::Qwerty => [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Z, X, C, V],
+            Layout::Azerty => [Num1, Num2, Num3, Num4, A, Z, E, R, Q, S, D, F, W, X, C, V],
+            Layout::Dvorak => [
+                Num1, Num2, Num3, Num4, Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K,
+            ],
+        }
+    }
+
+    /// Returns the keypad key for a keyboard key, or None if it isn't part of the
+    /// keypad.
+    pub fn chip8_key(self: &Self, keycode: Keycode) -> Option<u8> {
+        self.keys()
+            .iter()
+            .position(|&key| key == keycode)
+            .map(|index| KEYPAD[index])
+    }
+
+    /// Returns the keyboard key for a keypad key, for showing in the UI.
+    pub fn keyboard_key(self: &Self, chip8_key: u8) -> Keycode {
+        let index = KEYPAD
+            .iter()
+            .position(|&key| key == chip8_key & 0xf)
+            .unwrap();
+        self.keys()[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_layout_covers_the_keypad() {
+        for layout in &LAYOUTS {
+            for key in 0..16 {
+                let keycode = layout.keyboard_key(key);
+                assert_eq!(layout.chip8_key(keycode), Some(key), "{}", layout.name());
+            }
+        }
+    }
+
+    #[test]
+    fn qwerty_uses_the_left_block() {
+        assert_eq!(Layout::Qwerty.chip8_key(Num4), Some(0xC));
+        assert_eq!(Layout::Qwerty.chip8_key(Q), Some(0x4));
+        assert_eq!(Layout::Qwerty.chip8_key(X), Some(0x0));
+        assert_eq!(Layout::Qwerty.chip8_key(V), Some(0xF));
+        assert_eq!(Layout::Qwerty.chip8_key(Num5), None);
+        assert_eq!(Layout::Hex.chip8_key(Num5), Some(0x5));
+        assert_eq!(Layout::Hex.chip8_key(Q), None);
+    }
+}
@@ -0,0 +1,12 @@
+//! The Chipper emulator core. It has no dependencies on SDL2 or egui, so that it can
+//! be embedded elsewhere, such as in the browser via the `wasm` feature or from C via
+//! the `cdylib` feature.
+
+pub mod chip8;
+pub mod snapshot;
+
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
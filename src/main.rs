@@ -3,81 +3,565 @@
 
 extern crate gl;
 
-use chip8::Chip8;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode::*;
-use sdl2::video::GLProfile;
+use chipper::chip8::{self, Breakpoint, Chip8, FaultKind, InputLog, StopReason, Variant};
+use config::{Config, Overlay};
+use emulator::{Command, Emulator, Machine, MachineView, RUN_TO_MAX_STEPS};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::video::{GLProfile, Window};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
-use std::{collections::HashMap, fs, io, path::PathBuf};
-mod chip8;
-use egui::{color, combo_box_with_label, vec2, Image, Pos2, Rect, Srgba};
-
-// Helper function to get all valid Chip8 ROM Files in the "roms"
-// directory. The dictionary maps a filename to a file path.
-fn get_roms(dir: &str) -> io::Result<HashMap<String, String>> {
-    let mut files: HashMap<String, String> = HashMap::new();
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let data = entry.metadata()?;
-        let path = entry.path();
-        let file_name = entry.file_name().into_string().unwrap();
-        if data.is_file() {
-            if let Some(ex) = path.extension() {
-                if ex == "ch8" {
-                    //println!("Found rom: {}", file_name);
-                    files.insert(file_name, path.display().to_string());
-                }
+use watcher::RomWatcher;
+mod args;
+mod audio;
+mod cheats;
+mod config;
+mod conformance;
+mod coverage;
+mod crt;
+mod emulator;
+mod gl_display;
+mod heatmap;
+mod hotkeys;
+mod keymap;
+mod metadata;
+mod palette;
+mod panels;
+mod pause_menu;
+mod recorder;
+mod rewind;
+mod roms;
+mod scanner;
+mod states;
+mod stats;
+mod toasts;
+mod trace;
+mod watcher;
+use crt::{CRT_HEIGHT, CRT_WIDTH};
+use egui::{color, vec2, Pos2, Rect, Srgba, TextureId};
+use gl_display::GlDisplay;
+use heatmap::{HEATMAP_HEIGHT, HEATMAP_WIDTH};
+use hotkeys::Action;
+use metadata::RomMetadata;
+use panels::{Frontend, Meters, PanelState, Requests};
+use pause_menu::{Item, PauseMenu};
+use stats::{FrameTimes, RateMeter};
+use toasts::{Toast, ToastQueue};
+use trace::{Trace, TraceFormat, TRACE_MAX_LINES};
+
+// The size of the Chip8 display. Hi-res is exactly double, so this is what the
+// display is scaled up from.
+const CHIP8_DISPLAY_WIDTH: u32 = 64;
+const CHIP8_DISPLAY_HEIGHT: u32 = 32;
+
+// The width of the controls panel and the space around the display.
+const CONTROLS_WIDTH: u32 = 300;
+const DISPLAY_MARGIN: u32 = 16;
+
+// The window is never made shorter than this by the scale setting, so that the
+// controls still fit.
+const MIN_WINDOW_HEIGHT: u32 = 520;
+
+//The texture is sized for the hi-res display. Lo-res pixels are doubled up.
+const TEXTURE_WIDTH: usize = 128;
+const TEXTURE_HEIGHT: usize = 64;
+
+//The colors for highlighting the pixels that changed in a frame, which should stand
+//out against any palette.
+const PIXEL_SET_COLOR: Srgba = color::srgba(0, 255, 64, 255);
+const PIXEL_CLEARED_COLOR: Srgba = color::srgba(255, 0, 128, 255);
+
+// Name of the boot ROM. If it isn't in the ROM directory the built-in copy is used.
+const BOOT_ROM_NAME: &str = "ChipperBoot.ch8";
+
+// Name of the diagnostic ROM, which is built in the same way.
+const TEST_ROM_NAME: &str = "ChipperTest.ch8";
+
+// What a piped in ROM is called in the ROM list.
+const STDIN_ROM_NAME: &str = "<stdin>";
+
+// Input recordings are saved here, relative to the working directory.
+const INPUT_DIR: &str = "./inputs";
+
+// The frame time graph covers this many frames, which is two seconds at 60Hz.
+const FRAME_TIME_SAMPLES: usize = 120;
+
+// Helper function to scan the ROM directory. A directory that can't be read is
+// reported in the UI and treated as empty, as the built-in boot ROM still works.
+fn scan_roms(dir: &str, toasts: &mut ToastQueue) -> BTreeMap<String, String> {
+    match roms::get_roms(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            toasts.push(Toast::error(format!(
+                "Couldn't read the ROM folder {}: {}",
+                dir, e
+            )));
+            BTreeMap::new()
+        }
+    }
+}
+
+// Helper function to read a ROM from stdin into a temporary file. It's loaded to
+// check it, so that the error is given before the window opens.
+fn read_piped_rom(chip8: &mut Chip8) -> io::Result<PathBuf> {
+    let rom = args::read_rom(io::stdin(), chip8.get_memory_size() - 0x200)?;
+    chip8.load_rom_bytes(&rom)?;
+    let path = std::env::temp_dir().join(format!("chipper-stdin-{}.ch8", std::process::id()));
+    fs::write(&path, &rom)?;
+    Ok(path)
+}
+
+// Helper function to tell whether a ROM is one of the built-in ones. They end by
+// jumping to themselves, and there's nothing to resume in them.
+fn is_built_in_rom(rom_name: &str) -> bool {
+    rom_name == BOOT_ROM_NAME || rom_name == TEST_ROM_NAME
+}
+
+// Helper function to boot a ROM from the ROM directory, or the built-in boot or test
+// ROM if it's not there. Failures are reported in the UI and leave the current program
+// running. Returns the variant the ROM looks like it was written for, if that's an
+// extension of the currently active one. The quirks and clock speed recommended by
+// the ROM's metadata are set, and otherwise the quirks are the ones in the config.
+fn boot_rom(
+    chip8: &mut Chip8,
+    config: &Config,
+    path: Option<&str>,
+    rom_name: &str,
+    toasts: &mut ToastQueue,
+) -> Option<Variant> {
+    let result = match path {
+        Some(path) => chip8.boot_rom(path),
+        None if rom_name == BOOT_ROM_NAME => chip8.load_rom_bytes(chip8::BOOT_ROM),
+        None if rom_name == TEST_ROM_NAME => chip8.load_rom_bytes(chip8::TEST_ROM),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "it's no longer in the ROM folder",
+        )),
+    };
+    if let Err(e) = result {
+        toasts.push(Toast::error(format!("Failed to load {}: {}", rom_name, e)));
+        return None;
+    }
+    config.apply(chip8);
+    //Saved breakpoints are for this ROM only, so they replace any that were set.
+    if config.save_breakpoints {
+        chip8.clear_all_breakpoints();
+        for text in config.breakpoints.get(rom_name).into_iter().flatten() {
+            match Breakpoint::parse(text) {
+                Ok(breakpoint) => chip8.set_breakpoint(breakpoint.address, breakpoint.condition),
+                Err(e) => println!("Skipped saved breakpoint {}: {}", text, e),
+            }
+        }
+    }
+    if let Some(metadata) = path.and_then(metadata::load) {
+        metadata.apply(chip8);
+    }
+
+    let detected = chip8.detect_variant();
+    if detected > chip8.get_variant() {
+        Some(detected)
+    } else {
+        None
+    }
+}
+
+// Helper function to boot a ROM on the emulation thread, with boot_rom(). The
+// variant it suggests comes back as an emulator::Event::Loaded.
+fn load_rom(
+    emulator: &Emulator,
+    config: &Config,
+    rom_files: &BTreeMap<String, String>,
+    rom_name: &str,
+) {
+    let config = config.clone();
+    let path = rom_files.get(rom_name).cloned();
+    let rom_name = rom_name.to_string();
+    emulator.send(Command::Load(Box::new(move |chip8, toasts| {
+        boot_rom(chip8, &config, path.as_deref(), &rom_name, toasts)
+    })));
+}
+
+// How long the hint for getting the controls back is shown in display only mode.
+const DISPLAY_ONLY_HINT_TIME: Duration = Duration::from_secs(3);
+
+// While paused, the UI is only updated this often unless something happens, to save
+// CPU. It keeps to the refresh rate for a while after any input, so that the UI's
+// animations and tooltips aren't held up.
+const IDLE_WAIT_MS: u32 = 250;
+const IDLE_AFTER: Duration = Duration::from_secs(1);
+
+// The textures that are drawn into every frame. They belong to the painter.
+struct Textures {
+    display: TextureId,
+    crt: TextureId,
+    heatmap: TextureId,
+}
+
+// Helper function to create the painter and the textures that are drawn into.
+// The painter can't be resized, so this is also called when the window is. The
+// display starts out blank, in the pixel off color.
+fn create_painter(
+    video_subsystem: &sdl2::VideoSubsystem,
+    width: u32,
+    height: u32,
+    pixel_off: Srgba,
+) -> (egui_sdl::Painter, Textures) {
+    let mut painter = egui_sdl::Painter::new(video_subsystem, width, height);
+    let srgba = vec![pixel_off; TEXTURE_WIDTH * TEXTURE_HEIGHT];
+    let display = painter.new_user_texture((TEXTURE_WIDTH, TEXTURE_HEIGHT), &srgba, false);
+    let srgba = vec![color::BLACK; HEATMAP_WIDTH * HEATMAP_HEIGHT];
+    let heatmap = painter.new_user_texture((HEATMAP_WIDTH, HEATMAP_HEIGHT), &srgba, false);
+    //The CRT texture is smoothed, as otherwise the scanlines flicker in and out at
+    //scales that don't divide evenly.
+    let srgba = vec![pixel_off; CRT_WIDTH * CRT_HEIGHT];
+    let crt = painter.new_user_texture((CRT_WIDTH, CRT_HEIGHT), &srgba, true);
+    (
+        painter,
+        Textures {
+            display,
+            crt,
+            heatmap,
+        },
+    )
+}
+
+// Helper function to convert the display to texels for the display texture, with a
+// color for each pixel value. The texels replace the contents of the buffer.
+// Given the display as it was before, the pixels that have been set since are
+// highlighted in one color and the ones that have been cleared in another.
+fn display_texels(
+    texels: &mut Vec<Srgba>,
+    chip8: &Chip8,
+    colors: &[Srgba; 4],
+    previous: Option<&[u8]>,
+) {
+    let display = chip8.get_display_data();
+    let width = chip8.get_display_width();
+    let height = chip8.get_display_height();
+    //Nothing's highlighted after switching between lores and hires.
+    let previous = previous.filter(|previous| previous.len() == display.len());
+    texels.clear();
+    for y in 0..TEXTURE_HEIGHT {
+        for x in 0..TEXTURE_WIDTH {
+            let i = (y * height / TEXTURE_HEIGHT) * width + x * width / TEXTURE_WIDTH;
+            let pixel = display[i];
+            let texel = match previous.map(|previous| previous[i]) {
+                Some(before) if before != pixel && pixel == 0 => PIXEL_CLEARED_COLOR,
+                Some(before) if before != pixel => PIXEL_SET_COLOR,
+                _ => colors[pixel as usize & 3],
+            };
+            texels.push(texel);
+        }
+    }
+}
+
+// Helper function to convert a color from the config to an opaque egui color.
+fn to_srgba(rgb: [u8; 3]) -> Srgba {
+    color::srgba(rgb[0], rgb[1], rgb[2], 255)
+}
+
+// Helper function to work out the window size that exactly fits the display at the
+// given scale next to the controls.
+fn window_size(scale: u32) -> (u32, u32) {
+    (
+        CONTROLS_WIDTH + CHIP8_DISPLAY_WIDTH * scale + DISPLAY_MARGIN,
+        (CHIP8_DISPLAY_HEIGHT * scale + DISPLAY_MARGIN).max(MIN_WINDOW_HEIGHT),
+    )
+}
+
+// Helper function to work out egui's pixels per point for the display the window is
+// on. If the display's DPI isn't known, which happens on some Windows setups, it's
+// worked out from how the window's drawable size compares with its size instead,
+// the same way round.
+fn window_pixels_per_point(video_subsystem: &sdl2::VideoSubsystem, window: &Window) -> f32 {
+    let dpi = window
+        .display_index()
+        .and_then(|index| video_subsystem.display_dpi(index));
+    match dpi {
+        Ok((dpi, _, _)) if dpi > 0f32 => 96f32 / dpi,
+        _ => {
+            let (width, _) = window.size();
+            let (drawable_width, _) = window.drawable_size();
+            if width > 0 && drawable_width > 0 {
+                width as f32 / drawable_width as f32
+            } else {
+                1f32
             }
         }
     }
-    Ok(files)
 }
 
-// Helper function to convert a SDL2 keycode to a Chip8 key.
-fn keycode_to_chip8_key(keycode: &sdl2::keyboard::Keycode) -> u8 {
-    let key: u8;
-    match keycode {
-        Num0 => key = 0,
-        Num1 => key = 1,
-        Num2 => key = 2,
-        Num3 => key = 3,
-        Num4 => key = 4,
-        Num5 => key = 5,
-        Num6 => key = 6,
-        Num7 => key = 7,
-        Num8 => key = 8,
-        Num9 => key = 9,
-        A => key = 0xa,
-        B => key = 0xb,
-        C => key = 0xc,
-        D => key = 0xd,
-        E => key = 0xe,
-        F => key = 0xf,
-        _ => key = 0xff,
+// Helper function to say why the debugger stopped running the program, unless it's
+// shown some other way already, as faults and breakpoints are.
+fn report_stop(reason: Option<StopReason>, toasts: &mut ToastQueue) {
+    match reason {
+        Some(StopReason::OutOfSteps) => toasts.push(Toast::warning(format!(
+            "Gave up after {} instructions",
+            RUN_TO_MAX_STEPS
+        ))),
+        Some(StopReason::Halted) => toasts.push(Toast::info("The program exited")),
+        None => toasts.push(Toast::warning(
+            "The debugger can't run while input is being recorded or played back",
+        )),
+        _ => {}
+    }
+}
+
+// Helper function to save the config. A failure isn't worth stopping the emulator for.
+fn save_config(config: &Config) {
+    if let Err(e) = config.save() {
+        println!("Failed to save config: {}", e);
+    }
+}
+
+// Helper function to put the settings that live in the Chip8 on to the machine, as
+// soon as they're changed.
+fn apply_config(emulator: &Emulator, config: &Config) {
+    emulator.send(Command::Settings(Box::new(config.clone())));
+}
+
+// Helper function to get the input recording file for a ROM. Like memory dumps,
+// there's one per ROM.
+fn input_log_path(rom_name: &str) -> String {
+    let stem = Path::new(rom_name)
+        .file_stem()
+        .map_or("input".into(), |s| s.to_string_lossy());
+    format!("{}/{}.inp", INPUT_DIR, stem)
+}
+
+// Helper function to save an input recording once it's finished.
+fn save_input_log(log: &InputLog, rom_name: &str, toasts: &mut ToastQueue) {
+    let path = input_log_path(rom_name);
+    let result = fs::create_dir_all(INPUT_DIR).and_then(|()| fs::write(&path, log.to_bytes()));
+    toasts.push(match result {
+        Ok(()) => Toast::info(format!("Saved input recording to {}", path)),
+        Err(e) => Toast::error(format!("Failed to save input recording: {}", e)),
+    });
+}
+
+// Helper functions to save and load the active save state slot, saying how it went.
+fn save_state(chip8: &Chip8, slot: usize, toasts: &mut ToastQueue) {
+    toasts.push(match states::save(chip8, slot) {
+        Ok(()) => Toast::info(format!("State saved to slot {}", slot)),
+        Err(e) => Toast::error(format!("Failed to save state: {}", e)),
+    });
+}
+
+fn load_state(chip8: &mut Chip8, slot: usize, toasts: &mut ToastQueue) {
+    toasts.push(match states::load(chip8, slot) {
+        Ok(()) => Toast::info(format!("State loaded from slot {}", slot)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Toast::warning(format!("Slot {} is empty", slot))
+        }
+        Err(e) => Toast::error(format!("Failed to load state: {}", e)),
+    });
+}
+
+// Helper function to start or stop recording a GIF. Only one recording can be
+// made at a time, so this stops the current one if there is one.
+fn toggle_recording(
+    recording: &mut Option<recorder::Recording>,
+    config: &Config,
+    rom_name: &str,
+    toasts: &mut ToastQueue,
+) {
+    let toast = match recording.take() {
+        Some(r) => match r.finish() {
+            Ok(path) => Toast::info(format!("Saved recording to {}", path.display())),
+            Err(e) => Toast::error(format!("Failed to save recording: {}", e)),
+        },
+        None => match recorder::Recording::start(
+            rom_name,
+            config.recording_scale as usize,
+            config.display_colors(),
+        ) {
+            Ok(r) => {
+                *recording = Some(r);
+                Toast::info("Recording started")
+            }
+            Err(e) => Toast::error(format!("Failed to start recording: {}", e)),
+        },
+    };
+    toasts.push(toast);
+}
+
+// Helper function to start tracing the instructions to a file, or to stop and save
+// the trace.
+fn toggle_trace(
+    trace: &mut Option<Trace>,
+    chip8: &mut Chip8,
+    format: TraceFormat,
+    rom_name: &str,
+    toasts: &mut ToastQueue,
+) {
+    let toast = match trace.take() {
+        Some(t) => {
+            t.write(chip8.take_trace());
+            chip8.set_tracing(false);
+            match t.finish() {
+                Ok(path) => Toast::info(format!("Saved trace to {}", path.display())),
+                Err(e) => Toast::error(format!("Failed to save trace: {}", e)),
+            }
+        }
+        None => match Trace::start(rom_name, format) {
+            Ok(t) => {
+                chip8.set_tracing(true);
+                *trace = Some(t);
+                Toast::info("Tracing started")
+            }
+            Err(e) => Toast::error(format!("Failed to start tracing: {}", e)),
+        },
+    };
+    toasts.push(toast);
+}
+
+// Helper function to build the window title from the ROM name and emulator state.
+fn window_title(rom_name: &str, is_paused: bool, is_halted: bool) -> String {
+    let state = if is_paused {
+        " (paused)"
+    } else if is_halted {
+        " (exited)"
+    } else {
+        ""
     };
-    key
+    format!("Chipper \u{2014} {}{}", rom_name, state)
 }
 
 pub fn main() {
-    const CHIP8_DISPLAY_WIDTH: u32 = 64;
-    const CHIP8_DISPLAY_HEIGHT: u32 = 32;
-    const DISPLAY_SCALE: u32 = 8;
-    const WINDOW_WIDTH: u32 = CHIP8_DISPLAY_WIDTH * DISPLAY_SCALE + 8;
-    const WINDOW_HEIGHT: u32 = 420;
+    //chipper compare runs without opening a window.
+    if std::env::args().nth(1).as_deref() == Some("compare") {
+        let compare = match args::parse_compare(std::env::args().skip(2)) {
+            Ok(compare) => compare,
+            Err(e) => {
+                args::show_message(&format!("{}\n\n{}", e, args::USAGE), true);
+                std::process::exit(2);
+            }
+        };
+        match compare.run() {
+            Ok(divergence) => {
+                args::show_message(&compare.describe(divergence), false);
+                std::process::exit(divergence.is_some() as i32);
+            }
+            Err(e) => {
+                args::show_message(&e, true);
+                std::process::exit(1);
+            }
+        }
+    }
+    //So does chipper check, for running test ROMs from CI.
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        let check = match args::parse_check(std::env::args().skip(2)) {
+            Ok(check) => check,
+            Err(e) => {
+                args::show_message(&format!("{}\n\n{}", e, args::USAGE), true);
+                std::process::exit(2);
+            }
+        };
+        match conformance::check(&check.manifest, check.rom.as_deref()) {
+            Ok((report, passed)) => {
+                args::show_message(&report, !passed);
+                std::process::exit(!passed as i32);
+            }
+            Err(e) => {
+                args::show_message(&e, true);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    //Command line arguments override the settings, but only for this session.
+    let args = match args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            args::show_message(&format!("{}\n\n{}", e, args::USAGE), true);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        args::show_message(args::USAGE, false);
+        return;
+    }
+    if let Some(path) = args.rom.as_ref().filter(|path| !Path::new(path).is_file()) {
+        args::show_message(&format!("Can't find the ROM {}", path), true);
+        std::process::exit(1);
+    }
 
-    let rom_path = PathBuf::from("./roms");
-    let rom_files = get_roms(&rom_path.display().to_string()).unwrap();
-    let mut selected_rom = "ChipperBoot.ch8";
+    let mut config = Config::load();
+    let mut chip8 = Chip8::new();
+    args.apply(&mut config, &mut chip8);
+    config.apply(&mut chip8);
+    states::load_flags(&mut chip8);
+    //A piped in ROM is kept in a temporary file, so it can be reset like any other.
+    let piped_rom = if args.stdin {
+        match read_piped_rom(&mut chip8) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                args::show_message(&format!("Failed to read the ROM from stdin: {}", e), true);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let (window_width, window_height) = window_size(config.display_scale);
+    //Errors are shown as toasts over the display rather than stopping the emulator.
+    let mut toasts = ToastQueue::default();
+    let mut rom_dir = config.rom_dir.clone();
+    let mut rom_files = scan_roms(&rom_dir, &mut toasts);
+    //Resuming the last session starts with the ROM that was running at exit, if it's
+    //still in the ROM folder, rather than the power-on ROM.
+    let last_rom = if config.resume_last_session {
+        roms::power_on_rom(&rom_files, &config.last_rom).map(str::to_string)
+    } else {
+        None
+    };
+    let power_on_rom = roms::power_on_rom(&rom_files, &config.power_on_rom).map(str::to_string);
+    let mut selected_rom = match last_rom.clone().or(power_on_rom) {
+        Some(name) => name,
+        None => {
+            if !config.power_on_rom.is_empty() {
+                toasts.push(Toast::warning(format!(
+                    "The power-on ROM {} isn't in the ROM folder",
+                    config.power_on_rom
+                )));
+            }
+            BOOT_ROM_NAME.to_string()
+        }
+    };
+    //A ROM given on the command line is run from where it is, as if it were in the
+    //ROM folder.
+    if let Some(path) = &args.rom {
+        selected_rom = Path::new(path)
+            .file_name()
+            .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+        rom_files.insert(selected_rom.clone(), path.clone());
+    }
+    if let Some(path) = &piped_rom {
+        selected_rom = STDIN_ROM_NAME.to_string();
+        rom_files.insert(selected_rom.clone(), path.to_string_lossy().into_owned());
+    }
+    if args.test_rom {
+        selected_rom = TEST_ROM_NAME.to_string();
+    }
+    //Set until the last session's ROM is loaded, unless another ROM was asked for.
+    let mut resuming_session = last_rom.as_ref() == Some(&selected_rom);
 
     //for (filename, _path) in &rom_files {
     //    selected_rom = filename;
     //    break;
     //}
 
-    let mut chip8 = Chip8::new();
-    chip8
-        .boot_rom(rom_files.get(selected_rom).expect("No rom files to load!"))
-        .expect("Failed to load rom!");
+    let mut suggested_variant = boot_rom(
+        &mut chip8,
+        &config,
+        rom_files.get(&selected_rom).map(String::as_str),
+        &selected_rom,
+        &mut toasts,
+    );
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -87,23 +571,64 @@ pub fn main() {
     // OpenGL 3.2 is the minimum that we will support.
     gl_attr.set_context_version(3, 2);
 
-    let window = video_subsystem
-        .window(
-            "Chipper - Chip8 Emulator in Rust",
-            WINDOW_WIDTH,
-            WINDOW_HEIGHT,
-        )
-        .position_centered()
-        //.resizable()
-        .opengl()
-        .build()
-        .unwrap();
+    let mut window_builder = video_subsystem.window(
+        "Chipper - Chip8 Emulator in Rust",
+        window_width,
+        window_height,
+    );
+    window_builder.position_centered().resizable().opengl();
+    if args.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let mut window = window_builder.build().unwrap();
 
     let _ctx = window.gl_create_context().unwrap();
+    //egui_sdl loads its own copy of the gl functions, so load ours for the display.
+    gl::load_with(|name| video_subsystem.gl_get_proc_address(name) as *const _);
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    //Carry on without sound if there's no audio device available. The emulation
+    //thread tells the beeper when to play.
+    let (sound, sound_events) = mpsc::channel();
+    let mut beeper = match audio::Beeper::new(&sdl_context, sound_events) {
+        Ok(beeper) => Some(beeper),
+        Err(e) => {
+            println!("Failed to open audio device: {}", e);
+            None
+        }
+    };
+
+    //Tracing from the command line starts before the first instruction. What's run
+    //before the trace is handed to the machine is kept until then.
+    let mut trace_format = args.trace.unwrap_or(TraceFormat::Text);
+    let mut startup_trace = None;
+    if args.trace.is_some() {
+        toggle_trace(
+            &mut startup_trace,
+            &mut chip8,
+            trace_format,
+            &selected_rom,
+            &mut toasts,
+        );
+    }
+
+    //From here on the chip8 runs on its own thread, and is changed by sending it
+    //commands.
+    let mut machine = Machine::new(chip8);
+    machine.paused = args.paused;
+    machine.trace = startup_trace;
+    machine
+        .rewind
+        .set_capacity(config.rewind_seconds as usize * 60);
+    let mut emulator = Emulator::start(machine, sound);
+
     //Egui related stuff
-    let mut painter = egui_sdl::Painter::new(&video_subsystem, WINDOW_WIDTH, WINDOW_HEIGHT);
+    let (mut painter, mut textures) = create_painter(
+        &video_subsystem,
+        window_width,
+        window_height,
+        to_srgba(config.pixel_off_color),
+    );
 
     //Try for Adaptive VSync, for smoother rendering. Or set it to 1 for normal VSync.
     //Setting it to 0 allows for unlocked rendering.
@@ -113,7 +638,7 @@ pub fn main() {
     }
 
     let mut egui_ctx = egui::CtxRef::default();
-    let pixels_per_point = 96f32 / video_subsystem.display_dpi(0).unwrap().0;
+    let mut pixels_per_point = window_pixels_per_point(&video_subsystem, &window);
     let (width, height) = window.size();
     let mut raw_input = egui::RawInput {
         screen_rect: Some(Rect::from_min_size(
@@ -127,33 +652,68 @@ pub fn main() {
     //End of egui related stuff
 
     let start_time = Instant::now();
-    let mut srgba: Vec<Srgba> = Vec::new();
-
-    let chip8_display = chip8.get_display_data();
-    for y in 0..CHIP8_DISPLAY_HEIGHT as usize {
-        for x in 0..CHIP8_DISPLAY_WIDTH as usize {
-            let pixel = chip8_display[y * (CHIP8_DISPLAY_WIDTH as usize) + x];
-            let c = if pixel > 0 {
-                color::LIGHT_GRAY
-            } else {
-                color::BLACK
-            };
-            srgba.push(c);
-        }
-    }
-    let chip8_tex_id = painter.new_user_texture(
-        (CHIP8_DISPLAY_WIDTH as usize, CHIP8_DISPLAY_HEIGHT as usize),
-        srgba.as_slice(),
-        false,
-    );
     let bg_color = color::srgba(128, 128, 128, 0);
-    let mut use_vy_for_shift_operations = false;
-    let mut increment_i_on_ld_operations = false;
+    //The state of the panels and windows, such as which are open and what's been
+    //typed into them.
+    let mut panel_state = PanelState::new();
     let mut frame_count = 0;
     let mut avg_frame_time = 0u128;
     let mut fps = 0u128;
+    //In microseconds, to be accurate at high refresh rates.
     let mut frame_time_accum = 0u128;
-    let mut is_paused = false;
+    let mut next_frame = Instant::now();
+    //An event that came in while waiting for one when idle, which is handled along
+    //with the rest on the next frame.
+    let mut idle_event = None;
+    let mut last_event = Instant::now();
+    let mut frame_times = FrameTimes::new(FRAME_TIME_SAMPLES);
+    let mut emulation_rate = RateMeter::default();
+    //The display is kept from before and after each change while the changes are
+    //shown, to compare.
+    let mut shown_display: Vec<u8> = Vec::new();
+    let mut previous_display: Vec<u8> = Vec::new();
+    let mut loaded_rom_hash = None;
+    //The title and so on of the ROM that's loaded, if it has a metadata file.
+    let mut rom_metadata: Option<RomMetadata> = None;
+    //The breakpoint that was last stopped at, which is told about once.
+    let mut breakpoint_hit = None;
+    //How many of the odd addresses code has been run from have been warned about.
+    let mut odd_pcs_reported = 0;
+    //How many writes over code that had been run have been told about.
+    let mut patches_reported = 0;
+    //The buffer the display is converted into, reused every frame.
+    let mut texels: Vec<Srgba> = Vec::with_capacity(CRT_WIDTH * CRT_HEIGHT);
+    //Set when the display texture has to be uploaded even though the display
+    //hasn't changed, such as when the painter has been recreated.
+    let mut texture_stale = true;
+    let mut last_look = (
+        config.display_colors(),
+        config.crt_effect,
+        config.crt_intensity,
+        false,
+        false,
+    );
+    //The display is drawn straight to the window unless something needs egui to draw
+    //it. This texture is the same size as the egui display texture.
+    let mut gl_display = GlDisplay::new(TEXTURE_WIDTH, TEXTURE_HEIGHT);
+    let mut skipped_uploads = 0u64;
+    //What the uploads that weren't skipped took, to set against the ones that were.
+    let mut uploads = 0u64;
+    let mut upload_time = Duration::from_secs(0);
+    //Counts frames up to the frame skip, drawing the display when it wraps round.
+    let mut skip_count = 0;
+    let mut title = String::new();
+    let mut resize_to: Option<(u32, u32)> = None;
+    //Set when the window moves, to check whether it's now on a display with another DPI.
+    let mut check_dpi = false;
+    let mut window_scale = config.display_scale;
+    let mut rom_watcher: Option<RomWatcher> = None;
+    //The ROM the input's being recorded on, which it's saved for even if another ROM
+    //is loaded, as that ends the recording.
+    let mut input_rom = String::new();
+    //Set by the color pickers and sliders, which change on every frame of a drag. The
+    //config is saved once the mouse button is let go.
+    let mut config_dirty = false;
 
     //The main loop.
     //Processes events, runs emulation steps, updates display
@@ -162,123 +722,528 @@ pub fn main() {
         raw_input.time = Some(start_time.elapsed().as_nanos() as f64 * 1e-9);
         egui_ctx.begin_frame(raw_input.take());
 
-        let mut srgba: Vec<Srgba> = Vec::new();
+        //The steps asked for in the UI, sent once it's been built, and what's
+        //chosen from the pause menu, with the mouse or the keyboard, which is done once
+        //the events have been handled.
+        let mut requests = Requests::default();
+        //What's come back from the emulation thread since the last frame. It keeps
+        //the latest view of the machine itself.
+        for event in emulator.receive() {
+            match event {
+                emulator::Event::Stopped(reason) => report_stop(reason, &mut toasts),
+                emulator::Event::Loaded(variant) => suggested_variant = variant,
+                //What was recorded up to the change is still worth keeping.
+                emulator::Event::InputInterrupted(log) => {
+                    toasts.push(Toast::warning(
+                        "Input recording stopped, as the machine was changed",
+                    ));
+                    save_input_log(&log, &input_rom, &mut toasts);
+                }
+                emulator::Event::Toast(toast) => toasts.push(toast),
+                emulator::Event::View(_) => {}
+            }
+        }
 
-        //The chip8 display will be blit to this texture every frame.
-        let chip8_display = chip8.get_display_data();
-        for y in 0..CHIP8_DISPLAY_HEIGHT as usize {
-            for x in 0..CHIP8_DISPLAY_WIDTH as usize {
-                let pixel = chip8_display[y * (CHIP8_DISPLAY_WIDTH as usize) + x];
-                let c = if pixel > 0 {
-                    color::BLACK
-                } else {
-                    color::LIGHT_GRAY
-                };
-                srgba.push(c);
+        //Reload the ROM when it's been rebuilt on disk. Pausing is left as it was.
+        if config.auto_reload_rom {
+            if let Some(path) = rom_files.get(&selected_rom) {
+                let watching = rom_watcher
+                    .as_ref()
+                    .map_or(false, |w| w.path() == path.as_str());
+                if !watching {
+                    rom_watcher = Some(RomWatcher::new(path));
+                }
+                if let Some(watcher) = &mut rom_watcher {
+                    if watcher.poll() {
+                        load_rom(&emulator, &config, &rom_files, &selected_rom);
+                        toasts.push(Toast::info(format!("{} reloaded", selected_rom)));
+                    }
+                }
             }
         }
 
-        painter.update_user_texture_data(chip8_tex_id, &srgba);
+        toasts.expire();
 
-        &egui::Window::new("Chipper")
-            .fixed_pos(Pos2::new(0f32, 0f32))
-            //.default_size(vec2(WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32))
-            .resizable(false)
-            .collapsible(false)
-            .title_bar(false)
-            .show(&mut egui_ctx, |ui| {
-                if !is_paused {
-                    ui.label(format!("FPS: {} ({} ms/frame)", fps, avg_frame_time));
-                } else {
-                    ui.label(format!("PAUSED"));
+        let rom_hash = emulator.view().chip8.get_rom_hash();
+        if loaded_rom_hash != Some(rom_hash) {
+            loaded_rom_hash = Some(rom_hash);
+            panel_state.resume_state = if is_built_in_rom(&selected_rom) {
+                None
+            } else {
+                states::read_autosave(&emulator.view().chip8)
+            };
+            //The last session carries on from where it was without asking. An autosave
+            //that can't be loaded has already been skipped over, so it starts afresh.
+            if resuming_session {
+                resuming_session = false;
+                if let Some(state) = panel_state.resume_state.take() {
+                    let rom_name = selected_rom.clone();
+                    emulator.edit(move |machine, toasts| {
+                        toasts.push(match machine.chip8.load_state(&state) {
+                            Ok(()) => Toast::info(format!("Resumed {}", rom_name)),
+                            Err(e) => Toast::error(format!("Failed to resume: {}", e)),
+                        })
+                    });
                 }
+            }
+            let rom_cheats = cheats::load(rom_hash);
+            emulator.edit(move |machine, _| machine.cheats = rom_cheats);
+            panel_state.scanner = None;
+            rom_metadata = rom_files
+                .get(&selected_rom)
+                .and_then(|path| metadata::load(path));
+        }
 
-                ui.add(Image::new(
-                    chip8_tex_id,
-                    vec2(
-                        (CHIP8_DISPLAY_WIDTH * DISPLAY_SCALE) as f32,
-                        (CHIP8_DISPLAY_HEIGHT * DISPLAY_SCALE) as f32,
-                    ),
-                ));
-                ui.label("");
-
-                combo_box_with_label(ui, "ROM files", selected_rom, |ui| {
-                    //Doesn't work ATM
-                    for (f, _p) in &rom_files {
-                        if ui.selectable_value(&mut selected_rom, f, f).clicked {
-                            chip8
-                                .boot_rom(
-                                    rom_files.get(selected_rom).expect("No rom files to load!"),
-                                )
-                                .expect("Failed to load rom!");
-                        };
-                        /*if ui.button(f).clicked {
-                            selected_rom = f;
-                            chip8.boot_rom(rom_files.get(selected_rom).expect("No rom files to load!")).expect("Failed to load rom!");
-                        };*/
-                    }
-                });
-                //There is probably a better way to add line breaks in egui....
-                ui.label("");
-                if ui
-                    .checkbox(
-                        &mut use_vy_for_shift_operations,
-                        "Use Vy for shift operations",
-                    )
-                    .clicked
-                {
-                    chip8.shift_using_vy = use_vy_for_shift_operations;
+        //With frame skip on the display is only updated every few frames, though the
+        //emulator carries on running every frame on its own thread.
+        skip_count = (skip_count + 1) % config.frame_skip.max(1);
+        let draw_frame = skip_count == 0;
+        let display_changed = draw_frame && emulator.take_display_dirty();
+
+        //The UI is built from the latest view of the machine, so that the emulator
+        //carries on while it is. Changes are sent to the machine as commands, so the
+        //view doesn't show them until the next one comes.
+        let view = emulator.view();
+        let MachineView {
+            chip8,
+            paused: is_paused,
+            unfocused,
+            rewinding,
+            frames_run,
+            trace_full,
+            ..
+        } = view;
+        emulation_rate.update(*frames_run);
+        let idle = config.idle_sleep
+            && (*is_paused || *unfocused)
+            && !*rewinding
+            && panel_state.display_only_since.elapsed() > DISPLAY_ONLY_HINT_TIME;
+        //Resuming from the side panel closes the pause menu too. The view has to have
+        //caught up, or it'd close as soon as it's opened.
+        if !*is_paused && emulator.is_view_current() {
+            panel_state.pause_menu = None;
+        }
+
+        //Only touch the window title when something has changed.
+        //The ROM is always in the title, as it's updated on every frame no matter how
+        //the ROM was booted. Showing the paused state is optional.
+        let new_title = window_title(
+            &selected_rom,
+            (*is_paused || *unfocused) && config.title_shows_paused,
+            chip8.is_halted(),
+        );
+        if new_title != title {
+            //This only fails if the name contains a nul byte, so just keep the old title.
+            let _ = window.set_title(&new_title);
+            title = new_title;
+        }
+
+        if chip8.get_breakpoint_hit() != breakpoint_hit {
+            breakpoint_hit = chip8.get_breakpoint_hit();
+            if let Some(address) = breakpoint_hit {
+                toasts.push(Toast::info(format!(
+                    "Stopped at breakpoint {:03X}",
+                    address
+                )));
+            }
+        }
+
+        //Each odd address is only warned about once. There are fewer after a reload
+        //or a rewind.
+        odd_pcs_reported = odd_pcs_reported.min(chip8.get_odd_pcs().len());
+        for pc in &chip8.get_odd_pcs()[odd_pcs_reported..] {
+            toasts.push(Toast::warning(format!(
+                "Running code from the odd address {:03X}",
+                pc
+            )));
+        }
+        odd_pcs_reported = chip8.get_odd_pcs().len();
+
+        //Writes over code that's been run are told about a frame at a time, so that a
+        //program that keeps patching itself doesn't bury everything else.
+        if let Some(writes) = chip8.get_code_writes() {
+            patches_reported = patches_reported.min(writes.patch_count);
+            if writes.patch_count > patches_reported {
+                let mut message = match writes.patches.get(patches_reported as usize) {
+                    Some(patch) => match patch.pc {
+                        Some(pc) => format!("{:03X} rewrote the code at {:03X}", pc, patch.addr),
+                        None => format!("The code at {:03X} was poked", patch.addr),
+                    },
+                    None => "Code that had run was rewritten".to_string(),
                 };
-                if ui
-                    .checkbox(
-                        &mut increment_i_on_ld_operations,
-                        "Increment I on  LD Vx operations",
-                    )
-                    .clicked
-                {
-                    chip8.increment_i_on_ld = increment_i_on_ld_operations;
+                let more = writes.patch_count - patches_reported - 1;
+                if more > 0 {
+                    message += &format!(", and {} more", more);
+                }
+                toasts.push(Toast::info(message));
+                patches_reported = writes.patch_count;
+            }
+        }
+
+        //Faults are shown over the display until they're dealt with or closed. The
+        //built-in ROMs end by jumping to themselves, which isn't worth mentioning, and
+        //would cover up the test ROM's results.
+        let fault = chip8.get_fault().filter(|f| {
+            Some(*f) != panel_state.closed_fault
+                && !(f.kind == FaultKind::ProgramEnded && is_built_in_rom(&selected_rom))
+        });
+
+        //The display goes through egui when it's needed for the CRT effect or for
+        //drawing over the display, as the direct path is drawn after egui. Otherwise
+        //it's blit straight to the window. Anything that's painted over the display
+        //has to be listed here, or the blit covers it up.
+        let direct_display = !config.crt_effect
+            && config.overlay == Overlay::Off
+            && !panel_state.show_pixel_inspector
+            && !panel_state.show_keypad
+            && !panel_state.holding_keypad_key
+            && !panel_state.show_about
+            && !panel_state.show_help
+            && panel_state.pause_menu.is_none()
+            && fault.is_none()
+            && panel_state.resume_state.is_none()
+            && toasts.is_empty()
+            && !rom_files.is_empty()
+            && !panel_state.showing_hint();
+
+        //The changes are drawn over the plain display, so not with the CRT effect.
+        let highlight_changes =
+            panel_state.show_display_changes && *is_paused && !config.crt_effect;
+        //The chip8 display is only blit to the texture when it's changed, or when the
+        //way it looks has. That includes while paused, so color changes show up
+        //straight away.
+        let look = (
+            config.display_colors(),
+            config.crt_effect,
+            config.crt_intensity,
+            direct_display,
+            highlight_changes,
+        );
+        //The displays kept for comparing are let go of once the changes aren't shown.
+        if !panel_state.show_display_changes && !shown_display.is_empty() {
+            shown_display = Vec::new();
+            previous_display = Vec::new();
+        }
+        if display_changed && panel_state.show_display_changes {
+            previous_display =
+                std::mem::replace(&mut shown_display, chip8.get_display_data().to_vec());
+        }
+        if draw_frame && (display_changed || texture_stale || look != last_look) {
+            let upload_start = Instant::now();
+            let rgb = config.display_colors();
+            let colors = [
+                to_srgba(rgb[0]),
+                to_srgba(rgb[1]),
+                to_srgba(rgb[2]),
+                to_srgba(rgb[3]),
+            ];
+            if config.crt_effect {
+                crt::crt_texels(
+                    &mut texels,
+                    chip8.get_display_data(),
+                    chip8.get_display_width(),
+                    chip8.get_display_height(),
+                    &colors,
+                    config.crt_intensity,
+                );
+                painter.update_user_texture_data(textures.crt, &texels);
+            } else {
+                let previous = if highlight_changes {
+                    Some(&previous_display[..])
+                } else {
+                    None
                 };
-                ui.label("");
-                ui.label("ESC = Pause/Resume.  F2 = Reset.");
+                display_texels(&mut texels, chip8, &colors, previous);
+                if direct_display {
+                    gl_display.update(&texels);
+                } else {
+                    painter.update_user_texture_data(textures.display, &texels);
+                }
+            }
+            texture_stale = false;
+            last_look = look;
+            uploads += 1;
+            upload_time += upload_start.elapsed();
+        } else {
+            skipped_uploads += 1;
+        }
+
+        if let Some(counts) = chip8.get_access_counts() {
+            painter.update_user_texture_data(textures.heatmap, &heatmap::heatmap_texels(counts));
+        }
+
+        let meters = Meters {
+            fps,
+            avg_frame_time,
+            emulation_rate: emulation_rate.rate(),
+            frame_times: &frame_times,
+            skipped_uploads,
+            avg_upload_time: if uploads > 0 {
+                upload_time.as_micros() / uploads as u128
+            } else {
+                0
+            },
+        };
+        let mut front = Frontend {
+            emulator: &emulator,
+            video_subsystem: &video_subsystem,
+            textures: &textures,
+            pixels_per_point,
+            config: &mut config,
+            config_dirty: &mut config_dirty,
+            toasts: &mut toasts,
+            rom_dir: &mut rom_dir,
+            rom_files: &mut rom_files,
+            selected_rom: &mut selected_rom,
+            suggested_variant: &mut suggested_variant,
+            rom_metadata: &rom_metadata,
+            input_rom: &mut input_rom,
+            trace_format: &mut trace_format,
+        };
+        //The controls are hidden altogether in display only mode.
+        if !panel_state.display_only {
+            panels::controls(
+                &egui_ctx,
+                &mut front,
+                &mut panel_state,
+                view,
+                &meters,
+                &mut requests,
+            );
+        }
+        let display_rect = panels::overlays(
+            &egui_ctx,
+            &mut front,
+            &mut panel_state,
+            view,
+            fault,
+            direct_display,
+            &mut requests,
+        );
+
+        //Fit the window to the display scale when it's changed in the options.
+        if config.display_scale != window_scale {
+            window_scale = config.display_scale;
+            let (width, height) = window_size(window_scale);
+            match window.set_size(width, height) {
+                Ok(()) => resize_to = Some((width, height)),
+                Err(e) => println!("Failed to resize window: {}", e),
+            }
+        }
+
+        //The steps asked for in the UI are sent on to the machine.
+        if requests.step_frame {
+            emulator.send(Command::StepFrame);
+        }
+        if requests.step_instruction {
+            emulator.send(Command::StepInstruction);
+        }
+        if requests.undo_step {
+            emulator.send(Command::UndoStep);
+        }
+        if requests.step_over {
+            emulator.send(Command::StepOver);
+        }
+        if let Some(address) = requests.run_to {
+            emulator.send(Command::RunTo(address));
+        }
+        //A trace that's got to its limit isn't written to any more, so it's stopped.
+        //The view can say so more than once, so it's only stopped if it's still going.
+        if *trace_full {
+            let rom_name = selected_rom.clone();
+            emulator.edit(move |machine, toasts| {
+                if machine.is_trace_full() {
+                    toasts.push(Toast::warning(format!(
+                        "Tracing stopped after {} instructions",
+                        TRACE_MAX_LINES
+                    )));
+                    let Machine { chip8, trace, .. } = machine;
+                    toggle_trace(trace, chip8, trace_format, &rom_name, toasts);
+                }
             });
+        }
 
         let (_output, paint_cmds) = egui_ctx.end_frame();
         let paint_jobs = egui_ctx.tesselate(paint_cmds);
         painter.paint_jobs(bg_color, paint_jobs, &egui_ctx.texture(), pixels_per_point);
+        if direct_display {
+            gl_display.draw(display_rect, pixels_per_point, window.size().1);
+        }
 
         window.gl_swap_window();
-        for event in event_pump.poll_iter() {
+        if config_dirty && !event_pump.mouse_state().left() {
+            save_config(&config);
+            config_dirty = false;
+        }
+        let layout = config.keyboard_layout;
+        for event in idle_event.take().into_iter().chain(event_pump.poll_iter()) {
+            last_event = Instant::now();
             match event {
                 Event::Quit { .. } => break 'running,
+                //A ROM dropped on the window is run from where it is, as if it were in
+                //the ROM folder, the same as one given on the command line.
+                Event::DropFile { filename, .. } => {
+                    let name = Path::new(&filename)
+                        .file_name()
+                        .map_or(filename.clone(), |name| name.to_string_lossy().into_owned());
+                    rom_files.insert(name.clone(), filename);
+                    selected_rom = name;
+                    load_rom(&emulator, &config, &rom_files, &selected_rom);
+                }
+                Event::Window {
+                    win_event: WindowEvent::Resized(width, height),
+                    ..
+                } => {
+                    resize_to = Some((width.max(1) as u32, height.max(1) as u32));
+                }
+                //It might have moved onto a display with a different DPI.
+                Event::Window {
+                    win_event: WindowEvent::Moved(..),
+                    ..
+                } => {
+                    check_dpi = true;
+                }
+                //A pause from ESC is left alone either way.
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => {
+                    emulator.send(Command::Unfocused(config.pause_when_unfocused));
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => {
+                    emulator.send(Command::Unfocused(false));
+                }
+                //The help closes on any key, which does nothing else.
+                Event::KeyDown { .. } if panel_state.show_help => {}
+                Event::KeyUp { .. } if panel_state.show_help => {
+                    panel_state.show_help = false;
+                }
+                //The pause menu takes all of the keys while it's open, so none of them
+                //get through to the program. Keys that were held down are let go of.
                 Event::KeyDown {
                     keycode: Some(t), ..
-                } => match t {
-                    Num0 | Num1 | Num2 | Num3 | Num4 | Num5 | Num6 | Num7 | Num8 | Num9 => {
-                        chip8.set_key_pressed(keycode_to_chip8_key(&t));
+                } if panel_state.pause_menu.is_some() => {
+                    let menu = panel_state.pause_menu.as_mut().unwrap();
+                    if t == Keycode::Up || t == Keycode::Down {
+                        menu.move_in_list(t == Keycode::Down, rom_files.len());
                     }
-                    A | B | C | D | E | F => {
-                        chip8.set_key_pressed(keycode_to_chip8_key(&t));
+                }
+                Event::KeyUp {
+                    keycode: Some(t), ..
+                } if panel_state.pause_menu.is_some() => {
+                    if let Some(key) = layout.chip8_key(t) {
+                        emulator.send(Command::KeyUp(key));
                     }
-                    _ => chip8.set_key_pressed(0xff),
-                },
+                    let menu = panel_state.pause_menu.as_mut().unwrap();
+                    if t == Keycode::Return || t == Keycode::KpEnter {
+                        if menu.is_choosing_rom() {
+                            let roms = roms::list_order(&rom_files, &config.favorite_roms);
+                            requests.menu_rom = roms.get(menu.selected()).map(|rom| (*rom).clone());
+                        } else {
+                            requests.menu_choice = Some(menu.selected_item());
+                        }
+                    } else if hotkeys::action(t, layout) == Some(Action::Pause) {
+                        if menu.is_choosing_rom() {
+                            *menu = PauseMenu::default();
+                        } else {
+                            requests.menu_choice = Some(Item::Resume);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(t), ..
+                } if hotkeys::action(t, layout) == Some(Action::Rewind) => {
+                    emulator.send(Command::Rewind(true));
+                }
                 Event::KeyUp {
                     keycode: Some(t), ..
-                } => match t {
-                    Num0 | Num1 | Num2 | Num3 | Num4 | Num5 | Num6 | Num7 | Num8 | Num9 => {
-                        chip8.set_key_pressed(0xff);
+                } if hotkeys::action(t, layout) == Some(Action::Rewind) => {
+                    emulator.send(Command::Rewind(false));
+                }
+                Event::KeyDown {
+                    keycode: Some(t), ..
+                } if hotkeys::action(t, layout) == Some(Action::ShowKeypad) => {
+                    panel_state.holding_keypad_key = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(t), ..
+                } if hotkeys::action(t, layout) == Some(Action::ShowKeypad) => {
+                    panel_state.holding_keypad_key = false;
+                }
+                Event::KeyDown {
+                    keycode: Some(t), ..
+                } => {
+                    let key = layout.chip8_key(t).unwrap_or(0xff);
+                    emulator.send(Command::KeyDown(key));
+                }
+                Event::KeyUp {
+                    keycode: Some(t), ..
+                } if layout.chip8_key(t).is_some() => {
+                    if let Some(key) = layout.chip8_key(t) {
+                        emulator.send(Command::KeyUp(key));
                     }
-                    A | B | C | D | E | F => {
-                        chip8.set_key_pressed(0xff);
+                }
+                Event::KeyUp {
+                    keycode: Some(t), ..
+                } => match hotkeys::action(t, layout) {
+                    Some(Action::Pause) => {
+                        panel_state.pause_menu = Some(PauseMenu::default());
+                        emulator.send(Command::Pause(true));
                     }
-                    Escape => {
-                        is_paused = !is_paused;
+                    Some(Action::Reset) => {
+                        load_rom(&emulator, &config, &rom_files, &selected_rom);
                     }
-                    F2 => {
-                        chip8
-                            .boot_rom(rom_files.get(selected_rom).expect("No rom files to load!"))
-                            .expect("Failed to load rom!");
+                    Some(action @ Action::PreviousRom) | Some(action @ Action::NextRom) => {
+                        if let Some(name) = roms::adjacent_rom(
+                            &rom_files,
+                            &config.favorite_roms,
+                            &selected_rom,
+                            action == Action::NextRom,
+                        ) {
+                            selected_rom = name;
+                            load_rom(&emulator, &config, &rom_files, &selected_rom);
+                        }
                     }
-                    _ => (),
+                    Some(Action::TestRom) => {
+                        selected_rom = TEST_ROM_NAME.to_string();
+                        load_rom(&emulator, &config, &rom_files, &selected_rom);
+                    }
+                    Some(Action::StepFrame) => emulator.send(Command::StepFrame),
+                    Some(Action::StepOver) => {
+                        if *is_paused {
+                            emulator.send(Command::StepOver);
+                        }
+                    }
+                    Some(Action::SaveState) => {
+                        save_state(chip8, panel_state.active_slot, &mut toasts)
+                    }
+                    Some(Action::LoadState) => {
+                        let slot = panel_state.active_slot;
+                        emulator.edit(move |machine, toasts| {
+                            load_state(&mut machine.chip8, slot, toasts)
+                        });
+                    }
+                    Some(Action::RecordGif) => {
+                        let (config, rom_name) = (config.clone(), selected_rom.clone());
+                        emulator.edit(move |machine, toasts| {
+                            toggle_recording(&mut machine.recording, &config, &rom_name, toasts)
+                        });
+                    }
+                    Some(Action::DisplayOnly) => {
+                        panel_state.display_only = !panel_state.display_only;
+                        panel_state.display_only_since = Instant::now();
+                    }
+                    Some(Action::Mute) => {
+                        config.muted = !config.muted;
+                        save_config(&config);
+                    }
+                    Some(Action::Help) => {
+                        panel_state.show_help = true;
+                    }
+                    Some(Action::Rewind) | Some(Action::ShowKeypad) | None => (),
                 },
                 _ => {
                     egui_sdl::input_to_egui(event, clipboard.as_mut(), &mut raw_input);
@@ -286,36 +1251,161 @@ pub fn main() {
             }
         }
 
-        if !is_paused {
-            for _ in 0..10 {
-                chip8.step();
+        //The pause menu's choices are done here, whether they came from a click or a
+        //key. Quitting goes the same way as closing the window.
+        if let Some(rom) = requests.menu_rom {
+            selected_rom = rom;
+            load_rom(&emulator, &config, &rom_files, &selected_rom);
+            emulator.send(Command::Pause(false));
+            panel_state.pause_menu = None;
+        }
+        if let Some(item) = requests.menu_choice {
+            match item {
+                Item::Resume => {
+                    emulator.send(Command::Pause(false));
+                    panel_state.pause_menu = None;
+                }
+                Item::Reset => {
+                    load_rom(&emulator, &config, &rom_files, &selected_rom);
+                    emulator.send(Command::Pause(false));
+                    panel_state.pause_menu = None;
+                }
+                Item::LoadRom => {
+                    let roms = roms::list_order(&rom_files, &config.favorite_roms);
+                    let current = roms.iter().position(|rom| **rom == selected_rom);
+                    panel_state.pause_menu = Some(PauseMenu::rom_list(current.unwrap_or(0)));
+                }
+                Item::SaveState => save_state(chip8, panel_state.active_slot, &mut toasts),
+                Item::LoadState => {
+                    let slot = panel_state.active_slot;
+                    emulator
+                        .edit(move |machine, toasts| load_state(&mut machine.chip8, slot, toasts));
+                    emulator.send(Command::Pause(false));
+                    panel_state.pause_menu = None;
+                }
+                //The settings are in the side panel, and it stays paused while they're
+                //changed.
+                Item::Settings => {
+                    panel_state.display_only = false;
+                    panel_state.pause_menu = None;
+                }
+                Item::Quit => break 'running,
             }
         }
 
-        let elapsed_frame_time = frame_time.elapsed();
-        let frame_time_in_ms = elapsed_frame_time.as_millis();
-        //let frame_time_in_ns = elapsed_frame_time.as_nanos();
+        //Applied every frame, so changes are heard straight away. It only does anything
+        //when they've changed.
+        if let Some(beeper) = beeper.as_mut() {
+            beeper.set_volume(config.volume, config.muted);
+            beeper.set_waveform(config.waveform);
+        }
 
-        //Try to maintain ~60FPS. This isn't the best way, but it's fine for
-        //for now.
-        if frame_time_in_ms < 16 {
-            //    //std::thread::sleep(Duration::from_nanos((16000000 - frame_time_in_ns) as u64));
-            std::thread::sleep(Duration::from_millis((16 - frame_time_in_ms) as u64));
+        if check_dpi {
+            check_dpi = false;
+            let new_pixels_per_point = window_pixels_per_point(&video_subsystem, &window);
+            if new_pixels_per_point != pixels_per_point {
+                pixels_per_point = new_pixels_per_point;
+                raw_input.pixels_per_point = Some(pixels_per_point);
+                resize_to = Some(window.size());
+            }
+        }
+
+        //Keep egui and the painter in step with the window, whether it was resized by
+        //the user, by changing the display scale or by moving to a display with a
+        //different DPI.
+        if let Some((width, height)) = resize_to.take() {
+            raw_input.screen_rect = Some(Rect::from_min_size(
+                Pos2::new(0f32, 0f32),
+                vec2(width as f32, height as f32) / pixels_per_point,
+            ));
+            painter.cleanup();
+            let (new_painter, new_textures) = create_painter(
+                &video_subsystem,
+                width,
+                height,
+                to_srgba(config.pixel_off_color),
+            );
+            painter = new_painter;
+            textures = new_textures;
+            texture_stale = true;
         }
-        let frame_time_in_ms = frame_time.elapsed().as_millis();
-        frame_time_accum += frame_time_in_ms;
+
+        //The graph is of the time spent on the frame itself, so it's taken before the
+        //wait for the next one, which would otherwise fill every frame up to the
+        //refresh period and hide how much room is left.
+        frame_times.push(frame_time.elapsed());
+
+        //The UI is paced against a deadline that moves on by a frame each time, rather
+        //than by sleeping off what's left of 16ms, so it keeps to the refresh rate that's
+        //set whatever the monitor's. The emulator keeps its own time on its own thread,
+        //so this only changes how often the display is updated. If it falls well
+        //behind, such as when a frame took a long time, it starts over from now rather
+        //than rushing to catch up.
+        //While idle it waits for an event instead, or for IDLE_WAIT_MS at most, so that
+        //anything that happens is dealt with straight away.
+        let frame_period = Duration::from_nanos(1_000_000_000 / config.refresh_rate.max(1) as u64);
+        next_frame += frame_period;
+        let now = Instant::now();
+        if idle && now - last_event > IDLE_AFTER {
+            idle_event = event_pump.wait_event_timeout(IDLE_WAIT_MS);
+            next_frame = Instant::now();
+        } else if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else if now - next_frame > frame_period * 4 {
+            next_frame = now;
+        }
+        frame_time_accum += frame_time.elapsed().as_micros();
 
         frame_count += 1;
 
         if frame_count >= 10 {
             avg_frame_time = frame_time_accum / frame_count;
             if avg_frame_time > 0 {
-                fps = 1000 / avg_frame_time;
+                fps = 1_000_000 / avg_frame_time;
             }
             frame_time_accum = 0u128;
             frame_count = 0;
         }
-        chip8.update_timers();
+    }
+    //Don't leave a recording half written.
+    let mut machine = emulator.stop();
+    if machine.recording.is_some() {
+        toggle_recording(&mut machine.recording, &config, &selected_rom, &mut toasts);
+    }
+    machine.end_interrupted_input();
+    let input_log = machine.interrupted_input.take().or_else(|| {
+        machine
+            .input_recorder
+            .take()
+            .map(|recorder| recorder.finish())
+    });
+    if let Some(log) = input_log {
+        save_input_log(&log, &input_rom, &mut toasts);
+    }
+    if machine.trace.is_some() {
+        let Machine { chip8, trace, .. } = &mut machine;
+        toggle_trace(trace, chip8, trace_format, &selected_rom, &mut toasts);
+    }
+    //Save where the ROM was up to, to offer to resume from next time. The old autosave
+    //is kept if it was never answered, as nothing's been played since.
+    if !is_built_in_rom(&selected_rom) && panel_state.resume_state.is_none() {
+        if let Err(e) = states::autosave(&machine.chip8) {
+            println!("Failed to autosave: {}", e);
+        }
+    }
+    //Remember what was running, for resuming the session next time, and the flag
+    //registers, which outlast the ROM.
+    config.last_rom = if is_built_in_rom(&selected_rom) {
+        String::new()
+    } else {
+        selected_rom.clone()
+    };
+    save_config(&config);
+    if let Err(e) = states::save_flags(&machine.chip8) {
+        println!("Failed to save the flag registers: {}", e);
+    }
+    if let Some(path) = piped_rom {
+        let _ = fs::remove_file(path);
     }
     painter.cleanup();
 }
@@ -0,0 +1,127 @@
+use chipper::chip8::Chip8;
+use serde::Deserialize;
+use std::fs;
+
+/// What's known about a ROM, from an optional JSON file next to it named after it
+/// with .meta on the end, such as PONG.ch8.meta:
+///
+/// ```text
+/// {
+///     "title": "Pong",
+///     "author": "Paul Vervalin",
+///     "shift_using_vy": true,
+///     "cycles_per_frame": 12
+/// }
+/// ```
+///
+/// Everything is optional. The quirks and clock speed are the ones the ROM runs
+/// best with, and are set whenever it's loaded.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RomMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub shift_using_vy: Option<bool>,
+    pub increment_i_on_ld: Option<bool>,
+    pub wrap_sprites_x: Option<bool>,
+    pub wrap_sprites_y: Option<bool>,
+    pub vf_reset: Option<bool>,
+    pub jump_using_vx: Option<bool>,
+    pub wait_for_vblank: Option<bool>,
+    pub cycles_per_frame: Option<usize>,
+}
+
+impl RomMetadata {
+    /// Sets the quirks and clock speed that the metadata recommends.
+    pub fn apply(self: &Self, chip8: &mut Chip8) {
+        if let Some(shift_using_vy) = self.shift_using_vy {
+            chip8.shift_using_vy = shift_using_vy;
+        }
+        if let Some(increment_i_on_ld) = self.increment_i_on_ld {
+            chip8.increment_i_on_ld = increment_i_on_ld;
+        }
+        if let Some(wrap_sprites_x) = self.wrap_sprites_x {
+            chip8.wrap_sprites_x = wrap_sprites_x;
+        }
+        if let Some(wrap_sprites_y) = self.wrap_sprites_y {
+            chip8.wrap_sprites_y = wrap_sprites_y;
+        }
+        if let Some(vf_reset) = self.vf_reset {
+            chip8.vf_reset = vf_reset;
+        }
+        if let Some(jump_using_vx) = self.jump_using_vx {
+            chip8.jump_using_vx = jump_using_vx;
+        }
+        if let Some(wait_for_vblank) = self.wait_for_vblank {
+            chip8.wait_for_vblank = wait_for_vblank;
+        }
+        if let Some(cycles_per_frame) = self.cycles_per_frame.filter(|&c| c > 0) {
+            chip8.instructions_per_second = cycles_per_frame as u32 * 60;
+        }
+    }
+
+    /// Returns the title and author as one line, such as "Pong by Paul Vervalin".
+    pub fn heading(self: &Self) -> Option<String> {
+        match (&self.title, &self.author) {
+            (Some(title), Some(author)) => Some(format!("{} by {}", title, author)),
+            (Some(title), None) => Some(title.clone()),
+            (None, Some(author)) => Some(format!("By {}", author)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Loads the metadata for the ROM at rom_path. Most ROMs have none, and a broken
+/// file is ignored.
+pub fn load(rom_path: &str) -> Option<RomMetadata> {
+    let path = format!("{}.meta", rom_path);
+    let json = fs::read_to_string(&path).ok()?;
+    parse(&json)
+        .map_err(|e| println!("Ignoring invalid ROM metadata {}: {}", path, e))
+        .ok()
+}
+
+pub fn parse(json: &str) -> serde_json::Result<RomMetadata> {
+    serde_json::from_str(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PONG: &str = r#"{
+        "title": "Pong",
+        "author": "Paul Vervalin",
+        "shift_using_vy": true,
+        "cycles_per_frame": 12
+    }"#;
+
+    #[test]
+    fn parses_sample() {
+        let metadata = parse(PONG).unwrap();
+        assert_eq!(metadata.heading().unwrap(), "Pong by Paul Vervalin");
+        assert_eq!(metadata.description, None);
+        assert_eq!(metadata.increment_i_on_ld, None);
+
+        let mut chip8 = Chip8::new();
+        metadata.apply(&mut chip8);
+        assert!(chip8.shift_using_vy);
+        assert!(!chip8.increment_i_on_ld);
+        assert_eq!(chip8.instructions_per_second, 720);
+    }
+
+    #[test]
+    fn loads_from_next_to_rom() {
+        let rom = std::env::temp_dir().join("chipper-metadata-pong.ch8");
+        let rom = rom.to_str().unwrap();
+        assert_eq!(load(rom), None);
+
+        fs::write(format!("{}.meta", rom), PONG).unwrap();
+        assert_eq!(load(rom).unwrap().title.unwrap(), "Pong");
+
+        fs::write(format!("{}.meta", rom), "{ title: Pong }").unwrap();
+        assert_eq!(load(rom), None);
+        fs::remove_file(format!("{}.meta", rom)).unwrap();
+    }
+}
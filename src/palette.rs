@@ -0,0 +1,75 @@
+/// A named pair of display colors, as RGB.
+pub struct Palette {
+    pub name: &'static str,
+    pub on: [u8; 3],
+    pub off: [u8; 3],
+}
+
+/// The name shown when the colors have been picked by hand.
+pub const CUSTOM: &str = "Custom";
+
+/// The built-in palettes. The first one is the default.
+pub const PRESETS: [Palette; 7] = [
+    Palette {
+        name: "Chipper",
+        on: [0, 0, 0],
+        off: [220, 220, 220],
+    },
+    Palette {
+        name: "Green phosphor",
+        on: [51, 255, 102],
+        off: [8, 24, 12],
+    },
+    Palette {
+        name: "Amber",
+        on: [255, 176, 0],
+        off: [26, 16, 0],
+    },
+    Palette {
+        name: "White on black",
+        on: [255, 255, 255],
+        off: [0, 0, 0],
+    },
+    Palette {
+        name: "Black on white",
+        on: [0, 0, 0],
+        off: [255, 255, 255],
+    },
+    Palette {
+        name: "Olive handheld",
+        on: [15, 56, 15],
+        off: [155, 188, 15],
+    },
+    Palette {
+        name: "High contrast",
+        on: [255, 255, 0],
+        off: [0, 0, 96],
+    },
+];
+
+/// Looks up a built-in palette by name.
+pub fn find(name: &str) -> Option<&'static Palette> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_have_distinct_colors() {
+        for palette in &PRESETS {
+            assert_ne!(palette.on, palette.off, "{}", palette.name);
+        }
+    }
+
+    #[test]
+    fn presets_have_unique_names() {
+        for (i, palette) in PRESETS.iter().enumerate() {
+            assert_ne!(palette.name, CUSTOM);
+            assert!(PRESETS[i + 1..].iter().all(|p| p.name != palette.name));
+            assert_eq!(find(palette.name).unwrap().name, palette.name);
+        }
+        assert!(find(CUSTOM).is_none());
+    }
+}
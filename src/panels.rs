@@ -0,0 +1,2175 @@
+use crate::config::{Config, Overlay};
+use crate::emulator::{Command, Emulator, Machine, MachineView};
+use crate::heatmap::{HEATMAP_HEIGHT, HEATMAP_WIDTH};
+use crate::keymap::{self, Layout};
+use crate::metadata::RomMetadata;
+use crate::pause_menu::{self, Item, PauseMenu};
+use crate::scanner::{Filter, Scanner};
+use crate::states;
+use crate::stats::FrameTimes;
+use crate::toasts::{Severity, Toast, ToastQueue};
+use crate::trace::TraceFormat;
+use crate::{
+    apply_config, input_log_path, load_rom, load_state, save_config, save_input_log, save_state,
+    scan_roms, to_srgba, toggle_recording, toggle_trace, Textures, BOOT_ROM_NAME,
+    CHIP8_DISPLAY_HEIGHT, CHIP8_DISPLAY_WIDTH, CONTROLS_WIDTH, DISPLAY_MARGIN,
+    DISPLAY_ONLY_HINT_TIME, FRAME_TIME_SAMPLES, PIXEL_CLEARED_COLOR, PIXEL_SET_COLOR,
+};
+use crate::{audio, cheats, coverage, hotkeys, palette, rewind, roms};
+use chipper::chip8::{
+    self, Chip8, Condition, Fault, FaultKind, InputLog, InputPlayer, InputRecorder, Variant,
+    CODE_WRITE_LOG_LIMIT,
+};
+use egui::{color, combo_box_with_label, vec2, Image, Pos2, Rect};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+// The scales that GIF recordings can be made at.
+const RECORDING_SCALES: [u32; 3] = [1, 2, 4];
+
+// The clock speeds that can be picked in the options, in instructions a second.
+// 600 is 10 a frame, which most CHIP-8 programs expect.
+const CLOCK_SPEEDS: [u32; 7] = [300, 600, 900, 1200, 1800, 3000, 30000];
+
+// The limits on sprites drawn per frame that can be picked in the options. 0 is
+// no limit.
+const DRAW_LIMITS: [u32; 5] = [0, 1, 2, 4, 8];
+
+// The frame skips that can be picked in the options. The display is drawn every
+// Nth frame, so 1 is no skipping.
+const FRAME_SKIPS: [u32; 4] = [1, 2, 3, 4];
+
+// The refresh rates that can be picked in the options. They're how often the UI and
+// display are updated, and don't change how fast programs run.
+const REFRESH_RATES: [u32; 4] = [30, 60, 120, 144];
+
+// The display scales that can be picked in the options.
+const DISPLAY_SCALES: [u32; 4] = [4, 8, 12, 16];
+
+// Memory dumps are saved here, relative to the working directory.
+const DUMP_DIR: &str = "./dumps";
+
+// The size of the frame time graph, in points.
+const FRAME_GRAPH_WIDTH: f32 = 240f32;
+const FRAME_GRAPH_HEIGHT: f32 = 48f32;
+
+// The emulation speed readout turns amber, then red, when it's this far behind.
+const SPEED_WARNING_PERCENT: u64 = 95;
+const SPEED_ALERT_PERCENT: u64 = 80;
+
+// The pause menu is roughly this size, for centring it over the display.
+const PAUSE_MENU_WIDTH: f32 = 160f32;
+const PAUSE_MENU_HEIGHT: f32 = 180f32;
+
+// The memory viewer shows a page of this many rows of bytes at a time.
+const MEMORY_ROW_LENGTH: usize = 8;
+const MEMORY_PAGE_ROWS: usize = 16;
+
+// The most matches of a byte pattern that are listed, to jump the memory viewer to.
+const MAX_FIND_RESULTS: usize = 8;
+
+// The memory search only lists the addresses once it's down to this many.
+const MAX_SCAN_RESULTS: usize = 16;
+
+// The most recent writes into the ROM that are listed, when they're being logged.
+const CODE_WRITES_SHOWN: usize = 8;
+
+// How many instructions the disassembly lists, from the PC on.
+const DISASSEMBLY_LINES: u16 = 12;
+
+// The width of the toasts in the corner of the display area.
+const TOAST_WIDTH: f32 = 260f32;
+
+// The Chip8 keypad, row by row as it's laid out on the COSMAC VIP.
+const KEYPAD_ROWS: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// What the panels change outside of the machine, borrowed from main() for the frame.
+pub struct Frontend<'a> {
+    pub emulator: &'a Emulator,
+    /// For SDL's clipboard.
+    pub video_subsystem: &'a sdl2::VideoSubsystem,
+    pub textures: &'a Textures,
+    pub pixels_per_point: f32,
+    pub config: &'a mut Config,
+    /// Set when the config has changed during a drag, to be saved once it's over.
+    pub config_dirty: &'a mut bool,
+    pub toasts: &'a mut ToastQueue,
+    /// The ROM folder being typed in, which is only used once it's rescanned.
+    pub rom_dir: &'a mut String,
+    pub rom_files: &'a mut BTreeMap<String, String>,
+    pub selected_rom: &'a mut String,
+    pub suggested_variant: &'a mut Option<Variant>,
+    pub rom_metadata: &'a Option<RomMetadata>,
+    /// The ROM the input's being recorded on.
+    pub input_rom: &'a mut String,
+    pub trace_format: &'a mut TraceFormat,
+}
+
+/// How fast things are going, for the status line and the performance panel.
+pub struct Meters<'a> {
+    pub fps: u128,
+    /// In microseconds.
+    pub avg_frame_time: u128,
+    /// The emulator's frames per second.
+    pub emulation_rate: u64,
+    pub frame_times: &'a FrameTimes,
+    pub skipped_uploads: u64,
+    /// How long converting and uploading the display texture takes, in microseconds,
+    /// when it isn't skipped.
+    pub avg_upload_time: u128,
+}
+
+/// What's been asked for in the panels that's done to the machine once they're
+/// built. The pause menu's choices can come from the keyboard too.
+#[derive(Default)]
+pub struct Requests {
+    pub step_frame: bool,
+    pub step_instruction: bool,
+    pub undo_step: bool,
+    pub step_over: bool,
+    pub run_to: Option<u16>,
+    pub menu_choice: Option<Item>,
+    pub menu_rom: Option<String>,
+}
+
+/// What's open and what's been typed into the panels, which is kept from one frame
+/// to the next.
+pub struct PanelState {
+    pub show_about: bool,
+    pub show_help: bool,
+    pub show_pixel_inspector: bool,
+    /// The keypad is shown over the display while its key is held, or all the time.
+    pub show_keypad: bool,
+    pub holding_keypad_key: bool,
+    /// Highlights the pixels that changed in the last frame or step while paused.
+    pub show_display_changes: bool,
+    pub confirm_reset_config: bool,
+    /// The pause menu is open while this is Some.
+    pub pause_menu: Option<PauseMenu>,
+    /// Display only mode hides the controls. A hint for getting them back is shown
+    /// for a while after it's turned on.
+    pub display_only: bool,
+    pub display_only_since: Instant,
+    pub active_slot: usize,
+    /// The autosave of the ROM that's loaded, until the user says whether to resume it.
+    pub resume_state: Option<Vec<u8>>,
+    /// The fault that the user closed the fault window for, so it isn't shown again.
+    pub closed_fault: Option<Fault>,
+    /// The cheats being typed in, and whether they can go below 0x200.
+    pub cheat_text: String,
+    pub allow_interpreter_area: bool,
+    /// The start of the page of memory that's shown, and the byte pattern search.
+    pub memory_view_addr: usize,
+    pub find_text: String,
+    pub find_ascii: bool,
+    pub find_results: Vec<u16>,
+    /// The memory search, and the value being searched for.
+    pub scanner: Option<Scanner>,
+    pub scan_text: String,
+    /// The breakpoint being typed in.
+    pub breakpoint_text: String,
+    pub condition_text: String,
+    pub run_to_text: String,
+}
+
+impl PanelState {
+    pub fn new() -> PanelState {
+        PanelState {
+            show_about: false,
+            show_help: false,
+            show_pixel_inspector: false,
+            show_keypad: false,
+            holding_keypad_key: false,
+            show_display_changes: false,
+            confirm_reset_config: false,
+            pause_menu: None,
+            display_only: false,
+            display_only_since: Instant::now(),
+            active_slot: 0,
+            resume_state: None,
+            closed_fault: None,
+            cheat_text: String::new(),
+            allow_interpreter_area: false,
+            memory_view_addr: 0x200,
+            find_text: String::new(),
+            find_ascii: false,
+            find_results: Vec::new(),
+            scanner: None,
+            scan_text: String::new(),
+            breakpoint_text: String::new(),
+            condition_text: String::new(),
+            run_to_text: String::new(),
+        }
+    }
+
+    /// Whether the display only hint is still being shown.
+    pub fn showing_hint(self: &Self) -> bool {
+        self.display_only && self.display_only_since.elapsed() < DISPLAY_ONLY_HINT_TIME
+    }
+}
+
+/// The controls down the side of the window. They scroll rather than get cut off
+/// when the window is small.
+pub fn controls(
+    ctx: &egui::CtxRef,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    view: &MachineView,
+    meters: &Meters,
+    requests: &mut Requests,
+) {
+    egui::SidePanel::left("controls", CONTROLS_WIDTH as f32).show(ctx, |ui| {
+        egui::ScrollArea::auto_sized().show(ui, |ui| {
+            status(ui, view, meters, requests);
+            ui.label("");
+            rom_options(ui, front, &view.chip8);
+            display_options(ui, front, &view.chip8);
+            //There is probably a better way to add line breaks in egui....
+            ui.label("");
+            quirk_options(ui, front);
+            other_options(ui, front, panels);
+            ui.label("");
+            ui.collapsing("Debug", |ui| {
+                debug(ui, front, panels, view, meters, requests);
+            });
+
+            //Frame times show whether drawing is slow, and the emulator's frame
+            //rate whether it's falling behind, which it can do by itself.
+            ui.collapsing("Performance", |ui| {
+                performance(ui, view, meters);
+            });
+
+            //A page of memory at a time, with a search for finding things in it.
+            ui.collapsing("Memory", |ui| {
+                memory(ui, front.toasts, panels, &view.chip8);
+            });
+
+            //Cheats belong to the ROM like save states, and are saved as they're changed.
+            ui.collapsing("Cheats", |ui| {
+                cheat_options(ui, front, panels, view);
+            });
+
+            ui.label("");
+            recording_options(ui, front, panels, view);
+            ui.label("");
+            key_options(ui, front.config);
+            ui.label("");
+            ui.horizontal(|ui| {
+                if ui.button("Display only").clicked {
+                    panels.display_only = true;
+                    panels.display_only_since = Instant::now();
+                }
+                if ui.button("About").clicked {
+                    panels.show_about = !panels.show_about;
+                }
+            });
+        });
+    });
+}
+
+/// Everything that's drawn over the display, and the display itself unless it's
+/// drawn straight to the window. Returns where the display is.
+pub fn overlays(
+    ctx: &egui::CtxRef,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    view: &MachineView,
+    fault: Option<Fault>,
+    direct_display: bool,
+    requests: &mut Requests,
+) -> Rect {
+    let chip8 = &view.chip8;
+    about_window(ctx, front, panels, chip8);
+    //The shortcuts are shown over the dimmed display, grouped the same way as the
+    //table the keys are handled by.
+    if panels.show_help {
+        help_window(ctx, front.config.keyboard_layout);
+    }
+    let (display_rect, display_area) =
+        display(ctx, front, panels, chip8, fault.is_some(), direct_display);
+    display_labels(ctx, front, panels, chip8, display_rect, display_area);
+    //Toasts stack up in the top right corner of the display area. Each can be
+    //dismissed before it goes away by itself.
+    if !front.toasts.is_empty() {
+        toast_list(ctx, front.toasts, display_area);
+    }
+    if let Some(menu) = &panels.pause_menu {
+        pause_menu_list(ctx, front, menu, display_rect, requests);
+    }
+    resume_window(ctx, front, panels);
+    if let Some(fault) = fault {
+        fault_window(ctx, front, panels, fault);
+    }
+    display_rect
+}
+
+// The line at the top that says what the machine is doing, and the step buttons
+// while it's paused.
+fn status(ui: &mut egui::Ui, view: &MachineView, meters: &Meters, requests: &mut Requests) {
+    let MachineView {
+        chip8,
+        paused: is_paused,
+        unfocused,
+        rewinding,
+        rewound,
+        step_undo_len,
+        recording,
+        recording_input,
+        replay_progress,
+        ..
+    } = view;
+    ui.horizontal(|ui| {
+        if *is_paused {
+            ui.label(format!("PAUSED"));
+        } else if *rewinding && *rewound {
+            ui.label("REWOUND (as far back as it goes)");
+        } else if *rewinding {
+            ui.label("REWINDING");
+        } else if *unfocused {
+            ui.label("PAUSED (unfocused)");
+        } else if chip8.is_halted() {
+            ui.label("EXITED");
+        } else {
+            ui.label(format!(
+                "FPS: {} ({:.1} ms/frame)",
+                meters.fps,
+                meters.avg_frame_time as f32 / 1000f32
+            ));
+            //Nothing's been measured for the first second.
+            if meters.emulation_rate > 0 {
+                speed_label(ui, meters.emulation_rate);
+            }
+        }
+        if *recording {
+            ui.colored_label(color::RED, "REC");
+        }
+        if *recording_input {
+            ui.colored_label(color::RED, "REC INPUT");
+        }
+        if let Some((tick, ticks)) = replay_progress {
+            ui.colored_label(color::GREEN, format!("REPLAY {}/{}", tick, ticks));
+        }
+    });
+    if *is_paused {
+        ui.horizontal(|ui| {
+            if ui.button("Step frame").clicked {
+                requests.step_frame = true;
+            }
+            if ui.button("Step instruction").clicked {
+                requests.step_instruction = true;
+            }
+            if ui.button("Step over").clicked {
+                requests.step_over = true;
+            }
+            let step_back = ui
+                .add(
+                    egui::Button::new(format!("Step back ({})", step_undo_len))
+                        .enabled(*step_undo_len > 0),
+                )
+                .on_hover_text(if *step_undo_len == 0 {
+                    "Nothing to step back to. Stepping is kept track of while \
+                     paused, until the program runs on."
+                } else {
+                    "Undo the last step"
+                });
+            if step_back.clicked {
+                requests.undo_step = true;
+            }
+        });
+    }
+}
+
+// The ROM to run and where they're found, and the variant to run it as.
+fn rom_options(ui: &mut egui::Ui, front: &mut Frontend, chip8: &Chip8) {
+    let emulator = front.emulator;
+    let config = &mut *front.config;
+    let toasts = &mut *front.toasts;
+    let rom_dir = &mut *front.rom_dir;
+    let rom_files = &mut *front.rom_files;
+    let selected_rom = &mut *front.selected_rom;
+    let suggested_variant = &mut *front.suggested_variant;
+    let rom_metadata = front.rom_metadata;
+    if let Some(variant) = *suggested_variant {
+        ui.label(format!(
+            "This looks like a {} program - switch mode?",
+            variant.name()
+        ));
+        ui.horizontal(|ui| {
+            if ui.button("Switch").clicked {
+                emulator.edit(move |machine, _| machine.chip8.set_variant(variant));
+                load_rom(emulator, config, rom_files, selected_rom);
+            }
+            if ui.button("Dismiss").clicked {
+                *suggested_variant = None;
+            }
+        });
+        ui.label("");
+    }
+
+    if rom_files.is_empty() {
+        ui.label(format!(
+            "No ROMs found in {} - choose a folder below, or drag one in",
+            config.rom_dir
+        ));
+    } else {
+        combo_box_with_label(ui, "ROM files", selected_rom.clone(), |ui| {
+            let mut toggled_favorite = None;
+            for f in roms::list_order(rom_files, &config.favorite_roms) {
+                ui.horizontal(|ui| {
+                    let star = if config.favorite_roms.contains(f) {
+                        "\u{2605}"
+                    } else {
+                        "\u{2606}"
+                    };
+                    if ui.button(star).clicked {
+                        toggled_favorite = Some(f.clone());
+                    }
+                    if ui.selectable_value(selected_rom, f.clone(), f).clicked {
+                        load_rom(emulator, config, rom_files, selected_rom);
+                    };
+                });
+            }
+            if let Some(f) = toggled_favorite {
+                if !config.favorite_roms.remove(&f) {
+                    config.favorite_roms.insert(f);
+                }
+                save_config(config);
+            }
+        });
+    }
+    if let Some(metadata) = rom_metadata {
+        if let Some(heading) = metadata.heading() {
+            ui.label(heading);
+        }
+        if let Some(description) = &metadata.description {
+            ui.label(description.as_str());
+        }
+    }
+    ui.horizontal(|ui| {
+        ui.label("ROM folder");
+        ui.text_edit_singleline(rom_dir);
+        if ui.button("Rescan").clicked {
+            config.rom_dir = rom_dir.clone();
+            save_config(config);
+            *rom_files = scan_roms(&config.rom_dir, toasts);
+        }
+    });
+    let mut power_on_rom = config.power_on_rom.clone();
+    let power_on_name = if power_on_rom.is_empty() {
+        BOOT_ROM_NAME.to_string()
+    } else {
+        power_on_rom.clone()
+    };
+    combo_box_with_label(ui, "Power-on ROM", power_on_name, |ui| {
+        ui.selectable_value(&mut power_on_rom, String::new(), BOOT_ROM_NAME);
+        for f in roms::list_order(rom_files, &config.favorite_roms) {
+            ui.selectable_value(&mut power_on_rom, f.clone(), f);
+        }
+    });
+    if power_on_rom != config.power_on_rom {
+        config.power_on_rom = power_on_rom;
+        save_config(config);
+    }
+    if ui
+        .checkbox(
+            &mut config.resume_last_session,
+            "Resume the last session on startup",
+        )
+        .clicked
+    {
+        save_config(config);
+    }
+    let mut variant = chip8.get_variant();
+    combo_box_with_label(ui, "Variant", variant.name(), |ui| {
+        for v in &[Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+            ui.selectable_value(&mut variant, *v, v.name());
+        }
+    });
+    if variant != chip8.get_variant() {
+        emulator.edit(move |machine, _| machine.chip8.set_variant(variant));
+        load_rom(emulator, config, rom_files, selected_rom);
+    }
+}
+
+// How the display looks: its size, colors and effects.
+fn display_options(ui: &mut egui::Ui, front: &mut Frontend, chip8: &Chip8) {
+    let config = &mut *front.config;
+    let mut scale = config.display_scale;
+    combo_box_with_label(ui, "Display scale", format!("{}x", scale), |ui| {
+        for s in &DISPLAY_SCALES {
+            ui.selectable_value(&mut scale, *s, format!("{}x", s));
+        }
+    });
+    if scale != config.display_scale {
+        config.display_scale = scale;
+        save_config(config);
+    }
+    if ui
+        .checkbox(&mut config.integer_scaling, "Integer scaling")
+        .clicked
+    {
+        save_config(config);
+    }
+    let mut palette_name = config.palette.clone();
+    combo_box_with_label(ui, "Palette", palette_name.clone(), |ui| {
+        for p in &palette::PRESETS {
+            ui.selectable_value(&mut palette_name, p.name.to_string(), p.name);
+        }
+        ui.selectable_value(
+            &mut palette_name,
+            palette::CUSTOM.to_string(),
+            palette::CUSTOM,
+        );
+    });
+    if palette_name != config.palette {
+        if let Some(p) = palette::find(&palette_name) {
+            config.pixel_on_color = p.on;
+            config.pixel_off_color = p.off;
+        }
+        config.palette = palette_name;
+        save_config(config);
+    }
+    //Editing either color means the palette is no longer a preset. The | rather
+    //than || is so that both pickers are always shown.
+    if color_picker(ui, "Pixel on color", &mut config.pixel_on_color)
+        | color_picker(ui, "Pixel off color", &mut config.pixel_off_color)
+    {
+        config.palette = palette::CUSTOM.to_string();
+        *front.config_dirty = true;
+    }
+    //XO-CHIP programs can draw in two planes, so there are two more colors.
+    if chip8.get_variant() == Variant::XoChip
+        && (color_picker(ui, "Plane 2 color", &mut config.plane2_color)
+            | color_picker(ui, "Both planes color", &mut config.both_planes_color))
+    {
+        *front.config_dirty = true;
+    }
+    if ui.checkbox(&mut config.crt_effect, "CRT effect").clicked {
+        save_config(config);
+    }
+    if config.crt_effect {
+        let intensity = config.crt_intensity;
+        ui.add(egui::Slider::f32(&mut config.crt_intensity, 0f32..=1f32).text("CRT intensity"));
+        if config.crt_intensity != intensity {
+            *front.config_dirty = true;
+        }
+    }
+    let mut overlay = config.overlay;
+    combo_box_with_label(ui, "Overlay", overlay.name(), |ui| {
+        for o in &[Overlay::Off, Overlay::Grid, Overlay::Scanlines] {
+            ui.selectable_value(&mut overlay, *o, o.name());
+        }
+    });
+    if overlay != config.overlay {
+        config.overlay = overlay;
+        save_config(config);
+    }
+    if config.overlay != Overlay::Off {
+        let intensity = config.overlay_intensity;
+        ui.add(
+            egui::Slider::f32(&mut config.overlay_intensity, 0f32..=1f32).text("Overlay intensity"),
+        );
+        if config.overlay_intensity != intensity {
+            *front.config_dirty = true;
+        }
+    }
+}
+
+// The quirks, which are applied to the machine as soon as they're changed.
+fn quirk_options(ui: &mut egui::Ui, front: &mut Frontend) {
+    let config = &mut *front.config;
+    if ui
+        .checkbox(&mut config.shift_using_vy, "Use Vy for shift operations")
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.increment_i_on_ld,
+            "Increment I on  LD Vx operations",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(&mut config.wrap_sprites_x, "Wrap sprites at the sides")
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.wrap_sprites_y,
+            "Wrap sprites at the top and bottom",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(&mut config.vf_reset, "Reset VF on OR, AND and XOR")
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(&mut config.jump_using_vx, "Use Vx for Bxnn jumps")
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.wait_for_vblank,
+            "Wait for the next frame after drawing",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.strict_opcodes,
+            "Stop on unimplemented instructions",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.fault_on_odd_pc,
+            "Stop when code is run from an odd address",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    //Not how any real machine draws, so most programs will look wrong.
+    if ui
+        .checkbox(
+            &mut config.or_sprites,
+            "Draw sprites with OR instead of XOR (experimental)",
+        )
+        .clicked
+    {
+        apply_config(front.emulator, config);
+        save_config(config);
+    };
+    let mut clock_speed = config.instructions_per_second;
+    combo_box_with_label(ui, "Clock speed", format!("{}/s", clock_speed), |ui| {
+        for c in &CLOCK_SPEEDS {
+            ui.selectable_value(&mut clock_speed, *c, format!("{}/s", c));
+        }
+    });
+    if clock_speed != config.instructions_per_second {
+        config.instructions_per_second = clock_speed;
+        apply_config(front.emulator, config);
+        save_config(config);
+    }
+    let draw_limit_name = |limit: u32| match limit {
+        0 => "Unlimited".to_string(),
+        limit => limit.to_string(),
+    };
+    let mut max_draws = config.max_draws_per_frame;
+    combo_box_with_label(ui, "Sprites per frame", draw_limit_name(max_draws), |ui| {
+        for l in &DRAW_LIMITS {
+            ui.selectable_value(&mut max_draws, *l, draw_limit_name(*l));
+        }
+    });
+    if max_draws != config.max_draws_per_frame {
+        config.max_draws_per_frame = max_draws;
+        apply_config(front.emulator, config);
+        save_config(config);
+    }
+}
+
+// How often the UI is drawn, the sound, and the rest of the settings.
+fn other_options(ui: &mut egui::Ui, front: &mut Frontend, panels: &mut PanelState) {
+    let emulator = front.emulator;
+    let config = &mut *front.config;
+    let config_dirty = &mut *front.config_dirty;
+    let toasts = &mut *front.toasts;
+    let rom_dir = &mut *front.rom_dir;
+    let rom_files = &mut *front.rom_files;
+    let frame_skip_name = |skip: u32| match skip {
+        1 => "Off".to_string(),
+        skip => format!("Draw every {} frames", skip),
+    };
+    let mut frame_skip = config.frame_skip;
+    combo_box_with_label(ui, "Frame skip", frame_skip_name(frame_skip), |ui| {
+        for s in &FRAME_SKIPS {
+            ui.selectable_value(&mut frame_skip, *s, frame_skip_name(*s));
+        }
+    });
+    if frame_skip != config.frame_skip {
+        config.frame_skip = frame_skip;
+        save_config(config);
+    }
+    let mut refresh_rate = config.refresh_rate;
+    combo_box_with_label(ui, "Refresh rate", format!("{}Hz", refresh_rate), |ui| {
+        for r in &REFRESH_RATES {
+            ui.selectable_value(&mut refresh_rate, *r, format!("{}Hz", r));
+        }
+    });
+    if refresh_rate != config.refresh_rate {
+        config.refresh_rate = refresh_rate;
+        save_config(config);
+    }
+    ui.horizontal(|ui| {
+        if ui.checkbox(&mut config.muted, "Mute").clicked {
+            save_config(config);
+        }
+        let mut volume = config.volume as f32;
+        ui.add(egui::Slider::f32(&mut volume, 0f32..=100f32).text("Volume %"));
+        if volume.round() as u32 != config.volume {
+            config.volume = volume.round() as u32;
+            *config_dirty = true;
+        }
+    });
+    let mut waveform = config.waveform;
+    combo_box_with_label(ui, "Waveform", waveform.name(), |ui| {
+        for w in &audio::WAVEFORMS {
+            ui.selectable_value(&mut waveform, *w, w.name());
+        }
+    });
+    if waveform != config.waveform {
+        config.waveform = waveform;
+        save_config(config);
+    }
+    if ui
+        .checkbox(&mut config.pause_when_unfocused, "Pause when unfocused")
+        .clicked
+    {
+        emulator.send(Command::Unfocused(false));
+        save_config(config);
+    };
+    if ui
+        .checkbox(&mut config.idle_sleep, "Use less CPU while paused")
+        .clicked
+    {
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.title_shows_paused,
+            "Show when paused in the window title",
+        )
+        .clicked
+    {
+        save_config(config);
+    };
+    if ui
+        .checkbox(
+            &mut config.auto_reload_rom,
+            "Reload the ROM when it changes on disk",
+        )
+        .clicked
+    {
+        save_config(config);
+    };
+    if !panels.confirm_reset_config {
+        if ui.button("Reset settings to defaults").clicked {
+            panels.confirm_reset_config = true;
+        }
+    } else {
+        ui.horizontal(|ui| {
+            ui.label("Reset all settings?");
+            if ui.button("Yes").clicked {
+                *config = Config::default();
+                apply_config(emulator, config);
+                save_config(config);
+                *rom_dir = config.rom_dir.clone();
+                *rom_files = scan_roms(&config.rom_dir, toasts);
+                panels.confirm_reset_config = false;
+            }
+            if ui.button("No").clicked {
+                panels.confirm_reset_config = false;
+            }
+        });
+    }
+}
+
+// The debugger, and the tools for looking into what a program is doing.
+fn debug(
+    ui: &mut egui::Ui,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    view: &MachineView,
+    meters: &Meters,
+    requests: &mut Requests,
+) {
+    let chip8 = &view.chip8;
+    //Lights up while the tone is playing, even if there's no audio device.
+    ui.horizontal(|ui| {
+        let sound_color = if chip8.get_sound_active() {
+            color::GREEN
+        } else {
+            color::GRAY
+        };
+        ui.colored_label(sound_color, "SOUND");
+        ui.label(format!(
+            "DT: {:3}  ST: {:3}",
+            chip8.get_dt(),
+            chip8.get_st()
+        ));
+    });
+
+    //The subroutines that haven't returned yet, innermost first.
+    let call_stack = chip8.get_call_stack();
+    if call_stack.is_empty() {
+        ui.label("Call stack: empty");
+    } else {
+        ui.label("Call stack:");
+    }
+    for frame in call_stack.iter().rev() {
+        let note = if frame.overwritten {
+            "  (CALL overwritten)"
+        } else {
+            ""
+        };
+        let text = format!(
+            "{:04X}: CALL {:04X}  ->  {:04X}: {}{}",
+            frame.call_site,
+            frame.callee,
+            frame.return_address,
+            chip8::disassemble(chip8.peek_opcode(frame.return_address)),
+            note
+        );
+        let label = egui::Label::new(text).text_style(egui::TextStyle::Monospace);
+        if frame.overwritten {
+            ui.add(label.text_color(color::RED));
+        } else {
+            ui.add(label);
+        }
+    }
+
+    ui.label("");
+    breakpoints(ui, front, panels, chip8);
+    ui.label("");
+    disassembly(ui, front, panels, chip8, requests);
+    ui.label("");
+    debug_files(ui, front, view);
+    //Each skipped upload saves about what an upload takes, so together they show
+    //what skipping is worth.
+    ui.label(format!(
+        "Skipped texture uploads: {}, at {:.2} ms each, {:.0} ms saved",
+        meters.skipped_uploads,
+        meters.avg_upload_time as f32 / 1000f32,
+        (meters.skipped_uploads as u128 * meters.avg_upload_time) as f32 / 1000f32
+    ));
+    //Machine code calls and the like, which are skipped unless the
+    //option to stop on them is on.
+    ui.label(format!(
+        "Skipped unimplemented instructions: {}",
+        chip8.get_skipped_opcodes()
+    ));
+    ui.checkbox(
+        &mut panels.show_pixel_inspector,
+        "Show pixel under the mouse",
+    );
+    ui.checkbox(&mut panels.show_keypad, "Show the keypad over the display");
+    ui.checkbox(
+        &mut panels.show_display_changes,
+        "Highlight pixels changed by the last frame while paused",
+    );
+    if panels.show_display_changes {
+        ui.horizontal(|ui| {
+            ui.colored_label(PIXEL_SET_COLOR, "Set");
+            ui.colored_label(PIXEL_CLEARED_COLOR, "Cleared");
+        });
+    }
+    access_heatmap(ui, front, chip8);
+    code_writes(ui, front, chip8);
+}
+
+// Breakpoints stop the program before the instruction at an address, and pause.
+// Adding one at the same address changes its condition. Enter in either box sets
+// it too.
+fn breakpoints(ui: &mut egui::Ui, front: &mut Frontend, panels: &mut PanelState, chip8: &Chip8) {
+    let mut entered = false;
+    ui.horizontal(|ui| {
+        ui.label("Breakpoint at");
+        entered |= ui
+            .text_edit_singleline(&mut panels.breakpoint_text)
+            .lost_kb_focus;
+    });
+    ui.horizontal(|ui| {
+        ui.label("if");
+        entered |= ui
+            .text_edit_singleline(&mut panels.condition_text)
+            .lost_kb_focus;
+    });
+    entered &= ui.input().key_pressed(egui::Key::Enter);
+    ui.label("Such as V3 == 10 && I > 300 && [3A0] != 0, in hex.");
+    if ui.button("Set breakpoint").clicked || entered {
+        let address = parse_address(&panels.breakpoint_text, chip8);
+        match (address, Condition::parse(&panels.condition_text)) {
+            (Ok(address), Ok(condition)) => {
+                let mut chip8 = chip8.clone();
+                chip8.set_breakpoint(address, condition.clone());
+                save_breakpoints(front.config, &chip8, front.selected_rom);
+                front
+                    .emulator
+                    .edit(move |machine, _| machine.chip8.set_breakpoint(address, condition));
+            }
+            (Err(e), _) | (_, Err(e)) => front.toasts.push(Toast::error(e)),
+        }
+    }
+    let mut edit = None;
+    let mut remove = None;
+    for breakpoint in chip8.get_breakpoints() {
+        ui.horizontal(|ui| {
+            let hit = chip8.get_breakpoint_hit() == Some(breakpoint.address);
+            let text = breakpoint.to_string();
+            if hit {
+                ui.colored_label(color::RED, format!("{} - stopped here", text));
+            } else {
+                ui.label(text);
+            }
+            if ui.button("Edit").clicked {
+                edit = Some(breakpoint.clone());
+            }
+            if ui.button("Remove").clicked {
+                remove = Some(breakpoint.address);
+            }
+        });
+    }
+    if let Some(breakpoint) = edit {
+        panels.breakpoint_text = format!("{:03X}", breakpoint.address);
+        panels.condition_text = breakpoint.condition.to_string();
+    }
+    if let Some(address) = remove {
+        let mut chip8 = chip8.clone();
+        chip8.clear_breakpoint(address);
+        save_breakpoints(front.config, &chip8, front.selected_rom);
+        front
+            .emulator
+            .edit(move |machine, _| machine.chip8.clear_breakpoint(address));
+    }
+    if ui
+        .checkbox(
+            &mut front.config.save_breakpoints,
+            "Save breakpoints for each ROM",
+        )
+        .clicked
+    {
+        if front.config.save_breakpoints {
+            save_breakpoints(front.config, chip8, front.selected_rom);
+        } else {
+            save_config(front.config);
+        }
+    }
+}
+
+// The instructions from the PC on, marking the ones with breakpoints, and the ones
+// that have been rewritten since they were run when looking for self-modifying
+// code. They're read from memory every frame, so always show what will run now.
+// Clicking one runs to it, like Run to.
+fn disassembly(
+    ui: &mut egui::Ui,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    chip8: &Chip8,
+    requests: &mut Requests,
+) {
+    ui.label("Disassembly (click one to run to it):");
+    let pc = chip8.get_pc();
+    for i in 0..DISASSEMBLY_LINES {
+        let address = pc.wrapping_add(i * 2);
+        let opcode = chip8.peek_opcode(address);
+        let has_breakpoint = chip8
+            .get_breakpoints()
+            .iter()
+            .any(|breakpoint| breakpoint.address == address);
+        let patched = chip8
+            .get_code_writes()
+            .map(|writes| writes.is_patched(address) || writes.is_patched(address.wrapping_add(1)))
+            .unwrap_or(false);
+        let text = format!(
+            "{}{} {:04X}: {:04X}  {}",
+            if has_breakpoint { "*" } else { " " },
+            if patched { "~" } else { " " },
+            address,
+            opcode,
+            chip8::disassemble(opcode)
+        );
+        if ui.selectable_label(i == 0, text).clicked {
+            requests.run_to = Some(address);
+        }
+    }
+    if chip8.get_code_writes().is_some() {
+        ui.label("* is a breakpoint, ~ was rewritten after it ran");
+    }
+    //Runs straight to an address without a breakpoint, pausing first.
+    ui.horizontal(|ui| {
+        ui.label("Run to");
+        ui.text_edit_singleline(&mut panels.run_to_text);
+        if ui.button("Go").clicked {
+            match parse_address(&panels.run_to_text, chip8) {
+                Ok(address) => requests.run_to = Some(address),
+                Err(e) => front.toasts.push(Toast::error(e)),
+            }
+        }
+    });
+}
+
+// What can be saved to a file or the clipboard, or loaded back: the state, memory,
+// input and traces.
+fn debug_files(ui: &mut egui::Ui, front: &mut Frontend, view: &MachineView) {
+    let emulator = front.emulator;
+    let video_subsystem = front.video_subsystem;
+    let config = &mut *front.config;
+    let toasts = &mut *front.toasts;
+    let rom_files = &mut *front.rom_files;
+    let selected_rom = &mut *front.selected_rom;
+    let input_rom = &mut *front.input_rom;
+    let trace_format = &mut *front.trace_format;
+    let MachineView {
+        chip8,
+        tracing,
+        recording_input,
+        replay_progress,
+        ..
+    } = view;
+    //egui_sdl's clipboard is only for egui text fields, so SDL's is used here.
+    if ui.button("Copy state to clipboard").clicked {
+        let dump = state_dump(chip8, selected_rom);
+        match video_subsystem.clipboard().set_clipboard_text(&dump) {
+            Ok(()) => toasts.push(Toast::info("Copied state to clipboard")),
+            Err(e) => toasts.push(Toast::error(format!(
+                "Failed to copy state to clipboard: {}",
+                e
+            ))),
+        }
+    }
+
+    ui.horizontal(|ui| {
+        let path = memory_dump_path(selected_rom);
+        if ui.button("Dump memory").clicked {
+            let result = fs::create_dir_all(DUMP_DIR).and_then(|()| chip8.dump_memory(&path));
+            toasts.push(match result {
+                Ok(()) => Toast::info(format!("Dumped memory to {}", path)),
+                Err(e) => Toast::error(format!("Failed to dump memory: {}", e)),
+            });
+        }
+        if ui.button("Load memory").clicked {
+            emulator.edit(move |machine, toasts| {
+                toasts.push(match machine.chip8.load_memory(&path) {
+                    Ok(()) => Toast::info(format!("Loaded memory from {}", path)),
+                    Err(e) => Toast::error(format!("Failed to load memory: {}", e)),
+                })
+            });
+        }
+    });
+
+    //Input is recorded and replayed from the start of the ROM, so both
+    //reset it first.
+    ui.horizontal(|ui| {
+        if *recording_input {
+            if ui.button("Stop recording input").clicked {
+                let input_rom = input_rom.clone();
+                emulator.edit(move |machine, toasts| {
+                    if let Some(recorder) = machine.input_recorder.take() {
+                        save_input_log(&recorder.finish(), &input_rom, toasts);
+                    }
+                });
+            }
+        } else if replay_progress.is_some() {
+            if ui.button("Stop replay").clicked {
+                emulator.edit(|machine, _| machine.input_player = None);
+            }
+        } else {
+            if ui.button("Record input").clicked {
+                *input_rom = selected_rom.clone();
+                load_rom(emulator, config, rom_files, selected_rom);
+                let seed = rand::random();
+                emulator.edit(move |machine, _| {
+                    let recorder = InputRecorder::start(&mut machine.chip8, seed);
+                    machine.input_recorder = Some(recorder);
+                });
+            }
+            if ui.button("Replay input").clicked {
+                let path = input_log_path(selected_rom);
+                match fs::read(&path).and_then(|bytes| InputLog::from_bytes(&bytes)) {
+                    Ok(log) => {
+                        load_rom(emulator, config, rom_files, selected_rom);
+                        emulator.edit(move |machine, toasts| {
+                            match InputPlayer::start(&mut machine.chip8, log) {
+                                Ok(player) => machine.input_player = Some(player),
+                                Err(e) => toasts.push(Toast::error(format!(
+                                    "Failed to replay {}: {}",
+                                    path, e
+                                ))),
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        toasts.push(Toast::error(format!("Failed to replay {}: {}", path, e)))
+                    }
+                }
+            }
+        }
+    });
+
+    //Every instruction can be written to a file, as text or as CSV.
+    ui.horizontal(|ui| {
+        let label = if *tracing {
+            "Stop tracing"
+        } else {
+            "Trace to file"
+        };
+        if ui.button(label).clicked {
+            let (format, rom_name) = (*trace_format, selected_rom.clone());
+            emulator.edit(move |machine, toasts| {
+                let Machine { chip8, trace, .. } = machine;
+                toggle_trace(trace, chip8, format, &rom_name, toasts);
+            });
+        }
+        if !*tracing {
+            for format in &[TraceFormat::Text, TraceFormat::Csv] {
+                ui.selectable_value(trace_format, *format, format.name());
+            }
+        }
+    });
+}
+
+// Which parts of memory are being read, written and run, and how much of the ROM
+// has been.
+fn access_heatmap(ui: &mut egui::Ui, front: &mut Frontend, chip8: &Chip8) {
+    let mut track_accesses = chip8.get_access_counts().is_some();
+    if ui
+        .checkbox(&mut track_accesses, "Show memory access heatmap")
+        .clicked
+    {
+        front
+            .emulator
+            .edit(move |machine, _| machine.chip8.set_access_tracking(track_accesses));
+    }
+    if !track_accesses {
+        return;
+    }
+    ui.add(Image::new(
+        front.textures.heatmap,
+        vec2(HEATMAP_WIDTH as f32 * 4f32, HEATMAP_HEIGHT as f32 * 4f32),
+    ));
+    ui.label("Red = write, green = execute, blue = read.");
+    ui.horizontal(|ui| {
+        if ui.button("Reset counts").clicked {
+            front
+                .emulator
+                .edit(|machine, _| machine.chip8.reset_access_counts());
+        }
+        //Which of the ROM has run since the counts were reset.
+        if ui.button("Export coverage").clicked {
+            front
+                .toasts
+                .push(match coverage::save_report(chip8, front.selected_rom) {
+                    Ok(path) => Toast::info(format!("Saved coverage report to {}", path)),
+                    Err(e) => Toast::error(format!("Failed to save coverage report: {}", e)),
+                });
+        }
+    });
+}
+
+// Writes into the ROM, which are usually self-modifying code.
+fn code_writes(ui: &mut egui::Ui, front: &mut Frontend, chip8: &Chip8) {
+    let mut track_code_writes = chip8.get_code_writes().is_some();
+    if ui
+        .checkbox(&mut track_code_writes, "Look for self-modifying code")
+        .clicked
+    {
+        front.emulator.edit(move |machine, _| {
+            machine
+                .chip8
+                .set_code_write_tracking(track_code_writes, false)
+        });
+    }
+    let writes = match chip8.get_code_writes() {
+        Some(writes) => writes,
+        None => return,
+    };
+    let mut log_code_writes = writes.log.is_some();
+    ui.label(format!("Writes into the ROM: {}", writes.count));
+    if let Some(log) = &writes.log {
+        for write in log.iter().rev().take(CODE_WRITES_SHOWN) {
+            ui.add(egui::Label::new(code_write_text(write)).text_style(egui::TextStyle::Monospace));
+        }
+        if log.len() == CODE_WRITE_LOG_LIMIT {
+            ui.label("The log is full, but writes are still counted.");
+        }
+    }
+    ui.label(format!(
+        "Writes over code that had run: {}",
+        writes.patch_count
+    ));
+    for patch in writes.patches.iter().rev().take(CODE_WRITES_SHOWN) {
+        ui.add(egui::Label::new(code_write_text(patch)).text_style(egui::TextStyle::Monospace));
+    }
+    ui.horizontal(|ui| {
+        if ui.checkbox(&mut log_code_writes, "Log the writes").clicked {
+            front.emulator.edit(move |machine, _| {
+                machine.chip8.set_code_write_tracking(true, log_code_writes)
+            });
+        }
+        if ui.button("Reset").clicked {
+            front
+                .emulator
+                .edit(|machine, _| machine.chip8.reset_code_writes());
+        }
+    });
+}
+
+// The graph of how long each frame's work took, not counting the wait for the next
+// frame, and how fast the emulator is keeping up.
+fn performance(ui: &mut egui::Ui, view: &MachineView, meters: &Meters) {
+    let frame_times = meters.frame_times;
+    draw_frame_graph(ui, frame_times);
+    if let (Some(p50), Some(p99)) = (frame_times.percentile(50f32), frame_times.percentile(99f32)) {
+        ui.label(format!(
+            "Frame work p50: {:.1} ms  p99: {:.1} ms  max: {:.1} ms",
+            p50,
+            p99,
+            frame_times.max()
+        ));
+    }
+    ui.label(format!(
+        "Emulator: {} frames/s of 60",
+        meters.emulation_rate
+    ));
+    ui.label(format!(
+        "Frames skipped for falling behind: {}",
+        view.frames_skipped
+    ));
+}
+
+// A page of memory, and a search for byte patterns in it.
+fn memory(ui: &mut egui::Ui, toasts: &mut ToastQueue, panels: &mut PanelState, chip8: &Chip8) {
+    let memory_size = chip8.get_memory_size();
+    let page = MEMORY_ROW_LENGTH * MEMORY_PAGE_ROWS;
+    if panels.memory_view_addr >= memory_size {
+        panels.memory_view_addr = 0;
+    }
+    ui.horizontal(|ui| {
+        if ui.button("<").clicked {
+            panels.memory_view_addr = panels.memory_view_addr.saturating_sub(page);
+        }
+        if ui.button(">").clicked && panels.memory_view_addr + page < memory_size {
+            panels.memory_view_addr += page;
+        }
+    });
+    for row in 0..MEMORY_PAGE_ROWS {
+        let addr = panels.memory_view_addr + row * MEMORY_ROW_LENGTH;
+        if addr >= memory_size {
+            break;
+        }
+        let bytes: Vec<u8> = (addr..addr + MEMORY_ROW_LENGTH)
+            .map(|a| chip8.peek(a as u16))
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&b| {
+                if b == b' ' || b.is_ascii_graphic() {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        ui.add(
+            egui::Label::new(format!("{:04X}: {} {}", addr, hex.join(" "), ascii))
+                .text_style(egui::TextStyle::Monospace),
+        );
+    }
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut panels.find_text);
+        ui.selectable_value(&mut panels.find_ascii, false, "Hex");
+        ui.selectable_value(&mut panels.find_ascii, true, "ASCII");
+        if ui.button("Find").clicked {
+            match parse_pattern(&panels.find_text, panels.find_ascii) {
+                Ok(pattern) => {
+                    panels.find_results = chip8.find_bytes(&pattern);
+                    match panels.find_results.first() {
+                        Some(&addr) => {
+                            panels.memory_view_addr =
+                                addr as usize / MEMORY_ROW_LENGTH * MEMORY_ROW_LENGTH;
+                        }
+                        None => toasts.push(Toast::info("Not found")),
+                    }
+                }
+                Err(e) => toasts.push(Toast::error(e)),
+            }
+        }
+    });
+    if !panels.find_results.is_empty() {
+        ui.label(format!("Found at {} addresses:", panels.find_results.len()));
+        ui.horizontal(|ui| {
+            for &addr in panels.find_results.iter().take(MAX_FIND_RESULTS) {
+                if ui.button(format!("{:04X}", addr)).clicked {
+                    panels.memory_view_addr = addr as usize / MEMORY_ROW_LENGTH * MEMORY_ROW_LENGTH;
+                }
+            }
+        });
+    }
+}
+
+// The ROM's cheats, and a search for where a value is kept to make new ones from.
+fn cheat_options(
+    ui: &mut egui::Ui,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    view: &MachineView,
+) {
+    let chip8 = &view.chip8;
+    let emulator = front.emulator;
+    let toasts = &mut *front.toasts;
+    let mut rom_cheats = view.cheats.clone();
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut panels.cheat_text);
+        if ui.button("Add").clicked {
+            let memory_size = chip8.get_memory_size();
+            match cheats::parse(
+                &panels.cheat_text,
+                memory_size,
+                panels.allow_interpreter_area,
+            ) {
+                Ok(new_cheats) => {
+                    rom_cheats.extend(new_cheats);
+                    panels.cheat_text.clear();
+                    changed = true;
+                }
+                Err(e) => toasts.push(Toast::error(e)),
+            }
+        }
+    });
+    ui.label("Enter address=value in hex, like 3A2=FF.");
+    ui.checkbox(
+        &mut panels.allow_interpreter_area,
+        "Allow addresses below 200",
+    );
+
+    let mut removed = None;
+    for (i, cheat) in rom_cheats.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            let name = format!("{:03X} = {:02X}", cheat.address, cheat.value);
+            changed |= ui.checkbox(&mut cheat.enabled, name).clicked;
+            changed |= ui.checkbox(&mut cheat.frozen, "Freeze").clicked;
+            if ui.button("Apply").clicked {
+                let cheat = cheat.clone();
+                emulator.edit(move |machine, _| cheat.apply(&mut machine.chip8));
+            }
+            if ui.button("x").clicked {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        rom_cheats.remove(i);
+        changed = true;
+    }
+
+    //Finding where a value is kept, by searching again each time it changes.
+    ui.collapsing("Search memory", |ui| {
+        if ui.button("New search").clicked {
+            panels.scanner = Some(Scanner::new(chip8));
+        }
+        if let Some(scanner) = panels.scanner.as_mut() {
+            let scan_text = &mut panels.scan_text;
+            let mut filter = None;
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(scan_text);
+                if ui.button("Equals").clicked {
+                    match u8::from_str_radix(scan_text.trim(), 16) {
+                        Ok(value) => filter = Some(Filter::Equals(value)),
+                        Err(_) => toasts.push(Toast::error(format!("{} isn't a byte", scan_text))),
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Increased").clicked {
+                    filter = Some(Filter::Increased);
+                }
+                if ui.button("Decreased").clicked {
+                    filter = Some(Filter::Decreased);
+                }
+                if ui.button("Unchanged").clicked {
+                    filter = Some(Filter::Unchanged);
+                }
+            });
+            if let Some(filter) = filter {
+                scanner.search(chip8, filter);
+            }
+            let candidates = scanner.candidates();
+            ui.label(format!("{} addresses left.", candidates.len()));
+            if candidates.len() <= MAX_SCAN_RESULTS {
+                ui.label("Click one to freeze it as a cheat.");
+                for &address in candidates {
+                    let value = chip8.peek(address);
+                    let name = format!("{:03X} = {:02X}", address, value);
+                    if ui.button(name).clicked {
+                        rom_cheats.push(cheats::Cheat {
+                            address,
+                            value,
+                            enabled: true,
+                            frozen: true,
+                        });
+                        changed = true;
+                    }
+                }
+            }
+        }
+    });
+    if changed {
+        let cheats = rom_cheats.clone();
+        emulator.edit(move |machine, _| machine.cheats = cheats);
+        if let Err(e) = cheats::save(chip8.get_rom_hash(), &rom_cheats) {
+            toasts.push(Toast::error(format!("Failed to save cheats: {}", e)));
+        }
+    }
+}
+
+// GIF recording, save states and rewinding.
+fn recording_options(
+    ui: &mut egui::Ui,
+    front: &mut Frontend,
+    panels: &mut PanelState,
+    view: &MachineView,
+) {
+    let Frontend {
+        emulator,
+        config,
+        toasts,
+        selected_rom,
+        ..
+    } = front;
+    let chip8 = &view.chip8;
+    ui.horizontal(|ui| {
+        let label = if view.recording {
+            "Stop recording"
+        } else {
+            "Record GIF"
+        };
+        if ui.button(label).clicked {
+            let (config, rom_name) = (config.clone(), selected_rom.clone());
+            emulator.edit(move |machine, toasts| {
+                toggle_recording(&mut machine.recording, &config, &rom_name, toasts)
+            });
+        }
+        let mut scale = config.recording_scale;
+        combo_box_with_label(ui, "Scale", format!("{}x", scale), |ui| {
+            for s in &RECORDING_SCALES {
+                ui.selectable_value(&mut scale, *s, format!("{}x", s));
+            }
+        });
+        if scale != config.recording_scale {
+            config.recording_scale = scale;
+            save_config(config);
+        }
+    });
+
+    //Save states belong to the ROM, so the slots are for the ROM that's running.
+    ui.horizontal(|ui| {
+        let rom_hash = chip8.get_rom_hash();
+        combo_box_with_label(
+            ui,
+            "State",
+            slot_label(rom_hash, panels.active_slot),
+            |ui| {
+                for slot in 0..states::SLOT_COUNT {
+                    ui.selectable_value(&mut panels.active_slot, slot, slot_label(rom_hash, slot));
+                }
+            },
+        );
+        if ui.button("Save").clicked {
+            save_state(chip8, panels.active_slot, toasts);
+        }
+        if ui.button("Load").clicked {
+            let slot = panels.active_slot;
+            emulator.edit(move |machine, toasts| load_state(&mut machine.chip8, slot, toasts));
+        }
+    });
+    let rewind_name = |seconds: u32| match seconds {
+        0 => "Off".to_string(),
+        seconds => format!("{} seconds", seconds),
+    };
+    let mut rewind_seconds = config.rewind_seconds;
+    combo_box_with_label(ui, "Rewind", rewind_name(rewind_seconds), |ui| {
+        for s in &rewind::REWIND_SECONDS {
+            ui.selectable_value(&mut rewind_seconds, *s, rewind_name(*s));
+        }
+    });
+    if rewind_seconds != config.rewind_seconds {
+        config.rewind_seconds = rewind_seconds;
+        apply_config(emulator, config);
+        save_config(config);
+    }
+}
+
+// The keyboard layout, and which keys are which.
+fn key_options(ui: &mut egui::Ui, config: &mut Config) {
+    let mut layout = config.keyboard_layout;
+    combo_box_with_label(ui, "Keys", layout.name(), |ui| {
+        for l in &keymap::LAYOUTS {
+            ui.selectable_value(&mut layout, *l, l.name());
+        }
+    });
+    if layout != config.keyboard_layout {
+        config.keyboard_layout = layout;
+        save_config(config);
+    }
+    ui.label(keypad_help(config.keyboard_layout));
+    ui.label("F1 = All of the shortcuts.");
+}
+
+// The emulator's setup, for bug reports.
+fn about_window(ctx: &egui::CtxRef, front: &mut Frontend, panels: &mut PanelState, chip8: &Chip8) {
+    let Frontend {
+        video_subsystem,
+        toasts,
+        ..
+    } = front;
+    egui::Window::new("About")
+        .open(&mut panels.show_about)
+        .show(ctx, |ui| {
+            let about = about_text(chip8);
+            for line in about.lines() {
+                ui.label(line);
+            }
+            ui.label("");
+            if ui.button("Copy to clipboard").clicked {
+                match video_subsystem.clipboard().set_clipboard_text(&about) {
+                    Ok(()) => toasts.push(Toast::info("Copied to clipboard")),
+                    Err(e) => {
+                        toasts.push(Toast::error(format!("Failed to copy to clipboard: {}", e)))
+                    }
+                }
+            }
+        });
+}
+
+// All of the shortcuts, which close on any key.
+fn help_window(ctx: &egui::CtxRef, layout: Layout) {
+    egui::Window::new("Shortcuts").show(ctx, |ui| {
+        ui.label(keypad_help(layout));
+        for category in &hotkeys::CATEGORIES {
+            ui.label("");
+            ui.colored_label(color::WHITE, category.name());
+            for binding in hotkeys::active_bindings(*category, layout) {
+                ui.label(format!(
+                    "{} = {}",
+                    binding.key_names(layout),
+                    binding.description
+                ));
+            }
+        }
+        ui.label("");
+        ui.label("Press any key to close.");
+    });
+}
+
+// The display is centred in the rest of the window, letterboxed to keep its shape.
+// Returns where it is, and the area it's centred in.
+fn display(
+    ctx: &egui::CtxRef,
+    front: &Frontend,
+    panels: &PanelState,
+    chip8: &Chip8,
+    faulted: bool,
+    direct_display: bool,
+) -> (Rect, Rect) {
+    let config = &*front.config;
+    let pixels_per_point = front.pixels_per_point;
+    let display_texture = if config.crt_effect {
+        front.textures.crt
+    } else {
+        front.textures.display
+    };
+    let mut display_rect = Rect::from_min_size(Pos2::new(0f32, 0f32), vec2(0f32, 0f32));
+    let mut display_area = display_rect;
+    egui::CentralPanel::default().show(ctx, |ui| {
+        let area = ui.max_rect();
+        display_area = area;
+        let rect = display_layout(area, pixels_per_point, config.integer_scaling);
+        let size = rect.size();
+        display_rect = rect;
+        if direct_display {
+            return;
+        }
+        let response = ui.put(rect, Image::new(display_texture, size));
+
+        if panels.show_pixel_inspector {
+            let width = chip8.get_display_width();
+            let height = chip8.get_display_height();
+            let coords = ui
+                .input()
+                .mouse
+                .pos
+                .and_then(|pos| display_coords(rect, pos, width, height));
+            if let Some((x, y)) = coords {
+                let pixel = chip8.get_display_data()[y * width + x];
+                let state = if chip8.get_variant() == Variant::XoChip {
+                    format!("plane value {}", pixel)
+                } else if pixel > 0 {
+                    "on".to_string()
+                } else {
+                    "off".to_string()
+                };
+                response.on_hover_text(format!("({}, {}): {}", x, y, state));
+            }
+        }
+
+        draw_overlay(
+            ui,
+            rect,
+            config,
+            chip8.get_display_width(),
+            chip8.get_display_height(),
+            pixels_per_point,
+        );
+
+        if faulted || panels.show_help || panels.pause_menu.is_some() {
+            ui.painter()
+                .rect_filled(rect, 0f32, color::srgba(0, 0, 0, 160));
+        }
+    });
+    (display_rect, display_area)
+}
+
+// The labels drawn over the display: the display only hint, how to get ROMs when
+// there aren't any, and the keypad.
+fn display_labels(
+    ctx: &egui::CtxRef,
+    front: &Frontend,
+    panels: &PanelState,
+    chip8: &Chip8,
+    display_rect: Rect,
+    display_area: Rect,
+) {
+    let margin = DISPLAY_MARGIN as f32;
+    let hint_time = panels.display_only_since.elapsed();
+    if panels.display_only && hint_time < DISPLAY_ONLY_HINT_TIME {
+        let fade = 1f32 - hint_time.as_secs_f32() / DISPLAY_ONLY_HINT_TIME.as_secs_f32();
+        let alpha = (fade * 255f32) as u8;
+        egui::Area::new("display_only_hint")
+            .fixed_pos(display_area.left_top() + vec2(margin, margin))
+            .show(ctx, |ui| {
+                ui.colored_label(color::srgba(alpha, alpha, alpha, alpha), "Tab for menu");
+            });
+    }
+
+    //With no ROMs there's only the boot ROM to run, so say how to get some.
+    if front.rom_files.is_empty() {
+        egui::Area::new("no_roms")
+            .fixed_pos(display_rect.left_bottom() + vec2(margin, -3f32 * margin))
+            .show(ctx, |ui| {
+                ui.colored_label(
+                    color::WHITE,
+                    format!("No ROMs found in {} - drag one in", front.config.rom_dir),
+                );
+            });
+    }
+
+    if panels.show_keypad || panels.holding_keypad_key {
+        egui::Area::new("keypad")
+            .fixed_pos(display_rect.left_top() + vec2(margin, margin))
+            .show(ctx, |ui| {
+                keypad_state(ui, chip8.get_key_pressed(), front.config.keyboard_layout);
+            });
+    }
+}
+
+// The toasts, newest at the bottom, each with a button to dismiss it.
+fn toast_list(ctx: &egui::CtxRef, toasts: &mut ToastQueue, display_area: Rect) {
+    let margin = DISPLAY_MARGIN as f32;
+    let pos = display_area.right_top() + vec2(-TOAST_WIDTH - margin, margin);
+    let mut dismissed = None;
+    egui::Area::new("toasts").fixed_pos(pos).show(ctx, |ui| {
+        for (i, toast) in toasts.iter().enumerate() {
+            let color = match toast.severity {
+                Severity::Info => color::WHITE,
+                Severity::Warning => color::YELLOW,
+                Severity::Error => color::RED,
+            };
+            ui.horizontal(|ui| {
+                if ui.button("x").clicked {
+                    dismissed = Some(i);
+                }
+                ui.colored_label(color, toast.message.as_str());
+            });
+        }
+    });
+    if let Some(i) = dismissed {
+        toasts.dismiss(i);
+    }
+}
+
+// The pause menu, in the middle of the display. What's clicked on is done once the
+// events have been handled, along with what's chosen with the keyboard.
+fn pause_menu_list(
+    ctx: &egui::CtxRef,
+    front: &Frontend,
+    menu: &PauseMenu,
+    display_rect: Rect,
+    requests: &mut Requests,
+) {
+    let pos = display_rect.center() - vec2(PAUSE_MENU_WIDTH, PAUSE_MENU_HEIGHT) / 2f32;
+    egui::Area::new("pause_menu")
+        .fixed_pos(pos)
+        .show(ctx, |ui| {
+            if menu.is_choosing_rom() {
+                ui.colored_label(color::WHITE, "Load ROM (ESC to go back)");
+                let roms = roms::list_order(front.rom_files, &front.config.favorite_roms);
+                for (i, rom) in roms.iter().enumerate() {
+                    if ui
+                        .selectable_label(i == menu.selected(), rom.as_str())
+                        .clicked
+                    {
+                        requests.menu_rom = Some((*rom).clone());
+                    }
+                }
+            } else {
+                ui.colored_label(color::WHITE, "PAUSED");
+                for (i, item) in pause_menu::ITEMS.iter().enumerate() {
+                    if ui
+                        .selectable_label(i == menu.selected(), item.label())
+                        .clicked
+                    {
+                        requests.menu_choice = Some(*item);
+                    }
+                }
+            }
+        });
+}
+
+// Asks whether to carry on from the ROM's autosave, if it has one.
+fn resume_window(ctx: &egui::CtxRef, front: &mut Frontend, panels: &mut PanelState) {
+    let resume_state = match &panels.resume_state {
+        Some(resume_state) => resume_state,
+        None => return,
+    };
+    let mut answered = false;
+    egui::Window::new("Resume").show(ctx, |ui| {
+        ui.label("Resume from where you left off?");
+        ui.horizontal(|ui| {
+            if ui.button("Resume").clicked {
+                let (state, rom_name) = (resume_state.clone(), front.selected_rom.clone());
+                front.emulator.edit(move |machine, toasts| {
+                    toasts.push(match machine.chip8.load_state(&state) {
+                        Ok(()) => Toast::info(format!("Resumed {}", rom_name)),
+                        Err(e) => Toast::error(format!("Failed to resume: {}", e)),
+                    })
+                });
+                answered = true;
+            }
+            if ui.button("Start over").clicked {
+                answered = true;
+            }
+        });
+    });
+    if answered {
+        panels.resume_state = None;
+    }
+}
+
+// Says what went wrong, or that the program has ended, with what can be done about
+// it.
+fn fault_window(ctx: &egui::CtxRef, front: &mut Frontend, panels: &mut PanelState, fault: Fault) {
+    let title = if fault.kind == FaultKind::ProgramEnded {
+        "Program ended"
+    } else {
+        "Fault"
+    };
+    egui::Window::new(title).show(ctx, |ui| {
+        if fault.kind == FaultKind::ProgramEnded {
+            ui.label("The program has finished.");
+            ui.label(format!("It's waiting in a loop at {:04X}.", fault.pc));
+        } else {
+            ui.label(fault.kind.description());
+            ui.label(format!(
+                "PC: {:04X}  Opcode: {:04X}",
+                fault.pc, fault.opcode
+            ));
+            ui.label(chip8::disassemble(fault.opcode));
+        }
+        ui.label("");
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked {
+                load_rom(
+                    front.emulator,
+                    front.config,
+                    front.rom_files,
+                    front.selected_rom,
+                );
+            }
+            if fault.kind.can_continue() && ui.button("Ignore and continue").clicked {
+                front.emulator.edit(|machine, _| machine.chip8.skip_fault());
+                front.toasts.push(Toast::warning(format!(
+                    "Skipped {} at {:04X}",
+                    chip8::disassemble(fault.opcode),
+                    fault.pc
+                )));
+            }
+            if ui.button("Close").clicked {
+                panels.closed_fault = Some(fault);
+            }
+        });
+    });
+}
+
+// Helper function to show a color picker for a color in the config. Returns true
+// if the color was changed.
+fn color_picker(ui: &mut egui::Ui, label: &str, rgb: &mut [u8; 3]) -> bool {
+    let mut srgba = to_srgba(*rgb);
+    ui.horizontal(|ui| {
+        ui.color_edit_button_srgba(&mut srgba);
+        ui.label(label);
+    });
+    let new_rgb = [srgba.0[0], srgba.0[1], srgba.0[2]];
+    if new_rgb != *rgb {
+        *rgb = new_rgb;
+        true
+    } else {
+        false
+    }
+}
+
+// Helper function to work out the size of the display image in points. With integer
+// scaling it's the largest whole multiple of the Chip8 display that fits in the
+// available space, so that every Chip8 pixel is the same size on screen, and
+// otherwise as big as fits. If the space is smaller than the Chip8 display it's just
+// shrunk to fit.
+fn display_size(available: egui::Vec2, pixels_per_point: f32, integer: bool) -> egui::Vec2 {
+    let width = available.x.max(0f32) * pixels_per_point;
+    let height = available.y.max(0f32) * pixels_per_point;
+    let scale = (width / CHIP8_DISPLAY_WIDTH as f32).min(height / CHIP8_DISPLAY_HEIGHT as f32);
+    let scale = if integer && scale >= 1f32 {
+        scale.floor()
+    } else {
+        scale
+    };
+    vec2(
+        CHIP8_DISPLAY_WIDTH as f32 * scale,
+        CHIP8_DISPLAY_HEIGHT as f32 * scale,
+    ) / pixels_per_point
+}
+
+// Helper function to lay out the display in the area it's given, which is the rest of
+// the window next to the controls, or the whole window in display only mode. It's
+// centred, at the size display_size() works out.
+fn display_layout(area: Rect, pixels_per_point: f32, integer: bool) -> Rect {
+    Rect::from_center_size(
+        area.center(),
+        display_size(area.size(), pixels_per_point, integer),
+    )
+}
+
+// Helper function to show how fast the emulator is running against its 60Hz target,
+// as a percentage that's colored when it's falling behind.
+fn speed_label(ui: &mut egui::Ui, frames_per_second: u64) {
+    let percent = frames_per_second * 100 / 60;
+    let text = format!("Speed: {}%", percent);
+    if percent < SPEED_ALERT_PERCENT {
+        ui.colored_label(color::RED, text);
+    } else if percent < SPEED_WARNING_PERCENT {
+        ui.colored_label(color::srgba(255, 176, 0, 255), text);
+    } else {
+        ui.label(text);
+    }
+}
+
+// Helper function to draw a graph of the recent frame times, newest on the right.
+// It's scaled to the longest frame, and a line marks 60Hz.
+fn draw_frame_graph(ui: &mut egui::Ui, frame_times: &FrameTimes) {
+    let rect = ui.allocate_space(vec2(FRAME_GRAPH_WIDTH, FRAME_GRAPH_HEIGHT));
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0f32, color::srgba(0, 0, 0, 160));
+    let full_scale = frame_times.max().max(1000f32 / 30f32);
+    let y = |ms: f32| rect.bottom() - rect.height() * ms / full_scale;
+    let target = y(1000f32 / 60f32);
+    painter.line_segment(
+        [
+            Pos2::new(rect.left(), target),
+            Pos2::new(rect.right(), target),
+        ],
+        egui::Stroke::new(1f32, color::GRAY),
+    );
+    let step = rect.width() / (FRAME_TIME_SAMPLES - 1) as f32;
+    let points: Vec<Pos2> = frame_times
+        .samples()
+        .enumerate()
+        .map(|(i, ms)| Pos2::new(rect.left() + i as f32 * step, y(ms)))
+        .collect();
+    for line in points.windows(2) {
+        painter.line_segment([line[0], line[1]], egui::Stroke::new(1f32, color::GREEN));
+    }
+}
+
+// Helper function to draw the grid or scanline overlay over the display image. The
+// lines are one screen pixel wide and fall between the Chip8 pixels, whatever the
+// scale and resolution.
+fn draw_overlay(
+    ui: &egui::Ui,
+    rect: Rect,
+    config: &Config,
+    display_width: usize,
+    display_height: usize,
+    pixels_per_point: f32,
+) {
+    if config.overlay == Overlay::Off {
+        return;
+    }
+    let alpha = (config.overlay_intensity.max(0f32).min(1f32) * 255f32) as u8;
+    let stroke = egui::Stroke::new(1f32 / pixels_per_point, color::srgba(0, 0, 0, alpha));
+    let painter = ui.painter();
+
+    for row in 1..display_height {
+        let y = rect.top() + rect.height() * row as f32 / display_height as f32;
+        painter.line_segment(
+            [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+            stroke,
+        );
+    }
+    if config.overlay == Overlay::Grid {
+        for col in 1..display_width {
+            let x = rect.left() + rect.width() * col as f32 / display_width as f32;
+            painter.line_segment(
+                [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                stroke,
+            );
+        }
+    }
+}
+
+// Helper function to map a position on the display image back to the Chip8 pixel
+// under it, for the current resolution. Returns None if it's outside the image.
+fn display_coords(rect: Rect, pos: Pos2, width: usize, height: usize) -> Option<(usize, usize)> {
+    if !rect.contains(pos) || rect.width() <= 0f32 || rect.height() <= 0f32 {
+        return None;
+    }
+    let x = ((pos.x - rect.left()) / rect.width() * width as f32) as usize;
+    let y = ((pos.y - rect.top()) / rect.height() * height as f32) as usize;
+    Some((x.min(width - 1), y.min(height - 1)))
+}
+
+// Helper function to parse a pattern to find in memory. It's either text, or pairs
+// of hex digits which can be spaced out, such as "F0 90 F0".
+fn parse_pattern(text: &str, ascii: bool) -> Result<Vec<u8>, String> {
+    if ascii {
+        if !text.is_ascii() || text.is_empty() {
+            return Err("Enter some ASCII text to find".to_string());
+        }
+        return Ok(text.as_bytes().to_vec());
+    }
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.is_ascii() {
+        return Err("Enter pairs of hex digits to find, like F0 90".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("{} isn't hex", text))
+}
+
+// Helper function to parse an address typed into the debugger, in hex with or
+// without 0x.
+fn parse_address(text: &str, chip8: &Chip8) -> Result<u16, String> {
+    let digits = text
+        .trim()
+        .trim_start_matches("0x")
+        .trim_start_matches("0X");
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&address| (address as usize) < chip8.get_memory_size())
+        .ok_or_else(|| format!("{} isn't an address", text.trim()))
+}
+
+// Helper function to keep the ROM's breakpoints in the config, if they're being saved.
+fn save_breakpoints(config: &mut Config, chip8: &Chip8, rom_name: &str) {
+    if !config.save_breakpoints {
+        return;
+    }
+    let breakpoints: Vec<String> = chip8
+        .get_breakpoints()
+        .iter()
+        .map(|breakpoint| breakpoint.to_string())
+        .collect();
+    if breakpoints.is_empty() {
+        config.breakpoints.remove(rom_name);
+    } else {
+        config.breakpoints.insert(rom_name.to_string(), breakpoints);
+    }
+    save_config(config);
+}
+
+// Helper function to get the memory dump file for a ROM. There's one per ROM, so
+// loading reads back the last dump of the ROM that's running.
+fn memory_dump_path(rom_name: &str) -> String {
+    let stem = Path::new(rom_name)
+        .file_stem()
+        .map_or("memory".into(), |s| s.to_string_lossy());
+    format!("{}/{}.bin", DUMP_DIR, stem)
+}
+
+// Helper function to format the machine state as text for bug reports.
+fn state_dump(chip8: &Chip8, rom_name: &str) -> String {
+    let mut dump = format!(
+        "ROM: {}\nVariant: {}\n",
+        rom_name,
+        chip8.get_variant().name()
+    );
+    for i in 0..16 {
+        let separator = if i % 8 == 7 { "\n" } else { "  " };
+        dump += &format!("V{:X}: {:02X}{}", i, chip8.get_v(i), separator);
+    }
+    dump += &format!(
+        "I: {:04X}  PC: {:04X}  SP: {:X}  DT: {:02X}  ST: {:02X}\n\n",
+        chip8.get_i(),
+        chip8.get_pc(),
+        chip8.get_sp(),
+        chip8.get_dt(),
+        chip8.get_st()
+    );
+    dump += &chip8.display_ascii();
+    dump
+}
+
+// Helper function to show a write into code as where it was made from, where it was
+// made to, and the bytes written.
+fn code_write_text(write: &chip8::CodeWrite) -> String {
+    let bytes: Vec<String> = write.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let from = write
+        .pc
+        .map_or("poke".to_string(), |pc| format!("{:03X}", pc));
+    format!("{:>4}: {:03X} = {}", from, write.addr, bytes.join(" "))
+}
+
+// Helper function to describe the emulator's setup for the about window. It's
+// meant to be pasted into bug reports, so it covers everything that changes how a
+// ROM runs.
+fn about_text(chip8: &Chip8) -> String {
+    let mut quirks = Vec::new();
+    if chip8.shift_using_vy {
+        quirks.push("shift uses Vy");
+    }
+    if chip8.increment_i_on_ld {
+        quirks.push("LD [I] increments I");
+    }
+    if !chip8.wrap_sprites_x {
+        quirks.push("sprites clip at the sides");
+    }
+    if !chip8.wrap_sprites_y {
+        quirks.push("sprites clip at the top and bottom");
+    }
+    if chip8.vf_reset {
+        quirks.push("OR, AND and XOR reset VF");
+    }
+    if chip8.jump_using_vx {
+        quirks.push("Bxnn jumps to xnn + Vx");
+    }
+    if chip8.wait_for_vblank {
+        quirks.push("DRW waits for the next frame");
+    }
+    if chip8.strict_opcodes {
+        quirks.push("unimplemented instructions fault");
+    }
+    if chip8.or_sprites {
+        quirks.push("sprites are drawn with OR");
+    }
+    if chip8.fault_on_odd_pc {
+        quirks.push("odd PCs fault");
+    }
+    let draw_limit = chip8
+        .max_draws_per_frame
+        .map(|max_draws| format!("at most {} sprites per frame", max_draws));
+    if let Some(draw_limit) = &draw_limit {
+        quirks.push(draw_limit);
+    }
+    if quirks.is_empty() {
+        quirks.push("none");
+    }
+    format!(
+        "Chipper v{}\nVariant: {}\nResolution: {}x{}\nMemory: {} bytes\n\
+         Clock: {} instructions/s ({} per frame)\nTimers: 60Hz\nQuirks: {}",
+        env!("CARGO_PKG_VERSION"),
+        chip8.get_variant().name(),
+        chip8.get_display_width(),
+        chip8.get_display_height(),
+        chip8.get_memory_size(),
+        chip8.instructions_per_second,
+        chip8.cycles_per_frame(),
+        quirks.join(", ")
+    )
+}
+
+// Helper function to describe a save state slot for the slot picker.
+fn slot_label(rom_hash: u64, slot: usize) -> String {
+    match states::saved_at(rom_hash, slot) {
+        Some(time) => format!(
+            "Slot {} ({})",
+            slot,
+            states::format_age(time.elapsed().unwrap_or_default())
+        ),
+        None => format!("Slot {} (empty)", slot),
+    }
+}
+
+// Helper function to describe which keys make up the keypad, a row at a time.
+fn keypad_help(layout: Layout) -> String {
+    let rows: Vec<String> = KEYPAD_ROWS
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&key| layout.keyboard_key(key).name())
+                .collect()
+        })
+        .collect();
+    format!("Keypad = {}", rows.join(" "))
+}
+
+// Helper function to show the keypad as the machine sees it, with the key that's
+// held lit up, and which keyboard key it is.
+fn keypad_state(ui: &mut egui::Ui, pressed: Option<u8>, layout: Layout) {
+    ui.colored_label(color::WHITE, "KEYPAD");
+    for row in KEYPAD_ROWS.iter() {
+        ui.horizontal(|ui| {
+            for &key in row {
+                let key_color = if pressed == Some(key) {
+                    color::GREEN
+                } else {
+                    color::GRAY
+                };
+                ui.add(
+                    egui::Label::new(format!("{:X}", key))
+                        .text_style(egui::TextStyle::Monospace)
+                        .text_color(key_color),
+                );
+            }
+        });
+    }
+    let held = pressed.map_or("Nothing held".to_string(), |key| {
+        format!("{:X} held ({})", key, layout.keyboard_key(key).name())
+    });
+    ui.colored_label(color::WHITE, held);
+}
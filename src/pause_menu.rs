@@ -0,0 +1,113 @@
+/// What can be chosen from the pause menu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Item {
+    Resume,
+    Reset,
+    LoadRom,
+    SaveState,
+    LoadState,
+    Settings,
+    Quit,
+}
+
+pub const ITEMS: [Item; 7] = [
+    Item::Resume,
+    Item::Reset,
+    Item::LoadRom,
+    Item::SaveState,
+    Item::LoadState,
+    Item::Settings,
+    Item::Quit,
+];
+
+impl Item {
+    pub fn label(self: &Self) -> &'static str {
+        match self {
+            Item::Resume => "Resume",
+            Item::Reset => "Reset",
+            Item::LoadRom => "Load ROM...",
+            Item::SaveState => "Save state",
+            Item::LoadState => "Load state",
+            Item::Settings => "Settings",
+            Item::Quit => "Quit",
+        }
+    }
+}
+
+/// The menu that Escape brings up over the paused display. It works with the arrow
+/// keys and Enter as well as the mouse. Load ROM lists the ROMs in its place, which
+/// Escape goes back from.
+#[derive(Debug, Default)]
+pub struct PauseMenu {
+    selected: usize,
+    choosing_rom: bool,
+}
+
+impl PauseMenu {
+    /// A menu for choosing a ROM from, starting at the given one.
+    pub fn rom_list(selected: usize) -> PauseMenu {
+        PauseMenu {
+            selected,
+            choosing_rom: true,
+        }
+    }
+
+    pub fn is_choosing_rom(self: &Self) -> bool {
+        self.choosing_rom
+    }
+
+    /// The index of the selected item, or of the selected ROM in the ROM list.
+    pub fn selected(self: &Self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_item(self: &Self) -> Item {
+        ITEMS[self.selected % ITEMS.len()]
+    }
+
+    // Moves the selection up or down a list of the given length, wrapping around at
+    // the ends.
+    fn move_selection(self: &mut Self, down: bool, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.selected = if down {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+
+    /// Moves the selection up or down whichever list is showing.
+    pub fn move_in_list(self: &mut Self, down: bool, rom_count: usize) {
+        let len = if self.choosing_rom {
+            rom_count
+        } else {
+            ITEMS.len()
+        };
+        self.move_selection(down, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut menu = PauseMenu::default();
+        assert_eq!(menu.selected_item(), Item::Resume);
+        menu.move_in_list(false, 3);
+        assert_eq!(menu.selected_item(), Item::Quit);
+        menu.move_in_list(true, 3);
+        menu.move_in_list(true, 3);
+        assert_eq!(menu.selected_item(), Item::Reset);
+
+        // The ROM list is as long as there are ROMs.
+        let mut menu = PauseMenu::rom_list(2);
+        menu.move_in_list(true, 3);
+        assert_eq!(menu.selected(), 0);
+        menu.move_in_list(false, 0);
+        assert_eq!(menu.selected(), 0);
+    }
+}
@@ -0,0 +1,163 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Recordings are saved here, relative to the working directory.
+pub const RECORDING_DIR: &str = "./recordings";
+
+/// Frames are captured at the size of the hi-res display. Lo-res pixels are doubled.
+const FRAME_WIDTH: usize = 128;
+const FRAME_HEIGHT: usize = 64;
+
+/// Browsers slow down GIF frames shorter than this many centiseconds, so shorter
+/// frames are merged into the next one.
+const MIN_FRAME_DELAY: u16 = 2;
+
+/// Records the display to an animated GIF. Frames are sent to a worker thread that
+/// encodes them and writes them out as it goes, so that recording doesn't hold up
+/// the emulator and memory use stays the same however long the recording is.
+pub struct Recording {
+    sender: Sender<Vec<u8>>,
+    worker: JoinHandle<Result<(), String>>,
+    path: PathBuf,
+}
+
+impl Recording {
+    /// Starts a new recording in RECORDING_DIR, named after the ROM. Each captured
+    /// frame is scaled up by scale. colors has a color for each pixel value, as
+    /// Config::display_colors() does.
+    pub fn start(rom_name: &str, scale: usize, colors: [[u8; 3]; 4]) -> io::Result<Recording> {
+        fs::create_dir_all(RECORDING_DIR)?;
+        let stem = Path::new(rom_name)
+            .file_stem()
+            .map_or("recording".into(), |s| s.to_string_lossy());
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = Path::new(RECORDING_DIR).join(format!("{}-{}.gif", stem, time));
+        let file = BufWriter::new(File::create(&path)?);
+
+        let (sender, receiver) = mpsc::channel();
+        let palette = colors.concat();
+        let worker = thread::spawn(move || encode(file, receiver, scale.max(1), &palette));
+
+        Ok(Recording {
+            sender,
+            worker,
+            path,
+        })
+    }
+
+    /// Captures a frame of the display. Should be called once per 60Hz frame.
+    pub fn capture(self: &Self, display: &[u8], width: usize, height: usize) {
+        let mut frame = Vec::with_capacity(FRAME_WIDTH * FRAME_HEIGHT);
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let pixel = display[(y * height / FRAME_HEIGHT) * width + x * width / FRAME_WIDTH];
+                frame.push(pixel & 3);
+            }
+        }
+        // If the worker has failed the error is picked up by finish().
+        let _ = self.sender.send(frame);
+    }
+
+    /// Stops recording, waits for the rest of the frames to be written and returns
+    /// the path of the finished GIF.
+    pub fn finish(self: Self) -> Result<PathBuf, String> {
+        drop(self.sender);
+        match self.worker.join() {
+            Ok(Ok(())) => Ok(self.path),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("the encoder thread panicked".to_string()),
+        }
+    }
+}
+
+/// Returns the delay in centiseconds for a frame that's shown for ticks 60Hz frames,
+/// starting start ticks into the recording. Working from the total time rather than
+/// rounding each frame keeps the recording in step with real time.
+fn frame_delay(start: u64, ticks: u64) -> u16 {
+    let centiseconds = |ticks: u64| (ticks * 100 + 30) / 60;
+    (centiseconds(start + ticks) - centiseconds(start)) as u16
+}
+
+// The worker thread. Runs of identical frames are written as a single frame.
+fn encode(
+    file: BufWriter<File>,
+    receiver: Receiver<Vec<u8>>,
+    scale: usize,
+    palette: &[u8],
+) -> Result<(), String> {
+    let width = (FRAME_WIDTH * scale) as u16;
+    let height = (FRAME_HEIGHT * scale) as u16;
+    let mut encoder = gif::Encoder::new(file, width, height, palette).map_err(|e| e.to_string())?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(|e| e.to_string())?;
+
+    let mut write = |frame: &[u8], delay: u16| {
+        let mut scaled = Vec::with_capacity(width as usize * height as usize);
+        for row in frame.chunks(FRAME_WIDTH) {
+            for _ in 0..scale {
+                for &pixel in row {
+                    scaled.resize(scaled.len() + scale, pixel);
+                }
+            }
+        }
+        let gif_frame = gif::Frame {
+            width,
+            height,
+            delay,
+            buffer: Cow::Owned(scaled),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&gif_frame).map_err(|e| e.to_string())
+    };
+
+    // The frame waiting to be written, and how many ticks it's been shown for.
+    let mut pending: Option<(Vec<u8>, u64)> = None;
+    let mut written_ticks = 0u64;
+
+    for frame in receiver {
+        pending = match pending {
+            Some((last, ticks)) if last == frame => Some((last, ticks + 1)),
+            Some((last, ticks)) => {
+                let delay = frame_delay(written_ticks, ticks);
+                if delay < MIN_FRAME_DELAY {
+                    // Too short to show, so the new frame takes over its time.
+                    Some((frame, ticks + 1))
+                } else {
+                    write(&last, delay)?;
+                    written_ticks += ticks;
+                    Some((frame, 1))
+                }
+            }
+            None => Some((frame, 1)),
+        };
+    }
+
+    if let Some((last, ticks)) = pending {
+        let delay = frame_delay(written_ticks, ticks).max(MIN_FRAME_DELAY);
+        write(&last, delay)?;
+    }
+    // The GIF trailer is written when the encoder is dropped.
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_delays_keep_up_with_real_time() {
+        // One second of single frames adds up to exactly 100 centiseconds.
+        let total: u64 = (0..60).map(|t| frame_delay(t, 1) as u64).sum();
+        assert_eq!(total, 100);
+        assert_eq!(frame_delay(0, 60), 100);
+        assert_eq!(frame_delay(0, 3), 5);
+    }
+}
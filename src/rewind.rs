@@ -0,0 +1,131 @@
+use chipper::chip8::Chip8;
+use std::collections::VecDeque;
+
+/// The rewind lengths that can be picked in the options, in seconds. 0 is off.
+pub const REWIND_SECONDS: [u32; 4] = [0, 10, 20, 30];
+
+/// The last few seconds of the machine, a snapshot a frame, for rewinding through.
+/// Once it's full the oldest snapshot is dropped for each new one, so it never uses
+/// more than the capacity's worth of memory.
+pub struct RewindBuffer {
+    snapshots: VecDeque<Chip8>,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Changes how many snapshots are kept, dropping the oldest ones if there are
+    /// now too many.
+    pub fn set_capacity(self: &mut Self, capacity: usize) {
+        self.capacity = capacity;
+        while self.snapshots.len() > capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn push(self: &mut Self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        // A ROM being loaded is as far back as rewinding goes.
+        let other_rom = self
+            .snapshots
+            .back()
+            .map_or(false, |last| last.get_rom_hash() != chip8.get_rom_hash());
+        if other_rom {
+            self.snapshots.clear();
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        let mut snapshot = chip8.clone();
+        // The access counts can be a lot bigger than the machine, and restoring a
+        // snapshot keeps the current ones anyway, as it does the code writes and the
+        // trace.
+        snapshot.set_access_tracking(false);
+        snapshot.set_code_write_tracking(false, false);
+        snapshot.set_tracing(false);
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Takes the newest snapshot, or None once it's gone back as far as it can.
+    pub fn pop(self: &mut Self) -> Option<Chip8> {
+        self.snapshots.pop_back()
+    }
+
+    pub fn clear(self: &mut Self) {
+        self.snapshots.clear();
+    }
+
+    pub fn len(self: &Self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: [u8; 4] = [
+        0x70, 0x01, // 0x200: ADD V0, 1
+        0x12, 0x00, // 0x202: JP 0x200
+    ];
+
+    fn counter() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_rom_bytes(&COUNTER).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn pops_newest_first() {
+        let mut chip8 = counter();
+        let mut buffer = RewindBuffer::new(3);
+        for _ in 0..5 {
+            chip8.run_cycles(2);
+            buffer.push(&chip8);
+        }
+        assert_eq!(buffer.len(), 3);
+        let v0: Vec<u8> = std::iter::from_fn(|| buffer.pop())
+            .map(|s| s.get_v(0))
+            .collect();
+        assert_eq!(v0, [5, 4, 3]);
+    }
+
+    #[test]
+    fn shrinking_drops_oldest() {
+        let mut chip8 = counter();
+        let mut buffer = RewindBuffer::new(4);
+        for _ in 0..4 {
+            chip8.run_cycles(2);
+            buffer.push(&chip8);
+        }
+        buffer.set_capacity(2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop().unwrap().get_v(0), 4);
+
+        buffer.set_capacity(0);
+        buffer.push(&chip8);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn loading_another_rom_clears_it() {
+        let mut chip8 = counter();
+        let mut buffer = RewindBuffer::new(4);
+        buffer.push(&chip8);
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        buffer.push(&chip8);
+        assert_eq!(buffer.len(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+};
+
+/// The ROM directory that's used unless another one has been picked in the settings.
+pub const DEFAULT_ROM_DIR: &str = "./roms";
+
+/// Gets all valid Chip8 ROM files in the given directory. The dictionary maps a
+/// filename to a file path, and is sorted by filename so the ROM list is always in
+/// the same order. Called again whenever the ROM directory is rescanned.
+pub fn get_roms(dir: &str) -> io::Result<BTreeMap<String, String>> {
+    let mut files: BTreeMap<String, String> = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let data = entry.metadata()?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if data.is_file() {
+            if let Some(ex) = path.extension() {
+                if ex == "ch8" {
+                    //println!("Found rom: {}", file_name);
+                    files.insert(file_name, path.display().to_string());
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the ROM filenames in the order they're listed: favorites first, then
+/// the rest, each in filename order.
+pub fn list_order<'a>(
+    roms: &'a BTreeMap<String, String>,
+    favorites: &BTreeSet<String>,
+) -> Vec<&'a String> {
+    let (mut names, others): (Vec<&String>, Vec<&String>) =
+        roms.keys().partition(|name| favorites.contains(*name));
+    names.extend(others);
+    names
+}
+
+/// Returns the ROM after (or before, if not forward) the current one in list
+/// order, wrapping around at the ends. If the current ROM isn't in the list the
+/// first (or last) one is returned. Returns None if there are no ROMs.
+pub fn adjacent_rom(
+    roms: &BTreeMap<String, String>,
+    favorites: &BTreeSet<String>,
+    current: &str,
+    forward: bool,
+) -> Option<String> {
+    let names = list_order(roms, favorites);
+    let count = names.len();
+    if count == 0 {
+        return None;
+    }
+
+    let index = match names.iter().position(|name| name.as_str() == current) {
+        Some(i) if forward => (i + 1) % count,
+        Some(i) => (i + count - 1) % count,
+        None if forward => 0,
+        None => count - 1,
+    };
+    Some(names[index].clone())
+}
+
+/// Returns the power-on ROM from the settings if it's in the ROM folder. If it's not
+/// set, or it's gone, the built-in boot ROM is run instead.
+pub fn power_on_rom<'a>(roms: &BTreeMap<String, String>, power_on: &'a str) -> Option<&'a str> {
+    Some(power_on).filter(|name| !name.is_empty() && roms.contains_key(*name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chipper-roms-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn only_finds_ch8_files() {
+        let dir = temp_dir("filter");
+        fs::write(dir.join("PONG.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(dir.join("readme.txt"), "not a rom").unwrap();
+        fs::create_dir(dir.join("nested.ch8")).unwrap();
+
+        let roms = get_roms(&dir.display().to_string()).unwrap();
+        assert_eq!(roms.len(), 1);
+        assert!(roms["PONG.ch8"].ends_with("PONG.ch8"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rescan_picks_up_new_roms() {
+        let dir = temp_dir("rescan");
+        let dir_name = dir.display().to_string();
+        assert!(get_roms(&dir_name).unwrap().is_empty());
+
+        fs::write(dir.join("BRIX.ch8"), [0x12, 0x00]).unwrap();
+        let roms = get_roms(&dir_name).unwrap();
+        assert!(roms.contains_key("BRIX.ch8"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn roms_are_sorted_by_filename() {
+        let dir = temp_dir("sorted");
+        for name in &["TETRIS.ch8", "BRIX.ch8", "PONG.ch8", "INVADERS.ch8"] {
+            fs::write(dir.join(name), [0x12, 0x00]).unwrap();
+        }
+
+        let roms = get_roms(&dir.display().to_string()).unwrap();
+        let names: Vec<&str> = roms.keys().map(|name| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["BRIX.ch8", "INVADERS.ch8", "PONG.ch8", "TETRIS.ch8"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn adjacent_rom_wraps_around() {
+        let mut roms = BTreeMap::new();
+        for name in &["PONG.ch8", "BRIX.ch8", "TETRIS.ch8"] {
+            roms.insert(name.to_string(), format!("roms/{}", name));
+        }
+        let favorites = BTreeSet::new();
+        let next = |current: &str| adjacent_rom(&roms, &favorites, current, true).unwrap();
+        let previous = |current: &str| adjacent_rom(&roms, &favorites, current, false).unwrap();
+
+        assert_eq!(next("BRIX.ch8"), "PONG.ch8");
+        assert_eq!(next("TETRIS.ch8"), "BRIX.ch8");
+        assert_eq!(previous("BRIX.ch8"), "TETRIS.ch8");
+        assert_eq!(previous("PONG.ch8"), "BRIX.ch8");
+
+        assert_eq!(next("ChipperBoot.ch8"), "BRIX.ch8");
+        assert_eq!(previous("ChipperBoot.ch8"), "TETRIS.ch8");
+        assert!(adjacent_rom(&BTreeMap::new(), &favorites, "BRIX.ch8", true).is_none());
+    }
+
+    #[test]
+    fn favorites_are_listed_first() {
+        let mut roms = BTreeMap::new();
+        for name in &["PONG.ch8", "BRIX.ch8", "TETRIS.ch8", "INVADERS.ch8"] {
+            roms.insert(name.to_string(), format!("roms/{}", name));
+        }
+        let mut favorites = BTreeSet::new();
+        favorites.insert("TETRIS.ch8".to_string());
+        favorites.insert("INVADERS.ch8".to_string());
+        // A favorite that's no longer in the folder is left out.
+        favorites.insert("MAZE.ch8".to_string());
+
+        let names = list_order(&roms, &favorites);
+        assert_eq!(
+            names,
+            ["INVADERS.ch8", "TETRIS.ch8", "BRIX.ch8", "PONG.ch8"]
+        );
+        let next = adjacent_rom(&roms, &favorites, "TETRIS.ch8", true);
+        assert_eq!(next.as_deref(), Some("BRIX.ch8"));
+
+        favorites.remove("TETRIS.ch8");
+        let names = list_order(&roms, &favorites);
+        assert_eq!(
+            names,
+            ["INVADERS.ch8", "BRIX.ch8", "PONG.ch8", "TETRIS.ch8"]
+        );
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let dir = std::env::temp_dir().join("chipper-roms-does-not-exist");
+        assert!(get_roms(&dir.display().to_string()).is_err());
+    }
+
+    #[test]
+    fn power_on_rom_has_to_be_there() {
+        let mut roms = BTreeMap::new();
+        roms.insert("PONG.ch8".to_string(), "roms/PONG.ch8".to_string());
+        assert_eq!(power_on_rom(&roms, "PONG.ch8"), Some("PONG.ch8"));
+        assert_eq!(power_on_rom(&roms, "TETRIS.ch8"), None);
+        assert_eq!(power_on_rom(&roms, ""), None);
+        assert_eq!(power_on_rom(&BTreeMap::new(), "PONG.ch8"), None);
+    }
+}
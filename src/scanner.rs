@@ -0,0 +1,109 @@
+use chipper::chip8::Chip8;
+
+/// The memory that's searched, which is where programs keep their variables.
+const SCAN_START: u16 = 0x200;
+const SCAN_END: u16 = 0x1000;
+
+/// How each candidate's value has to have changed since the last search to be kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Equals(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+}
+
+impl Filter {
+    pub fn matches(self: &Self, old: u8, new: u8) -> bool {
+        match *self {
+            Filter::Equals(value) => new == value,
+            Filter::Increased => new > old,
+            Filter::Decreased => new < old,
+            Filter::Unchanged => new == old,
+        }
+    }
+}
+
+/// Searches memory for where a program keeps something, like a score or the number
+/// of lives, by narrowing down the addresses a search at a time. It starts with all
+/// of them, and each search keeps only the ones that match.
+pub struct Scanner {
+    candidates: Vec<u16>,
+    // The values at the last search, from SCAN_START.
+    snapshot: Vec<u8>,
+}
+
+impl Scanner {
+    pub fn new(chip8: &Chip8) -> Scanner {
+        Scanner {
+            candidates: (SCAN_START..SCAN_END).collect(),
+            snapshot: snapshot(chip8),
+        }
+    }
+
+    /// Keeps the candidates whose values match the filter.
+    pub fn search(self: &mut Self, chip8: &Chip8, filter: Filter) {
+        self.search_snapshot(snapshot(chip8), filter);
+    }
+
+    fn search_snapshot(self: &mut Self, snapshot: Vec<u8>, filter: Filter) {
+        let old = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let index = (address - SCAN_START) as usize;
+            filter.matches(old[index], snapshot[index])
+        });
+        self.snapshot = snapshot;
+    }
+
+    pub fn candidates(self: &Self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+fn snapshot(chip8: &Chip8) -> Vec<u8> {
+    (SCAN_START..SCAN_END).map(|a| chip8.peek(a)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner(snapshot: Vec<u8>) -> Scanner {
+        Scanner {
+            candidates: (SCAN_START..SCAN_START + snapshot.len() as u16).collect(),
+            snapshot,
+        }
+    }
+
+    #[test]
+    fn filters_compare_with_last_search() {
+        assert!(Filter::Equals(3).matches(9, 3));
+        assert!(!Filter::Equals(3).matches(3, 4));
+        assert!(Filter::Increased.matches(3, 4));
+        assert!(!Filter::Increased.matches(4, 4));
+        assert!(Filter::Decreased.matches(4, 3));
+        assert!(!Filter::Decreased.matches(3, 3));
+        assert!(Filter::Unchanged.matches(3, 3));
+        assert!(!Filter::Unchanged.matches(3, 2));
+    }
+
+    #[test]
+    fn searches_narrow_down_candidates() {
+        // A score at 0x201 going up, with noise either side.
+        let mut scanner = scanner(vec![5, 0, 7, 1]);
+        scanner.search_snapshot(vec![6, 1, 7, 0], Filter::Increased);
+        assert_eq!(scanner.candidates(), [0x200, 0x201]);
+        scanner.search_snapshot(vec![6, 1, 7, 0], Filter::Unchanged);
+        assert_eq!(scanner.candidates(), [0x200, 0x201]);
+        scanner.search_snapshot(vec![4, 2, 7, 0], Filter::Equals(2));
+        assert_eq!(scanner.candidates(), [0x201]);
+    }
+
+    #[test]
+    fn starts_with_the_program_area() {
+        let chip8 = Chip8::with_seed(1);
+        let scanner = Scanner::new(&chip8);
+        assert_eq!(scanner.candidates().len(), 0xE00);
+        assert_eq!(scanner.candidates()[0], 0x200);
+    }
+}
@@ -0,0 +1,155 @@
+//! Golden image tests. A ROM is run for a number of frames and what's on the display
+//! is checked against a blessed copy, saved as the text from Chip8::display_ascii()
+//! so that a change to it can be reviewed in a diff. Setting CHIPPER_BLESS=1 saves
+//! the display as the new blessed copy instead, after a change that's meant to alter
+//! what's drawn.
+
+use crate::chip8::{Chip8, QuirkProfile};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Snapshots are kept here, relative to the working directory, which is the
+/// package's directory when run by cargo test.
+pub const SNAPSHOT_DIR: &str = "tests/snapshots";
+
+/// The environment variable that says to save snapshots rather than check them.
+pub const BLESS_VAR: &str = "CHIPPER_BLESS";
+
+/// How a ROM is run for a snapshot. The variant is detected from the ROM, unless
+/// the quirks are given, in which case it's the profile's.
+#[derive(Clone, Copy, Debug)]
+pub struct Run<'a> {
+    pub rom: &'a [u8],
+    pub frames: u32,
+    pub seed: u64,
+    pub quirks: Option<QuirkProfile>,
+}
+
+impl<'a> Run<'a> {
+    /// Runs the ROM for the given number of frames, with seed 0 and the ROM's own
+    /// quirks.
+    pub fn new(rom: &'a [u8], frames: u32) -> Run<'a> {
+        Run {
+            rom,
+            frames,
+            seed: 0,
+            quirks: None,
+        }
+    }
+
+    /// Loads the ROM on a new machine and runs it, returning the machine.
+    pub fn run(self: &Self) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(self.seed);
+        chip8.load_rom_bytes(self.rom).unwrap();
+        if let Some(quirks) = self.quirks {
+            chip8.set_variant(quirks.variant());
+            quirks.apply(&mut chip8);
+        }
+        for _ in 0..self.frames {
+            chip8.run_frame();
+        }
+        chip8
+    }
+}
+
+/// Checks the display against the snapshot with the given name, or saves it if
+/// CHIPPER_BLESS is set. Panics with the two screens side by side if they differ.
+pub fn assert_snapshot(name: &str, chip8: &Chip8) {
+    let bless = std::env::var_os(BLESS_VAR)
+        .map(|value| value != "0")
+        .unwrap_or(false);
+    if let Err(e) = check(Path::new(SNAPSHOT_DIR), name, &chip8.display_ascii(), bless) {
+        panic!("{}", e);
+    }
+}
+
+/// The file a snapshot is kept in.
+pub fn snapshot_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.txt", name))
+}
+
+// Compares the display with the snapshot in dir, or saves it as the snapshot.
+fn check(dir: &Path, name: &str, actual: &str, bless: bool) -> Result<(), String> {
+    let path = snapshot_path(dir, name);
+    if bless {
+        return fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, actual))
+            .map_err(|e| format!("Couldn't save {}: {}", path.display(), e));
+    }
+    let expected = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "Couldn't read {}: {}. Run with {}=1 to save it.",
+            path.display(),
+            e,
+            BLESS_VAR
+        )
+    })?;
+    // Git may have checked it out with CRLF line endings.
+    let expected = expected.replace("\r\n", "\n");
+    if expected == actual {
+        return Ok(());
+    }
+    Err(format!(
+        "The display doesn't match {}. Run with {}=1 if the change is intended.\n{}",
+        path.display(),
+        BLESS_VAR,
+        side_by_side(&expected, actual)
+    ))
+}
+
+/// Puts two displays from display_ascii() side by side, marking the rows that
+/// differ with >, and says how many pixels are different.
+pub fn side_by_side(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let width = expected.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut pixels = 0;
+    let mut out = format!("  {:width$}  {}\n", "expected", "actual", width = width);
+    for row in 0..expected.len().max(actual.len()) {
+        let left = expected.get(row).copied().unwrap_or("");
+        let right = actual.get(row).copied().unwrap_or("");
+        let mut lefts = left.chars();
+        let mut rights = right.chars();
+        for _ in 0..left.len().max(right.len()) {
+            if lefts.next() != rights.next() {
+                pixels += 1;
+            }
+        }
+        let marker = if left == right { ' ' } else { '>' };
+        out += &format!("{} {:width$}  {}\n", marker, left, right, width = width);
+    }
+    out + &format!("{} pixels differ\n", pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_the_differences_side_by_side() {
+        let diff = side_by_side("#..\n.#.\n", "#..\n##.\n..#\n");
+        assert_eq!(
+            diff,
+            "  expected  actual\n\
+             \x20 #..  #..\n\
+             > .#.  ##.\n\
+             >      ..#\n\
+             4 pixels differ\n"
+        );
+    }
+
+    #[test]
+    fn blesses_then_checks() {
+        let dir = std::env::temp_dir().join(format!("chipper-snapshots-{}", std::process::id()));
+        assert!(check(&dir, "screen", "#.\n", false)
+            .unwrap_err()
+            .contains("CHIPPER_BLESS=1"));
+
+        check(&dir, "screen", "#.\n", true).unwrap();
+        assert!(check(&dir, "screen", "#.\n", false).is_ok());
+        let error = check(&dir, "screen", ".#\n", false).unwrap_err();
+        assert!(error.ends_with("> #.  .#\n2 pixels differ\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
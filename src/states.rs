@@ -0,0 +1,166 @@
+use chipper::chip8::Chip8;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Save states are kept here, relative to the working directory, in a directory per
+/// ROM named after its hash. That way the states follow the ROM even if it's renamed.
+pub const STATE_DIR: &str = "./states";
+
+/// The flag registers are kept here between sessions, as they're shared by every ROM.
+pub const FLAGS_FILE: &str = "./states/flags.bin";
+
+/// The number of save state slots each ROM has.
+pub const SLOT_COUNT: usize = 10;
+
+fn rom_dir(rom_hash: u64) -> PathBuf {
+    PathBuf::from(STATE_DIR).join(format!("{:016x}", rom_hash))
+}
+
+pub fn slot_path(rom_hash: u64, slot: usize) -> PathBuf {
+    rom_dir(rom_hash).join(format!("slot{}.sav", slot))
+}
+
+/// The autosave is kept alongside the slots, but isn't one of them.
+pub fn autosave_path(rom_hash: u64) -> PathBuf {
+    rom_dir(rom_hash).join("autosave.sav")
+}
+
+fn write_state(chip8: &Chip8, path: PathBuf) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, chip8.save_state())
+}
+
+/// Saves the machine to a slot, replacing whatever was in it.
+pub fn save(chip8: &Chip8, slot: usize) -> io::Result<()> {
+    write_state(chip8, slot_path(chip8.get_rom_hash(), slot))
+}
+
+/// Loads the machine from a slot. The machine is left alone if that fails.
+pub fn load(chip8: &mut Chip8, slot: usize) -> io::Result<()> {
+    let state = fs::read(slot_path(chip8.get_rom_hash(), slot))?;
+    chip8.load_state(&state)
+}
+
+/// Saves the machine to the autosave for its ROM, which is done on exit.
+pub fn autosave(chip8: &Chip8) -> io::Result<()> {
+    write_state(chip8, autosave_path(chip8.get_rom_hash()))
+}
+
+/// Reads the autosave for the ROM that's loaded, if there's one that can be loaded.
+/// One that can't, say because it's from an older version, is ignored.
+pub fn read_autosave(chip8: &Chip8) -> Option<Vec<u8>> {
+    let path = autosave_path(chip8.get_rom_hash());
+    let state = match fs::read(&path) {
+        Ok(state) => state,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            println!("Ignoring autosave {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match chip8.check_state(&state) {
+        Ok(()) => Some(state),
+        Err(e) => {
+            println!("Ignoring autosave {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Saves the flag registers, which is done on exit.
+pub fn save_flags(chip8: &Chip8) -> io::Result<()> {
+    save_flags_to(chip8, Path::new(FLAGS_FILE))
+}
+
+/// Loads the flag registers saved last session. They're left alone if there's nothing
+/// to load, or if it's not 16 bytes.
+pub fn load_flags(chip8: &mut Chip8) {
+    load_flags_from(chip8, Path::new(FLAGS_FILE))
+}
+
+pub fn save_flags_to(chip8: &Chip8, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, chip8.get_flags())
+}
+
+pub fn load_flags_from(chip8: &mut Chip8, path: &Path) {
+    match fs::read(path) {
+        Ok(bytes) if bytes.len() == 16 => {
+            let mut flags = [0; 16];
+            flags.copy_from_slice(&bytes);
+            chip8.set_flags(flags);
+        }
+        Ok(_) => println!("Ignoring flags {}: not 16 bytes", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => println!("Ignoring flags {}: {}", path.display(), e),
+    }
+}
+
+/// Returns when the slot was last saved to, or None if it's empty.
+pub fn saved_at(rom_hash: u64, slot: usize) -> Option<SystemTime> {
+    fs::metadata(slot_path(rom_hash, slot))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// Describes how long ago something happened, roughly, like "5 min ago".
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        "just now".to_string()
+    } else if secs < 60 * 60 {
+        format!("{} min ago", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{} h ago", secs / (60 * 60))
+    } else {
+        format!("{} days ago", secs / (24 * 60 * 60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_are_rounded_down() {
+        assert_eq!(format_age(Duration::from_secs(59)), "just now");
+        assert_eq!(format_age(Duration::from_secs(150)), "2 min ago");
+        assert_eq!(format_age(Duration::from_secs(2 * 60 * 60 + 59)), "2 h ago");
+        assert_eq!(
+            format_age(Duration::from_secs(3 * 24 * 60 * 60)),
+            "3 days ago"
+        );
+    }
+
+    #[test]
+    fn flags_are_kept_unless_broken() {
+        let path = std::env::temp_dir().join("chipper-flags.bin");
+        let mut chip8 = Chip8::new();
+        let flags = [7; 16];
+        chip8.set_flags(flags);
+        save_flags_to(&chip8, &path).unwrap();
+
+        let mut loaded = Chip8::new();
+        load_flags_from(&mut loaded, &path);
+        assert_eq!(loaded.get_flags(), flags);
+
+        fs::write(&path, [1, 2, 3]).unwrap();
+        load_flags_from(&mut loaded, &path);
+        assert_eq!(loaded.get_flags(), flags);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn slots_are_kept_per_rom() {
+        assert_ne!(slot_path(1, 0), slot_path(2, 0));
+        assert_ne!(slot_path(1, 0), slot_path(1, 1));
+        assert!(slot_path(0xab, 3).ends_with("00000000000000ab/slot3.sav"));
+        assert_eq!(autosave_path(0xab).parent(), slot_path(0xab, 3).parent());
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The frame times of the last few seconds, for graphing and for percentiles, which
+/// show up stutter that an average hides.
+pub struct FrameTimes {
+    // In milliseconds, oldest first.
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl FrameTimes {
+    pub fn new(capacity: usize) -> FrameTimes {
+        FrameTimes {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(self: &mut Self, frame_time: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(frame_time.as_secs_f32() * 1000f32);
+    }
+
+    /// The frame times in milliseconds, oldest first.
+    pub fn samples(self: &Self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn max(self: &Self) -> f32 {
+        self.samples().fold(0f32, f32::max)
+    }
+
+    /// Returns the frame time in milliseconds that the given percentage of frames
+    /// took no longer than, using the nearest sample. None if there aren't any yet.
+    pub fn percentile(self: &Self, percent: f32) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f32> = self.samples().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = (percent.max(0f32).min(100f32) / 100f32 * sorted.len() as f32).ceil();
+        let index = (rank as usize).max(1) - 1;
+        Some(sorted[index])
+    }
+}
+
+/// Measures how often something happens each second, from a running count of it,
+/// such as the frames the emulator has run.
+pub struct RateMeter {
+    count: u64,
+    since: Instant,
+    rate: u64,
+}
+
+impl Default for RateMeter {
+    fn default() -> RateMeter {
+        RateMeter {
+            count: 0,
+            since: Instant::now(),
+            rate: 0,
+        }
+    }
+}
+
+impl RateMeter {
+    /// Updates the rate from the latest count, once a second has passed.
+    pub fn update(self: &mut Self, count: u64) {
+        let elapsed = self.since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let counted = count.saturating_sub(self.count);
+            self.rate = (counted as f64 / elapsed.as_secs_f64()).round() as u64;
+            self.count = count;
+            self.since = Instant::now();
+        }
+    }
+
+    pub fn rate(self: &Self) -> u64 {
+        self.rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_times(millis: &[u64]) -> FrameTimes {
+        let mut times = FrameTimes::new(millis.len());
+        for &ms in millis {
+            times.push(Duration::from_millis(ms));
+        }
+        times
+    }
+
+    #[test]
+    fn percentiles_use_nearest_sample() {
+        let times = frame_times(&[16, 17, 15, 16, 16, 16, 16, 16, 16, 50]);
+        assert_eq!(times.percentile(50f32), Some(16f32));
+        assert_eq!(times.percentile(90f32), Some(17f32));
+        assert_eq!(times.percentile(99f32), Some(50f32));
+        assert_eq!(times.percentile(0f32), Some(15f32));
+        assert_eq!(times.percentile(100f32), Some(50f32));
+        assert_eq!(FrameTimes::new(4).percentile(50f32), None);
+    }
+
+    #[test]
+    fn oldest_samples_are_dropped() {
+        let mut times = frame_times(&[50, 16, 16]);
+        times.push(Duration::from_millis(17));
+        let samples: Vec<f32> = times.samples().collect();
+        assert_eq!(samples, [16f32, 16f32, 17f32]);
+        assert_eq!(times.max(), 17f32);
+        assert_eq!(times.percentile(99f32), Some(17f32));
+    }
+}
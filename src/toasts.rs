@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a toast is shown for.
+const TOAST_TIME: Duration = Duration::from_secs(3);
+
+/// Errors are left up for longer, so there's time to read them. They can be
+/// dismissed before then.
+const ERROR_TOAST_TIME: Duration = Duration::from_secs(10);
+
+/// The most toasts shown at once. Pushing another drops the oldest.
+const MAX_TOASTS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A short message about something that's happened, such as a ROM being reloaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toast {
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Toast {
+    pub fn info(message: impl Into<String>) -> Toast {
+        Toast {
+            message: message.into(),
+            severity: Severity::Info,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Toast {
+        Toast {
+            message: message.into(),
+            severity: Severity::Warning,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Toast {
+        Toast {
+            message: message.into(),
+            severity: Severity::Error,
+        }
+    }
+
+    fn time(self: &Self) -> Duration {
+        match self.severity {
+            Severity::Error => ERROR_TOAST_TIME,
+            _ => TOAST_TIME,
+        }
+    }
+}
+
+/// The toasts that are on screen, oldest first. Each one goes away by itself after
+/// a few seconds, or when it's dismissed.
+#[derive(Default)]
+pub struct ToastQueue {
+    toasts: VecDeque<(Toast, Instant)>,
+}
+
+impl ToastQueue {
+    pub fn push(self: &mut Self, toast: Toast) {
+        self.push_at(toast, Instant::now());
+    }
+
+    fn push_at(self: &mut Self, toast: Toast, now: Instant) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back((toast, now));
+    }
+
+    /// Drops the toasts that have been shown for long enough. Should be called once
+    /// a frame before the toasts are drawn.
+    pub fn expire(self: &mut Self) {
+        self.expire_at(Instant::now());
+    }
+
+    fn expire_at(self: &mut Self, now: Instant) {
+        self.toasts
+            .retain(|(toast, time)| now.duration_since(*time) < toast.time());
+    }
+
+    /// Removes the toast at index, counting from the oldest.
+    pub fn dismiss(self: &mut Self, index: usize) {
+        self.toasts.remove(index);
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.toasts.is_empty()
+    }
+
+    pub fn iter(self: &Self) -> impl Iterator<Item = &Toast> {
+        self.toasts.iter().map(|(toast, _)| toast)
+    }
+
+    /// Takes all of the toasts out, oldest first, for passing them on to another
+    /// queue.
+    pub fn drain(self: &mut Self) -> impl Iterator<Item = Toast> + '_ {
+        self.toasts.drain(..).map(|(toast, _)| toast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(queue: &ToastQueue) -> Vec<&str> {
+        queue.iter().map(|toast| toast.message.as_str()).collect()
+    }
+
+    #[test]
+    fn toasts_expire_in_order() {
+        let start = Instant::now();
+        let mut queue = ToastQueue::default();
+        queue.push_at(Toast::info("first"), start);
+        queue.push_at(Toast::error("second"), start + Duration::from_secs(1));
+
+        queue.push_at(Toast::warning("third"), start + Duration::from_secs(1));
+
+        queue.expire_at(start + Duration::from_millis(2999));
+        assert_eq!(messages(&queue), ["first", "second", "third"]);
+
+        queue.expire_at(start + TOAST_TIME);
+        assert_eq!(messages(&queue), ["second", "third"]);
+
+        // Errors stay up for longer.
+        queue.expire_at(start + Duration::from_secs(10));
+        assert_eq!(messages(&queue), ["second"]);
+
+        queue.expire_at(start + Duration::from_secs(11));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn toasts_can_be_dismissed() {
+        let mut queue = ToastQueue::default();
+        queue.push(Toast::error("first"));
+        queue.push(Toast::error("second"));
+        queue.dismiss(0);
+        assert_eq!(messages(&queue), ["second"]);
+    }
+
+    #[test]
+    fn oldest_toast_is_dropped_when_full() {
+        let start = Instant::now();
+        let mut queue = ToastQueue::default();
+        for message in &["1", "2", "3", "4", "5"] {
+            queue.push_at(Toast::warning(*message), start);
+        }
+        assert_eq!(messages(&queue), ["2", "3", "4", "5"]);
+    }
+}
@@ -0,0 +1,297 @@
+use chipper::chip8::{self, TraceEntry};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Traces are saved here, relative to the working directory.
+pub const TRACE_DIR: &str = "./traces";
+
+/// A trace stops being written after this many instructions, so that leaving it on
+/// can't fill the disk. It's about 250MB of text, or a minute at 1000 instructions a
+/// frame.
+pub const TRACE_MAX_LINES: u64 = 5_000_000;
+
+/// How a trace is written out. Text is for reading, and CSV for spreadsheets and
+/// for diffing two runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Csv,
+}
+
+impl TraceFormat {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            TraceFormat::Text => "Text",
+            TraceFormat::Csv => "CSV",
+        }
+    }
+
+    fn extension(self: &Self) -> &'static str {
+        match self {
+            TraceFormat::Text => "txt",
+            TraceFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Writes every instruction that's executed to a file, one per line, up to a limit.
+/// Like recording a GIF, the writing is done on a worker thread so that it doesn't
+/// hold up the emulator.
+pub struct Trace {
+    sender: Sender<Vec<TraceEntry>>,
+    worker: JoinHandle<io::Result<()>>,
+    path: PathBuf,
+    full: Arc<AtomicBool>,
+}
+
+impl Trace {
+    /// Starts a new trace in TRACE_DIR, named after the ROM, of up to
+    /// TRACE_MAX_LINES instructions.
+    pub fn start(rom_name: &str, format: TraceFormat) -> io::Result<Trace> {
+        fs::create_dir_all(TRACE_DIR)?;
+        let stem = Path::new(rom_name)
+            .file_stem()
+            .map_or("trace".into(), |s| s.to_string_lossy());
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = Path::new(TRACE_DIR).join(format!("{}-{}.{}", stem, time, format.extension()));
+        Trace::start_at(path, format, TRACE_MAX_LINES)
+    }
+
+    // Starts a trace in the given file.
+    fn start_at(path: PathBuf, format: TraceFormat, max_lines: u64) -> io::Result<Trace> {
+        let file = BufWriter::new(File::create(&path)?);
+        let full = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let worker_full = full.clone();
+        let worker =
+            thread::spawn(move || write_trace(file, format, max_lines, &worker_full, receiver));
+
+        Ok(Trace {
+            sender,
+            worker,
+            path,
+            full,
+        })
+    }
+
+    /// Whether the trace has got to its limit, after which nothing more is written.
+    pub fn is_full(self: &Self) -> bool {
+        self.full.load(Ordering::Relaxed)
+    }
+
+    /// Adds the instructions from Chip8::take_trace() to the end of the trace.
+    pub fn write(self: &Self, entries: Vec<TraceEntry>) {
+        if !entries.is_empty() {
+            // If the worker has failed the error is picked up by finish().
+            let _ = self.sender.send(entries);
+        }
+    }
+
+    /// Stops tracing, waits for the rest to be written and returns the path of the
+    /// trace.
+    pub fn finish(self: Self) -> Result<PathBuf, String> {
+        drop(self.sender);
+        match self.worker.join() {
+            Ok(Ok(())) => Ok(self.path),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("the trace thread panicked".to_string()),
+        }
+    }
+}
+
+// The worker thread. Writes the entries as they come in, numbering them from 0,
+// until the trace is finished or max_lines have been written.
+fn write_trace<W: Write>(
+    mut out: W,
+    format: TraceFormat,
+    max_lines: u64,
+    full: &AtomicBool,
+    receiver: Receiver<Vec<TraceEntry>>,
+) -> io::Result<()> {
+    write_header(&mut out, format)?;
+    let mut step = 0;
+    for entries in receiver {
+        for entry in &entries {
+            if step == max_lines {
+                full.store(true, Ordering::Relaxed);
+                break;
+            }
+            write_entry(&mut out, format, step, entry)?;
+            step += 1;
+        }
+    }
+    out.flush()
+}
+
+/// Writes what comes before the entries, which is the column names for CSV.
+pub fn write_header<W: Write>(out: &mut W, format: TraceFormat) -> io::Result<()> {
+    if format == TraceFormat::Csv {
+        write!(out, "step,pc,opcode,mnemonic,changed")?;
+        for x in 0..16 {
+            write!(out, ",v{:x}", x)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes the line for an entry. The step is the entry's number in the trace. The
+/// registers the instruction changed are listed after it.
+pub fn write_entry<W: Write>(
+    out: &mut W,
+    format: TraceFormat,
+    step: u64,
+    entry: &TraceEntry,
+) -> io::Result<()> {
+    let mnemonic = chip8::disassemble(entry.opcode);
+    let changed = (0..16).filter(|x| entry.changed & 1 << x != 0);
+    match format {
+        TraceFormat::Text => {
+            write!(out, "{:8} {:04X}: {:04X}  ", step, entry.pc, entry.opcode)?;
+            if entry.changed == 0 {
+                return writeln!(out, "{}", mnemonic);
+            }
+            write!(out, "{:20}", mnemonic)?;
+            for x in changed {
+                write!(out, " V{:X}={:02X}", x, entry.v[x])?;
+            }
+            writeln!(out)
+        }
+        TraceFormat::Csv => {
+            // The mnemonics have commas in, so they're quoted.
+            let changed: Vec<String> = changed.map(|x| format!("v{:x}", x)).collect();
+            write!(
+                out,
+                "{},{:04X},{:04X},\"{}\",{}",
+                step,
+                entry.pc,
+                entry.opcode,
+                mnemonic,
+                changed.join(" ")
+            )?;
+            for v in &entry.v {
+                write!(out, ",{:02X}", v)?;
+            }
+            writeln!(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chipper::chip8::Chip8;
+
+    // Counts up in V1, then clears it.
+    const PROGRAM: [u8; 8] = [
+        0x60, 0x05, // 0x200: LD V0, 5
+        0x71, 0x02, // 0x202: ADD V1, 2
+        0x31, 0x08, // 0x204: SE V1, 8
+        0x12, 0x02, // 0x206: JP 0x202
+    ];
+
+    // Runs the program for the given number of instructions with tracing on.
+    fn run_traced(cycles: usize) -> Vec<TraceEntry> {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&PROGRAM).unwrap();
+        chip8.set_tracing(true);
+        chip8.run_cycles(cycles);
+        chip8.take_trace()
+    }
+
+    // Traces a few instructions in the given format.
+    fn trace_lines(format: TraceFormat) -> Vec<String> {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(run_traced(3)).unwrap();
+        drop(sender);
+        let mut out = Vec::new();
+        write_trace(&mut out, format, 100, &AtomicBool::new(false), receiver).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn csv_has_a_column_for_each_register() {
+        let lines = trace_lines(TraceFormat::Csv);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "step,pc,opcode,mnemonic,changed,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf"
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                "1,0202,7102,\"{}\",v1,05,02{}",
+                chip8::disassemble(0x7102),
+                ",00".repeat(14)
+            )
+        );
+        assert!(lines[3].starts_with(&format!("2,0204,3108,\"{}\",,", chip8::disassemble(0x3108))));
+    }
+
+    #[test]
+    fn text_is_a_line_an_instruction() {
+        let lines = trace_lines(TraceFormat::Text);
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "       0 0200: 6005  {:20} V0=05",
+                    chip8::disassemble(0x6005)
+                ),
+                format!(
+                    "       1 0202: 7102  {:20} V1=02",
+                    chip8::disassemble(0x7102)
+                ),
+                format!("       2 0204: 3108  {}", chip8::disassemble(0x3108)),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_a_file_up_to_the_limit() {
+        let path = std::env::temp_dir().join(format!("chipper-trace-{}.txt", std::process::id()));
+        let trace = Trace::start_at(path.clone(), TraceFormat::Text, 10).unwrap();
+        trace.write(run_traced(6));
+        trace.write(run_traced(6));
+        assert_eq!(trace.finish().unwrap(), path);
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert!(lines[4].starts_with("       4 0202: 7102"));
+        assert!(lines[4].ends_with(" V1=04"));
+        assert!(lines[9].starts_with("       9 0206: 1202"));
+    }
+
+    #[test]
+    fn says_when_its_full() {
+        let path = std::env::temp_dir().join(format!("chipper-full-{}.csv", std::process::id()));
+        let trace = Trace::start_at(path.clone(), TraceFormat::Csv, 4).unwrap();
+        trace.write(run_traced(4));
+        let full = trace.full.clone();
+        trace.finish().unwrap();
+        assert!(!full.load(Ordering::Relaxed));
+
+        let trace = Trace::start_at(path.clone(), TraceFormat::Csv, 4).unwrap();
+        trace.write(run_traced(5));
+        let full = trace.full.clone();
+        trace.finish().unwrap();
+        assert!(full.load(Ordering::Relaxed));
+        // The header and the first 4.
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::chip8::Chip8;
+use wasm_bindgen::prelude::*;
+
+/// A thin JS facing wrapper around the Chip8 core. See examples/wasm for a canvas
+/// front-end that uses it.
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// JS numbers can't hold a full u64, so the seed is limited to 32 bits.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Emulator {
+        Emulator {
+            chip8: Chip8::with_seed(seed as u64),
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.chip8
+            .load_rom_bytes(rom)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn run_frame(&mut self) {
+        self.chip8.run_frame();
+    }
+
+    /// Returns a copy of the display, one byte per pixel in row order.
+    pub fn display(&self) -> Vec<u8> {
+        self.chip8.get_display_data().to_vec()
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.chip8.get_display_width()
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.chip8.get_display_height()
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.chip8.set_key_pressed(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.chip8.set_key_released(key);
+    }
+
+    /// True while the tone should be playing.
+    pub fn sound_active(&self) -> bool {
+        self.chip8.get_sound_active()
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches a ROM file for changes by polling its modification time. A change is only
+/// reported once the file has stayed the same for a whole poll, so that a ROM that's
+/// in the middle of being written by an assembler isn't picked up half finished.
+/// A file that's missing or empty is assumed to be mid-write as well.
+pub struct RomWatcher {
+    path: String,
+    loaded: Option<SystemTime>,
+    pending: Option<(SystemTime, u64)>,
+    last_poll: Instant,
+}
+
+impl RomWatcher {
+    pub fn new(path: &str) -> RomWatcher {
+        RomWatcher {
+            path: path.to_string(),
+            loaded: fs::metadata(path).and_then(|m| m.modified()).ok(),
+            pending: None,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(self: &Self) -> &str {
+        &self.path
+    }
+
+    /// Returns true if the file has changed and should be reloaded. The file is only
+    /// checked once a second, so this is fine to call every frame.
+    pub fn poll(self: &mut Self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        self.check()
+    }
+
+    fn check(self: &mut Self) -> bool {
+        let (modified, len) = match fs::metadata(&self.path) {
+            Ok(m) => match m.modified() {
+                Ok(modified) => (modified, m.len()),
+                Err(_) => return false,
+            },
+            Err(_) => return false,
+        };
+
+        if self.pending.is_none() && self.loaded == Some(modified) {
+            return false;
+        }
+
+        if self.pending == Some((modified, len)) && len > 0 {
+            self.pending = None;
+            self.loaded = Some(modified);
+            return true;
+        }
+
+        self.pending = Some((modified, len));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_rom(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chipper-watcher-{}.ch8", name));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        path
+    }
+
+    // Makes sure the next write gets a different modification time.
+    fn touch(path: &PathBuf, data: &[u8]) {
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn reports_change_once_settled() {
+        let path = temp_rom("settled");
+        let mut watcher = RomWatcher::new(&path.display().to_string());
+        assert!(!watcher.check());
+
+        touch(&path, &[0x12, 0x02]);
+        assert!(!watcher.check());
+        assert!(watcher.check());
+        assert!(!watcher.check());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn waits_out_missing_and_truncated_files() {
+        let path = temp_rom("truncated");
+        let mut watcher = RomWatcher::new(&path.display().to_string());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.check());
+
+        touch(&path, &[]);
+        assert!(!watcher.check());
+        assert!(!watcher.check());
+
+        touch(&path, &[0x12, 0x04]);
+        assert!(!watcher.check());
+        assert!(watcher.check());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
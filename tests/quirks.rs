@@ -0,0 +1,119 @@
+//! Checks the quirks that differ between the machines, the way the community quirks
+//! test ROM does, by running a probe under each quirk profile. The ROM itself isn't
+//! bundled with Chipper, so the probe below tests the same things: VF reset, jumps,
+//! sprite clipping and waiting for vblank.
+
+use chipper::chip8::{Chip8, FaultKind, QuirkProfile};
+use chipper::snapshot::Run;
+
+const PROBE: [u8; 0x42] = [
+    // VF reset: VF is set and then OR'd over. VA = VF afterwards.
+    0x6F, 0x05, // 0x200: LD VF, 5
+    0x60, 0x01, // 0x202: LD V0, 1
+    0x61, 0x02, // 0x204: LD V1, 2
+    0x80, 0x11, // 0x206: OR V0, V1
+    0x8A, 0xF0, // 0x208: LD VA, VF
+    // Jump: B220 lands on 0x220 using V0 and on 0x224 using V2. VB = 1 or 2.
+    0x60, 0x00, // 0x20A: LD V0, 0
+    0x62, 0x04, // 0x20C: LD V2, 4
+    0xB2, 0x20, // 0x20E: JP V0, 0x220
+    0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 0x210: sprite, then blank
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 0x218: blank sprite
+    0x6B, 0x01, // 0x220: LD VB, 1
+    0x12, 0x26, // 0x222: JP 0x226
+    0x6B, 0x02, // 0x224: LD VB, 2
+    // Clipping: an 8 pixel row drawn at x = 60 either wraps around to x = 0..3
+    // or is cut off at the edge.
+    0xA2, 0x10, // 0x226: LD I, 0x210
+    0x6D, 0x3C, // 0x228: LD VD, 60
+    0x6E, 0x1E, // 0x22A: LD VE, 30
+    0xDD, 0xE1, // 0x22C: DRW VD, VE, 1
+    // Vblank: counts DRWs in VC until a delay of 3 frames runs out. Waiting for
+    // vblank makes that one a frame, so 3.
+    0x6C, 0x00, // 0x22E: LD VC, 0
+    0x64, 0x03, // 0x230: LD V4, 3
+    0xF4, 0x15, // 0x232: LD DT, V4
+    0xA2, 0x18, // 0x234: LD I, 0x218
+    0xD5, 0x51, // 0x236: DRW V5, V5, 1
+    0x7C, 0x01, // 0x238: ADD VC, 1
+    0xF4, 0x07, // 0x23A: LD V4, DT
+    0x34, 0x00, // 0x23C: SE V4, 0
+    0x12, 0x36, // 0x23E: JP 0x236
+    0x12, 0x40, // 0x240: JP 0x240
+];
+
+fn run_probe(profile: QuirkProfile) -> Chip8 {
+    let chip8 = Run {
+        quirks: Some(profile),
+        ..Run::new(&PROBE, 20)
+    }
+    .run();
+    assert_eq!(
+        chip8.get_fault().map(|fault| fault.kind),
+        Some(FaultKind::ProgramEnded),
+        "{}",
+        profile.name()
+    );
+    chip8
+}
+
+#[test]
+fn vf_is_reset_by_logic_ops_only_on_the_cosmac_vip() {
+    for &profile in &QuirkProfile::ALL {
+        let expected = match profile {
+            QuirkProfile::CosmacVip => 0,
+            _ => 5,
+        };
+        assert_eq!(
+            run_probe(profile).get_v(0xA),
+            expected,
+            "{}",
+            profile.name()
+        );
+    }
+}
+
+#[test]
+fn jumps_use_vx_only_on_superchip() {
+    for &profile in &QuirkProfile::ALL {
+        let expected = match profile {
+            QuirkProfile::SuperChip => 2,
+            _ => 1,
+        };
+        assert_eq!(
+            run_probe(profile).get_v(0xB),
+            expected,
+            "{}",
+            profile.name()
+        );
+    }
+}
+
+#[test]
+fn sprites_wrap_only_on_xochip() {
+    for &profile in &QuirkProfile::ALL {
+        let chip8 = run_probe(profile);
+        let wrapped = (0..4).all(|x| chip8.get_pixel(x, 30) > 0);
+        let clipped = (0..4).all(|x| chip8.get_pixel(x, 30) == 0);
+        assert!(
+            (60..64).all(|x| chip8.get_pixel(x, 30) > 0),
+            "{}",
+            profile.name()
+        );
+        match profile {
+            QuirkProfile::XoChip => assert!(wrapped, "{}", profile.name()),
+            _ => assert!(clipped, "{}", profile.name()),
+        }
+    }
+}
+
+#[test]
+fn drawing_waits_for_vblank_only_on_the_cosmac_vip() {
+    for &profile in &QuirkProfile::ALL {
+        let draws = run_probe(profile).get_v(0xC);
+        match profile {
+            QuirkProfile::CosmacVip => assert_eq!(draws, 3, "{}", profile.name()),
+            _ => assert!(draws > 3, "{} drew {} times", profile.name(), draws),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Golden image tests of the bundled ROMs. See chipper::snapshot for how to update
+//! the snapshots after a change that's meant to alter what's drawn.
+
+use chipper::chip8::{FaultKind, QuirkProfile, BOOT_ROM, TEST_ROM};
+use chipper::snapshot::{assert_snapshot, Run};
+
+#[test]
+fn test_rom_passes() {
+    // Frames rather than cycles, as one of the tests waits on the delay timer.
+    let chip8 = Run::new(TEST_ROM, 60).run();
+    assert_eq!(chip8.get_fault().unwrap().kind, FaultKind::ProgramEnded);
+    // The numbers of all of the tests with PASS underneath.
+    assert_snapshot("ChipperTest", &chip8);
+}
+
+#[test]
+fn boot_rom() {
+    assert_snapshot("ChipperBoot", &Run::new(BOOT_ROM, 60).run());
+}
+
+#[test]
+fn bc_test_passes_with_superchip_quirks() {
+    let chip8 = Run {
+        quirks: Some(QuirkProfile::SuperChip),
+        ..Run::new(include_bytes!("../roms/BC_test.ch8"), 200)
+    }
+    .run();
+    assert_snapshot("BC_test", &chip8);
+}
+
+#[test]
+fn maze_is_the_same_for_the_same_seed() {
+    let chip8 = Run {
+        seed: 8,
+        ..Run::new(include_bytes!("../roms/Maze [David Winter, 199x].ch8"), 200)
+    }
+    .run();
+    assert_snapshot("Maze", &chip8);
+}